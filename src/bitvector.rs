@@ -2,15 +2,15 @@ mod ascii;
 mod format;
 mod integers;
 mod iter;
+mod ops;
 mod tests;
 
 use std::alloc;
 use std::convert::TryInto;
+use std::ops::Range;
 
 use indiscriminant::*;
 
-pub use crate::bitvector::ascii::*;
-pub use crate::bitvector::format::*;
 pub use crate::bitvector::integers::*;
 pub use crate::bitvector::iter::*;
 
@@ -172,7 +172,7 @@ unsafe fn clone_be_bytes_to_usizes(bytes: &[u8], ptr: *mut usize) {
         if byte_index <= USIZE_BYTES {
             let mut temp = [0u8; USIZE_BYTES];
             temp[(USIZE_BYTES - byte_index)..USIZE_BYTES].clone_from_slice(&bytes[0..byte_index]);
-            let w = usize::from_be_bytes(temp) as usize;
+            let w = usize::from_be_bytes(temp);
             *ptr.add(ptr_index) = w;
             break;
         } else {
@@ -180,7 +180,7 @@ unsafe fn clone_be_bytes_to_usizes(bytes: &[u8], ptr: *mut usize) {
                 (&bytes[byte_index - USIZE_BYTES..byte_index])
                     .try_into()
                     .unwrap(),
-            ) as usize;
+            );
             *ptr.add(ptr_index) = w;
         }
         ptr_index += 1;
@@ -258,6 +258,8 @@ impl BitVector {
         }
     }
 
+    // The payload holds the bit itself rather than pointing anywhere
+    #[allow(clippy::manual_dangling_ptr)]
     pub fn new_one_bit() -> Self {
         Self {
             size: 1,
@@ -555,6 +557,16 @@ impl BitVector {
         }
     }
 
+    /// Returns the bits in the given range as a new bit-vector, where bit
+    /// indices count up from the least significant bit as with `get_bit`
+    pub fn get_range(&self, range: Range<usize>) -> Self {
+        let mut bv = Self::new(range.len(), self.is_four_state());
+        for (i, index) in range.enumerate() {
+            bv.set_bit(i, self.get_bit(index));
+        }
+        bv
+    }
+
     // Various status functions
 
     pub fn is_pointer(&self) -> bool {
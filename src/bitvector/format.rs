@@ -2,6 +2,7 @@ use std::fmt;
 
 use crate::bitvector::{BitVector, BitVectorRadix};
 
+#[allow(clippy::manual_is_multiple_of)]
 fn div_ceil(lhs: usize, rhs: usize) -> usize {
     if lhs % rhs == 0 {
         lhs / rhs
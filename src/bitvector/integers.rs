@@ -57,7 +57,7 @@ impl UnsignedInteger for usize {
         u
     }
     fn to_usize(&self) -> usize {
-        *self as usize
+        *self
     }
     fn get_width(&self) -> u32 {
         usize::BITS
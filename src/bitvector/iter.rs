@@ -26,7 +26,7 @@ impl<'a> Iterator for BitVectorIter<'a> {
 }
 
 impl BitVector {
-    pub fn iter(&self) -> BitVectorIter {
+    pub fn iter(&self) -> BitVectorIter<'_> {
         BitVectorIter {
             bv: self,
             bits: self.get_bit_width(),
@@ -0,0 +1,134 @@
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use crate::bitvector::{BitVector, Logic};
+
+// Bitwise operators follow the Verilog four-state truth tables, where a Z
+// input is treated as X. A known 0 wins for and, a known 1 wins for or, and
+// any unknown input makes xor and not unknown.
+
+impl BitAnd for Logic {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Self::Zero, _) | (_, Self::Zero) => Self::Zero,
+            (Self::One, Self::One) => Self::One,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl BitOr for Logic {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        match (self, rhs) {
+            (Self::One, _) | (_, Self::One) => Self::One,
+            (Self::Zero, Self::Zero) => Self::Zero,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl BitXor for Logic {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self {
+        if self.is_two_state() && rhs.is_two_state() {
+            Self::from(self != rhs)
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+impl Not for Logic {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        match self {
+            Self::Zero => Self::One,
+            Self::One => Self::Zero,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl BitVector {
+    // Applies an operator to each pair of bits, zero extending the narrower
+    // bit-vector to the width of the wider one
+    fn zip_bits(&self, other: &Self, op: impl Fn(Logic, Logic) -> Logic) -> Self {
+        let width = self.get_bit_width().max(other.get_bit_width());
+        let mut bv = Self::new(width, self.is_four_state() || other.is_four_state());
+        for i in 0..width {
+            bv.set_bit(i, op(self.get_bit(i), other.get_bit(i)));
+        }
+        bv
+    }
+
+    /// Returns a single bit that is 1 if the bit-vectors are equal after zero
+    /// extension, or X if either has an X or Z bit, as with Verilog `==`
+    pub fn logical_eq(&self, other: &Self) -> Logic {
+        let width = self.get_bit_width().max(other.get_bit_width());
+        let bits = (0..width).map(|i| (self.get_bit(i), other.get_bit(i)));
+        if bits
+            .clone()
+            .any(|(a, b)| !a.is_two_state() || !b.is_two_state())
+        {
+            Logic::Unknown
+        } else {
+            Logic::from(bits.clone().all(|(a, b)| a == b))
+        }
+    }
+
+    /// Joins bit-vectors into one with the first part in the most significant
+    /// bits, as with a Verilog concatenation
+    pub fn concat(parts: &[Self]) -> Self {
+        let width = parts.iter().map(|bv| bv.get_bit_width()).sum();
+        let mut bv = Self::new(width, parts.iter().any(|bv| bv.is_four_state()));
+        let mut index = 0;
+        for part in parts.iter().rev() {
+            for bit in part {
+                bv.set_bit(index, bit);
+                index += 1;
+            }
+        }
+        bv
+    }
+}
+
+impl BitAnd for &BitVector {
+    type Output = BitVector;
+
+    fn bitand(self, rhs: Self) -> BitVector {
+        self.zip_bits(rhs, Logic::bitand)
+    }
+}
+
+impl BitOr for &BitVector {
+    type Output = BitVector;
+
+    fn bitor(self, rhs: Self) -> BitVector {
+        self.zip_bits(rhs, Logic::bitor)
+    }
+}
+
+impl BitXor for &BitVector {
+    type Output = BitVector;
+
+    fn bitxor(self, rhs: Self) -> BitVector {
+        self.zip_bits(rhs, Logic::bitxor)
+    }
+}
+
+impl Not for &BitVector {
+    type Output = BitVector;
+
+    fn not(self) -> BitVector {
+        let mut bv = BitVector::new(self.get_bit_width(), self.is_four_state());
+        for (i, bit) in self.iter().enumerate() {
+            bv.set_bit(i, !bit);
+        }
+        bv
+    }
+}
@@ -20,9 +20,9 @@ fn test_bitvector() {
         }
 
         let bvc = bv.clone();
-        for i in 0..bv.get_bit_width() {
-            assert_eq!(bv.get_bit(i), v[i]);
-            assert_eq!(bvc.get_bit(i), v[i]);
+        for (i, bit) in v.iter().enumerate() {
+            assert_eq!(bv.get_bit(i), *bit);
+            assert_eq!(bvc.get_bit(i), *bit);
         }
     }
 
@@ -47,16 +47,16 @@ fn test_bitvector() {
         let mut prng: u32 = 0xdeadbeef;
         let mut v = Vec::new();
         for i in 0..bv.get_bit_width() {
-            let bit = Logic::from((prng & 1 == 1, prng & 2 == 1));
+            let bit = Logic::from((prng & 1 == 1, prng & 2 == 2));
             bv.set_bit(i, bit);
             v.push(bit);
             prng ^= (prng << 13) ^ (prng >> 17) ^ (prng << 5);
         }
 
         let bvc = bv.clone();
-        for i in 0..bv.get_bit_width() {
-            assert_eq!(bv.get_bit(i), v[i]);
-            assert_eq!(bvc.get_bit(i), v[i]);
+        for (i, bit) in v.iter().enumerate() {
+            assert_eq!(bv.get_bit(i), *bit);
+            assert_eq!(bvc.get_bit(i), *bit);
         }
     }
 
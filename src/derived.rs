@@ -0,0 +1,538 @@
+// Derived signals are virtual vector signals whose value is an expression over
+// other signals, such as `valid & ready`, `addr[31:12]` or `{a, b}`. A derived
+// signal added to a waveform is materialised the first time it is accessed by
+// replaying the merged changes of its sources once, keeping only the changes
+// of the result. The materialised changes are shared by every query until a
+// signal of the waveform changes, and can also be stored for good.
+//
+// Expressions follow Verilog where they overlap with it. Operators in order of
+// increasing precedence are `|`, `^`, `&`, `==` and `!=`, then `~`, with bit
+// and part selects such as `a[3]` or `a[7:4]` applied before any operator.
+// Signals are referenced by their full hierarchy path, where escaped names are
+// written with their backslashes, such as `top.\\a\.b`, and constants are
+// either plain decimal numbers, which are 32 bits wide, or sized literals such
+// as `8'hff` or `4'b10x1`.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use crate::bitvector::{BitVector, Logic};
+use crate::errors::*;
+use crate::query::WaveformQuery;
+use crate::vector::WaveformSignalVector;
+use crate::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WaveformExpression {
+    /// The value of the vector signal with the given id
+    Signal(usize),
+    Constant(BitVector),
+    Not(Box<Self>),
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    Xor(Box<Self>, Box<Self>),
+    /// A single bit that is 1 if both sides are equal, or X if either side
+    /// has an X or Z bit
+    Equal(Box<Self>, Box<Self>),
+    NotEqual(Box<Self>, Box<Self>),
+    /// The bits in the range, counting up from the least significant bit
+    Slice(Box<Self>, Range<usize>),
+    /// The parts joined with the first part in the most significant bits
+    Concat(Vec<Self>),
+}
+
+impl WaveformExpression {
+    /// Parses an expression, looking up the id of each signal path with the
+    /// given function
+    pub fn parse(
+        expression: &str,
+        resolve: impl Fn(&str) -> Option<usize>,
+    ) -> WaveformResult<Self> {
+        let mut parser = WaveformExpressionParser {
+            text: expression,
+            offset: 0,
+            resolve,
+        };
+        let result = parser.parse_or()?;
+        parser.skip_whitespace();
+        if parser.offset < expression.len() {
+            return Err(parser.error());
+        }
+        Ok(result)
+    }
+
+    /// Returns the sorted ids of every signal referenced by the expression
+    pub fn get_ids(&self) -> Vec<usize> {
+        let mut ids = Vec::new();
+        self.push_ids(&mut ids);
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    fn push_ids(&self, ids: &mut Vec<usize>) {
+        match self {
+            Self::Signal(id) => ids.push(*id),
+            Self::Constant(_) => {}
+            Self::Not(a) | Self::Slice(a, _) => a.push_ids(ids),
+            Self::And(a, b)
+            | Self::Or(a, b)
+            | Self::Xor(a, b)
+            | Self::Equal(a, b)
+            | Self::NotEqual(a, b) => {
+                a.push_ids(ids);
+                b.push_ids(ids);
+            }
+            Self::Concat(parts) => parts.iter().for_each(|part| part.push_ids(ids)),
+        }
+    }
+
+    /// Returns the width of the result, given a function returning the width
+    /// of each signal
+    pub fn get_width(
+        &self,
+        width: &impl Fn(usize) -> WaveformResult<usize>,
+    ) -> WaveformResult<usize> {
+        Ok(match self {
+            Self::Signal(id) => width(*id)?,
+            Self::Constant(bv) => bv.get_bit_width(),
+            Self::Not(a) => a.get_width(width)?,
+            Self::And(a, b) | Self::Or(a, b) | Self::Xor(a, b) => {
+                a.get_width(width)?.max(b.get_width(width)?)
+            }
+            Self::Equal(a, b) | Self::NotEqual(a, b) => {
+                a.get_width(width)?;
+                b.get_width(width)?;
+                1
+            }
+            Self::Slice(a, range) => {
+                a.get_width(width)?;
+                range.len()
+            }
+            Self::Concat(parts) => parts
+                .iter()
+                .map(|part| part.get_width(width))
+                .sum::<WaveformResult<usize>>()?,
+        })
+    }
+
+    /// Evaluates the expression, given a function returning the value of each
+    /// signal. Selected bits beyond the width of a value are 0, as with
+    /// `BitVector::get_bit`
+    pub fn evaluate(&self, value: &impl Fn(usize) -> BitVector) -> BitVector {
+        match self {
+            Self::Signal(id) => value(*id),
+            Self::Constant(bv) => bv.clone(),
+            Self::Not(a) => !&a.evaluate(value),
+            Self::And(a, b) => &a.evaluate(value) & &b.evaluate(value),
+            Self::Or(a, b) => &a.evaluate(value) | &b.evaluate(value),
+            Self::Xor(a, b) => &a.evaluate(value) ^ &b.evaluate(value),
+            Self::Equal(a, b) => BitVector::from(a.evaluate(value).logical_eq(&b.evaluate(value))),
+            Self::NotEqual(a, b) => {
+                BitVector::from(!a.evaluate(value).logical_eq(&b.evaluate(value)))
+            }
+            Self::Slice(a, range) => a.evaluate(value).get_range(range.clone()),
+            Self::Concat(parts) => BitVector::concat(
+                &parts
+                    .iter()
+                    .map(|part| part.evaluate(value))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+}
+
+struct WaveformExpressionParser<'a, F> {
+    text: &'a str,
+    offset: usize,
+    resolve: F,
+}
+
+impl<'a, F: Fn(&str) -> Option<usize>> WaveformExpressionParser<'a, F> {
+    fn rest(&self) -> &str {
+        &self.text[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    // Skips whitespace then consumes the token if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.offset += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> WaveformResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    // Consumes the longest run of characters matching the predicate
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let (text, start) = (self.text, self.offset);
+        let rest = self.rest();
+        self.offset += rest.find(|c| !predicate(c)).unwrap_or(rest.len());
+        &text[start..self.offset]
+    }
+
+    // Consumes a hierarchy path, where a backslash escapes the next character
+    fn take_path(&mut self) -> &'a str {
+        let (text, start) = (self.text, self.offset);
+        let mut chars = self.rest().char_indices();
+        let mut end = self.rest().len();
+        while let Some((offset, c)) = chars.next() {
+            if c == '\\' {
+                if chars.next().is_none() {
+                    break;
+                }
+            } else if !c.is_ascii_alphanumeric() && !"_$.".contains(c) {
+                end = offset;
+                break;
+            }
+        }
+        self.offset += end;
+        &text[start..self.offset]
+    }
+
+    // Returns an error for the token at the current offset
+    fn error(&self) -> WaveformError {
+        WaveformError::InvalidExpression {
+            offset: self.offset,
+            token: self
+                .rest()
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_string(),
+        }
+    }
+
+    fn parse_or(&mut self) -> WaveformResult<WaveformExpression> {
+        let mut result = self.parse_xor()?;
+        while self.eat("|") {
+            result = WaveformExpression::Or(Box::new(result), Box::new(self.parse_xor()?));
+        }
+        Ok(result)
+    }
+
+    fn parse_xor(&mut self) -> WaveformResult<WaveformExpression> {
+        let mut result = self.parse_and()?;
+        while self.eat("^") {
+            result = WaveformExpression::Xor(Box::new(result), Box::new(self.parse_and()?));
+        }
+        Ok(result)
+    }
+
+    fn parse_and(&mut self) -> WaveformResult<WaveformExpression> {
+        let mut result = self.parse_equality()?;
+        while self.eat("&") {
+            result = WaveformExpression::And(Box::new(result), Box::new(self.parse_equality()?));
+        }
+        Ok(result)
+    }
+
+    fn parse_equality(&mut self) -> WaveformResult<WaveformExpression> {
+        let mut result = self.parse_unary()?;
+        loop {
+            if self.eat("==") {
+                result = WaveformExpression::Equal(Box::new(result), Box::new(self.parse_unary()?));
+            } else if self.eat("!=") {
+                result =
+                    WaveformExpression::NotEqual(Box::new(result), Box::new(self.parse_unary()?));
+            } else {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> WaveformResult<WaveformExpression> {
+        if self.eat("~") {
+            return Ok(WaveformExpression::Not(Box::new(self.parse_unary()?)));
+        }
+        let mut result = self.parse_primary()?;
+        while self.eat("[") {
+            let start = self.offset;
+            let msb = self.parse_index()?;
+            let lsb = if self.eat(":") {
+                self.parse_index()?
+            } else {
+                msb
+            };
+            if msb < lsb {
+                self.offset = start;
+                return Err(self.error());
+            }
+            self.expect("]")?;
+            result = WaveformExpression::Slice(Box::new(result), lsb..msb + 1);
+        }
+        Ok(result)
+    }
+
+    fn parse_index(&mut self) -> WaveformResult<usize> {
+        self.skip_whitespace();
+        let start = self.offset;
+        let index = self.take_while(|c| c.is_ascii_digit()).parse().ok();
+        index.ok_or_else(|| {
+            self.offset = start;
+            self.error()
+        })
+    }
+
+    fn parse_primary(&mut self) -> WaveformResult<WaveformExpression> {
+        if self.eat("(") {
+            let result = self.parse_or()?;
+            self.expect(")")?;
+            return Ok(result);
+        }
+        if self.eat("{") {
+            let mut parts = vec![self.parse_or()?];
+            while self.eat(",") {
+                parts.push(self.parse_or()?);
+            }
+            self.expect("}")?;
+            return Ok(WaveformExpression::Concat(parts));
+        }
+        let start = self.offset;
+        match self.rest().chars().next() {
+            Some(c) if c.is_ascii_digit() => self.parse_constant(),
+            Some(c) if c.is_ascii_alphabetic() || "_$\\".contains(c) => {
+                let path = self.take_path();
+                match (self.resolve)(path) {
+                    Some(id) => Ok(WaveformExpression::Signal(id)),
+                    None => {
+                        self.offset = start;
+                        Err(self.error())
+                    }
+                }
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn parse_constant(&mut self) -> WaveformResult<WaveformExpression> {
+        let start = self.offset;
+        let error = |parser: &mut Self| {
+            parser.offset = start;
+            parser.error()
+        };
+        let size = self
+            .take_while(|c| c.is_ascii_digit() || c == '_')
+            .replace('_', "");
+        if !self.rest().starts_with('\'') {
+            // Unsized constants are 32 bits, as in Verilog
+            let Ok(value) = size.parse::<u32>() else {
+                return Err(error(self));
+            };
+            return Ok(WaveformExpression::Constant(BitVector::from(value)));
+        }
+        self.offset += 1;
+        let width = match size.parse::<usize>() {
+            Ok(width) if width > 0 => width,
+            _ => return Err(error(self)),
+        };
+        let radix = self.rest().chars().next().map(|c| c.to_ascii_lowercase());
+        self.offset += radix.map_or(0, char::len_utf8);
+        let digits = self
+            .take_while(|c| c.is_ascii_hexdigit() || "xXzZ_".contains(c))
+            .replace('_', "");
+        if digits.is_empty() {
+            return Err(error(self));
+        }
+        // Bits from the least significant up
+        let bits = match radix {
+            Some('d') => {
+                let Ok(value) = digits.parse::<u128>() else {
+                    return Err(error(self));
+                };
+                (0..128)
+                    .map(|i| Logic::from((value >> i) & 1 == 1))
+                    .collect()
+            }
+            Some(radix @ ('b' | 'o' | 'h')) => {
+                let (digit_bits, base) = match radix {
+                    'b' => (1, 2),
+                    'o' => (3, 8),
+                    _ => (4, 16),
+                };
+                let mut bits = Vec::new();
+                for c in digits.chars().rev() {
+                    let bit = match c.to_ascii_lowercase() {
+                        'x' => Some(Logic::Unknown),
+                        'z' => Some(Logic::HighImpedance),
+                        _ => None,
+                    };
+                    let value = match c.to_digit(base) {
+                        Some(value) => value,
+                        None if bit.is_some() => 0,
+                        None => return Err(error(self)),
+                    };
+                    for i in 0..digit_bits {
+                        bits.push(bit.unwrap_or(Logic::from((value >> i) & 1 == 1)));
+                    }
+                }
+                bits
+            }
+            _ => return Err(error(self)),
+        };
+        let mut bv = BitVector::new(width, bits.iter().any(|bit| !bit.is_two_state()));
+        for (i, bit) in bits.into_iter().take(width).enumerate() {
+            bv.set_bit(i, bit);
+        }
+        Ok(WaveformExpression::Constant(bv))
+    }
+}
+
+// Returns a value with every bit unknown, for signals without a value yet
+fn new_unknown(width: usize) -> BitVector {
+    let mut bv = BitVector::new(width, true);
+    for i in 0..width {
+        bv.set_bit(i, Logic::Unknown);
+    }
+    bv
+}
+
+// A derived signal along with its materialised changes, which are None if its
+// sources are no longer in the waveform
+pub(crate) struct WaveformDerivedSignal {
+    expression: WaveformExpression,
+    signal: OnceLock<Option<WaveformSignalVector>>,
+}
+
+impl WaveformDerivedSignal {
+    pub(crate) fn new(expression: WaveformExpression) -> Self {
+        Self {
+            expression,
+            signal: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn get_signal(&self, waveform: &Waveform) -> Option<&WaveformSignalVector> {
+        self.signal
+            .get_or_init(|| waveform.materialize(&self.expression).ok())
+            .as_ref()
+    }
+}
+
+impl Waveform {
+    /// Parses an expression, looking up signals by their full hierarchy path
+    pub fn parse_expression(&self, expression: &str) -> WaveformResult<WaveformExpression> {
+        WaveformExpression::parse(expression, |path| self.hierarchy.find_id(path))
+    }
+
+    /// Returns the width of the result of an expression, or an error if it
+    /// references an id that is not a vector signal
+    pub fn get_expression_width(&self, expression: &WaveformExpression) -> WaveformResult<usize> {
+        expression.get_width(&|id| {
+            if let Some(signal) = self.vector_signals.get(&id) {
+                Ok(signal.get_width())
+            } else if self.real_signals.contains_key(&id) {
+                Err(WaveformError::MismatchedSignalType { id })
+            } else {
+                Err(WaveformError::InvalidId { id })
+            }
+        })
+    }
+
+    /// Adds a derived signal with the given id, which is then returned by
+    /// `get_signal` and every query like a stored vector signal. The
+    /// expression can only reference stored vector signals. Derived signals
+    /// are not saved or written out, so they have to be added again after
+    /// loading a waveform
+    pub fn add_derived(&mut self, id: usize, expression: WaveformExpression) -> WaveformResult<()> {
+        if self.contains_id(id) {
+            return Err(WaveformError::DuplicateId { id });
+        }
+        self.get_expression_width(&expression)?;
+        self.derived_signals
+            .insert(id, WaveformDerivedSignal::new(expression));
+        Ok(())
+    }
+
+    pub fn get_derived(&self, id: usize) -> Option<&WaveformExpression> {
+        self.derived_signals
+            .get(&id)
+            .map(|derived| &derived.expression)
+    }
+
+    pub fn remove_derived(&mut self, id: usize) -> Option<WaveformExpression> {
+        self.derived_signals
+            .remove(&id)
+            .map(|derived| derived.expression)
+    }
+
+    // Drops the materialised changes of every derived signal, which has to be
+    // done whenever a stored signal or the timestamp indices change
+    pub(crate) fn invalidate_derived(&mut self) {
+        for derived in self.derived_signals.values_mut() {
+            derived.signal.take();
+        }
+    }
+
+    /// Evaluates an expression with the values in effect at the given
+    /// timestamp index, where signals without a value yet are all X
+    pub fn evaluate(&self, expression: &WaveformExpression, timestamp_index: usize) -> BitVector {
+        expression.evaluate(&|id| match self.search_value(
+            id,
+            timestamp_index,
+            WaveformSearchMode::Before,
+        ) {
+            Some(WaveformValueResult::Vector(bv, _)) => bv,
+            _ => new_unknown(self.vector_signals.get(&id).map_or(1, |s| s.get_width())),
+        })
+    }
+
+    /// Evaluates an expression at every change of its sources, returning a
+    /// vector signal that only holds the changes of the result
+    pub fn materialize(
+        &self,
+        expression: &WaveformExpression,
+    ) -> WaveformResult<WaveformSignalVector> {
+        let width = self.get_expression_width(expression)?;
+        let ids = expression.get_ids();
+        let mut values = ids
+            .iter()
+            .map(|id| (*id, new_unknown(self.vector_signals[id].get_width())))
+            .collect::<HashMap<_, _>>();
+        let mut signal = WaveformSignalVector::new(width);
+        let mut last = None;
+        let mut events = self.iter_events(&ids, 0..self.timestamps.len()).peekable();
+        while let Some((timestamp, id, value)) = events.next() {
+            if let WaveformValueResult::Vector(bv, _) = &value {
+                values.insert(id, bv.clone());
+            }
+            // Apply every change at the same timestamp before evaluating
+            if events.peek().is_some_and(|(next, _, _)| *next == timestamp) {
+                continue;
+            }
+            let bv = expression.evaluate(&|id| values[&id].clone());
+            if last.as_ref() != Some(&bv) {
+                signal.update(value.get_timestamp_index(), bv.clone());
+                last = Some(bv);
+            }
+        }
+        Ok(signal)
+    }
+
+    /// Replaces a derived signal with a stored vector signal holding its
+    /// materialised changes
+    pub fn materialize_derived(&mut self, id: usize) -> WaveformResult<()> {
+        let Some(derived) = self.derived_signals.get(&id) else {
+            return Err(WaveformError::InvalidId { id });
+        };
+        let signal = self.materialize(&derived.expression)?;
+        self.derived_signals.remove(&id);
+        self.vector_signals.insert(id, signal);
+        Ok(())
+    }
+}
@@ -1,3 +1,30 @@
+use crate::timescale::WaveformTimescale;
+
+#[derive(Debug)]
+pub enum WaveformVcdError {
+    UnexpectedEnd,
+    UnexpectedToken { token: String },
+    UnknownCommand { command: String },
+    UnknownIdentifier { identifier: String },
+    UnbalancedScope,
+    InvalidWidth { width: String },
+    MismatchedSignalType { identifier: String },
+    InvalidRange { range: String },
+    InvalidTimescale { timescale: String },
+    InvalidTimestamp { timestamp: String },
+    InvalidValue { value: String },
+}
+
+#[derive(Debug)]
+pub enum WaveformNativeError {
+    InvalidMagic,
+    UnsupportedVersion { version: u32 },
+    InvalidChecksum { offset: u64 },
+    InvalidOffset { offset: u64 },
+    InvalidHierarchy { offset: u64 },
+    InvalidSignal { id: usize },
+}
+
 #[derive(Debug)]
 pub enum WaveformError {
     DecreasingTimestamp {
@@ -12,6 +39,58 @@ pub enum WaveformError {
         actual: usize,
     },
     MismatchedTimestamps,
+    OverlappingTimestamps {
+        timestamp: u64,
+    },
+    MismatchedSignalType {
+        id: usize,
+    },
+    DuplicateId {
+        id: usize,
+    },
+    InvalidBlockSize {
+        size: usize,
+    },
+    InvalidBlockEncoding {
+        offset: usize,
+    },
+    InvalidBlockOrder {
+        block_index: usize,
+    },
+    InvalidValueIndex {
+        block_index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    Io {
+        error: std::io::Error,
+    },
+    InvalidVcd {
+        line: usize,
+        error: WaveformVcdError,
+    },
+    InvalidFst {
+        error: String,
+    },
+    InvalidNative {
+        error: WaveformNativeError,
+    },
+    CommittedTimestamp {
+        timestamp: u64,
+    },
+    InvalidRescale {
+        timescale: WaveformTimescale,
+    },
+    InvalidExpression {
+        offset: usize,
+        token: String,
+    },
+}
+
+impl From<std::io::Error> for WaveformError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io { error }
+    }
 }
 
 pub type WaveformResult<T> = Result<T, WaveformError>;
@@ -0,0 +1,80 @@
+// Merges the changes of many signals into a single stream in timestamp order,
+// so replaying or exporting a waveform does not have to walk every signal on
+// its own. Each signal keeps only its next change in a heap, so only one block
+// per signal is ever being decoded at a time.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Range;
+
+use crate::history::index::WaveformHistoryIndex;
+use crate::history::WaveformHistoryRangeIter;
+use crate::{WaveformSignalResult, WaveformValueResult};
+
+struct WaveformEventSignal<'a, B> {
+    id: usize,
+    signal: WaveformSignalResult<'a, B>,
+    iter: WaveformHistoryRangeIter<'a>,
+    next: Option<WaveformHistoryIndex>,
+}
+
+/// Iterator over the (timestamp, id, value) changes of a set of signals in
+/// timestamp order, where changes at the same timestamp are in the order the
+/// ids were given
+pub struct WaveformEventIter<'a, B = Vec<u8>> {
+    timestamps: &'a [u64],
+    signals: Vec<WaveformEventSignal<'a, B>>,
+    // Timestamp index of the next change of each signal, by signal position
+    heap: BinaryHeap<Reverse<(usize, usize)>>,
+}
+
+impl<'a, B: AsRef<[u8]>> WaveformEventIter<'a, B> {
+    pub(crate) fn new(
+        timestamps: &'a [u64],
+        signals: Vec<(usize, WaveformSignalResult<'a, B>)>,
+        range: Range<usize>,
+    ) -> Self {
+        let mut heap = BinaryHeap::with_capacity(signals.len());
+        let signals = signals
+            .into_iter()
+            .enumerate()
+            .map(|(position, (id, signal))| {
+                let mut iter = signal.get_history().iter_range(range.clone());
+                let next = iter.next();
+                if let Some(index) = &next {
+                    heap.push(Reverse((index.get_timestamp_index(), position)));
+                }
+                WaveformEventSignal {
+                    id,
+                    signal,
+                    iter,
+                    next,
+                }
+            })
+            .collect();
+        Self {
+            timestamps,
+            signals,
+            heap,
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Iterator for WaveformEventIter<'_, B> {
+    type Item = (u64, usize, WaveformValueResult);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((timestamp_index, position)) = self.heap.pop()?;
+        let signal = &mut self.signals[position];
+        let index = std::mem::replace(&mut signal.next, signal.iter.next()).unwrap();
+        if let Some(next) = &signal.next {
+            self.heap
+                .push(Reverse((next.get_timestamp_index(), position)));
+        }
+        Some((
+            self.timestamps[timestamp_index],
+            signal.id,
+            signal.signal.get_value(index, None),
+        ))
+    }
+}
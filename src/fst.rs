@@ -0,0 +1,13 @@
+// Fast Signal Trace support for reading waveforms into the database and writing
+// them back out, enabled with the "fst" feature. Decoding the blocks of the
+// format, including the zlib, LZ4 and FastLZ compressed variants, is handled by
+// the fst-reader crate and this module maps its hierarchy and value changes onto
+// a waveform. FST signal handles are already dense, so each handle index is
+// used directly as the signal id.
+//
+// Writing encodes the hierarchy, geometry and value change blocks directly from
+// the signal histories, where each signal gets the next handle in the order its
+// first variable is declared and any later variable becomes an alias.
+
+pub mod reader;
+pub mod writer;
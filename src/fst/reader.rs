@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Seek};
+
+use fst_reader::*;
+
+use crate::bitvector::BitVector;
+use crate::errors::*;
+use crate::hierarchy::*;
+use crate::timescale::WaveformTimescale;
+use crate::Waveform;
+
+fn fst_error(error: ReaderError) -> WaveformError {
+    match error {
+        ReaderError::Io(error) => WaveformError::Io { error },
+        error => WaveformError::InvalidFst {
+            error: error.to_string(),
+        },
+    }
+}
+
+fn get_scope_type(scope_type: FstScopeType) -> WaveformScopeType {
+    match scope_type {
+        FstScopeType::Module => WaveformScopeType::Module,
+        FstScopeType::Task => WaveformScopeType::Task,
+        FstScopeType::Function => WaveformScopeType::Function,
+        FstScopeType::Begin => WaveformScopeType::Begin,
+        FstScopeType::Fork => WaveformScopeType::Fork,
+        FstScopeType::Generate => WaveformScopeType::Generate,
+        FstScopeType::Struct => WaveformScopeType::Struct,
+        FstScopeType::Union => WaveformScopeType::Union,
+        FstScopeType::Class => WaveformScopeType::Class,
+        FstScopeType::Interface => WaveformScopeType::Interface,
+        FstScopeType::Package => WaveformScopeType::Package,
+        FstScopeType::Program => WaveformScopeType::Program,
+        FstScopeType::VhdlArchitecture => WaveformScopeType::VhdlArchitecture,
+        FstScopeType::VhdlProcedure => WaveformScopeType::VhdlProcedure,
+        FstScopeType::VhdlFunction => WaveformScopeType::VhdlFunction,
+        FstScopeType::VhdlRecord => WaveformScopeType::VhdlRecord,
+        FstScopeType::VhdlProcess => WaveformScopeType::VhdlProcess,
+        FstScopeType::VhdlBlock => WaveformScopeType::VhdlBlock,
+        FstScopeType::VhdlForGenerate => WaveformScopeType::VhdlForGenerate,
+        FstScopeType::VhdlIfGenerate => WaveformScopeType::VhdlIfGenerate,
+        FstScopeType::VhdlGenerate => WaveformScopeType::VhdlGenerate,
+        FstScopeType::VhdlPackage => WaveformScopeType::VhdlPackage,
+        FstScopeType::SvArray => WaveformScopeType::SvArray,
+        // These only mark attributes and never open a scope
+        FstScopeType::AttributeBegin
+        | FstScopeType::AttributeEnd
+        | FstScopeType::VcdScope
+        | FstScopeType::VcdUpScope => WaveformScopeType::Module,
+    }
+}
+
+fn get_variable_type(variable_type: FstVarType) -> WaveformVariableType {
+    match variable_type {
+        FstVarType::Event => WaveformVariableType::Event,
+        FstVarType::Integer => WaveformVariableType::Integer,
+        FstVarType::Parameter => WaveformVariableType::Parameter,
+        FstVarType::Real => WaveformVariableType::Real,
+        FstVarType::RealParameter => WaveformVariableType::RealParameter,
+        FstVarType::Reg => WaveformVariableType::Reg,
+        FstVarType::Supply0 => WaveformVariableType::Supply0,
+        FstVarType::Supply1 => WaveformVariableType::Supply1,
+        FstVarType::Time => WaveformVariableType::Time,
+        FstVarType::Tri => WaveformVariableType::Tri,
+        FstVarType::TriAnd => WaveformVariableType::TriAnd,
+        FstVarType::TriOr => WaveformVariableType::TriOr,
+        FstVarType::TriReg => WaveformVariableType::TriReg,
+        FstVarType::Tri0 => WaveformVariableType::Tri0,
+        FstVarType::Tri1 => WaveformVariableType::Tri1,
+        FstVarType::Wand => WaveformVariableType::WAnd,
+        FstVarType::Wire => WaveformVariableType::Wire,
+        FstVarType::Wor => WaveformVariableType::WOr,
+        FstVarType::Port => WaveformVariableType::Port,
+        FstVarType::SparseArray => WaveformVariableType::SparseArray,
+        FstVarType::RealTime => WaveformVariableType::RealTime,
+        FstVarType::GenericString => WaveformVariableType::String,
+        FstVarType::Bit => WaveformVariableType::Bit,
+        FstVarType::Logic => WaveformVariableType::Logic,
+        FstVarType::Int => WaveformVariableType::Int,
+        FstVarType::ShortInt => WaveformVariableType::ShortInt,
+        FstVarType::LongInt => WaveformVariableType::LongInt,
+        FstVarType::Byte => WaveformVariableType::Byte,
+        FstVarType::Enum => WaveformVariableType::Enum,
+        FstVarType::ShortReal => WaveformVariableType::ShortReal,
+    }
+}
+
+fn get_variable_direction(direction: FstVarDirection) -> WaveformVariableDirection {
+    match direction {
+        FstVarDirection::Implicit => WaveformVariableDirection::Implicit,
+        FstVarDirection::Input => WaveformVariableDirection::Input,
+        FstVarDirection::Output => WaveformVariableDirection::Output,
+        FstVarDirection::InOut => WaveformVariableDirection::Inout,
+        FstVarDirection::Buffer => WaveformVariableDirection::Buffer,
+        FstVarDirection::Linkage => WaveformVariableDirection::Linkage,
+    }
+}
+
+// Nine-value VHDL logic is folded into the four states of a bit-vector
+fn get_bitvector(value: &[u8], buffer: &mut Vec<u8>) -> BitVector {
+    if value.iter().all(|b| matches!(b, b'0' | b'1')) {
+        return BitVector::from_ascii(value);
+    }
+    buffer.clear();
+    buffer.extend(value.iter().map(|b| match b {
+        b'0' | b'l' | b'L' => b'0',
+        b'1' | b'h' | b'H' => b'1',
+        b'z' | b'Z' => b'z',
+        _ => b'x',
+    }));
+    BitVector::from_ascii_four_state(buffer)
+}
+
+pub struct WaveformFstReader<R: BufRead + Seek> {
+    reader: FstReader<R>,
+    // Width of each signal by id, or None for real signals
+    signals: HashMap<usize, Option<usize>>,
+    selected: Option<HashSet<usize>>,
+}
+
+impl<R: BufRead + Seek> WaveformFstReader<R> {
+    pub fn new(reader: R) -> WaveformResult<Self> {
+        Ok(Self {
+            reader: FstReader::open_and_read_time_table(reader).map_err(fst_error)?,
+            signals: HashMap::new(),
+            selected: None,
+        })
+    }
+
+    /// Restricts the body to only loading the given signal ids, where the
+    /// value changes of every other signal are never decompressed
+    pub fn set_selected(&mut self, selected: Option<HashSet<usize>>) {
+        self.selected = selected;
+    }
+
+    /// Returns the timescale as a power of ten in seconds
+    pub fn get_timescale_exponent(&self) -> i8 {
+        self.reader.get_header().timescale_exponent
+    }
+
+    /// Returns the width of every signal by id, or None for real signals, once
+    /// the header has been read
+    pub fn get_signals(&self) -> &HashMap<usize, Option<usize>> {
+        &self.signals
+    }
+
+    /// Reads an entire FST into a new waveform
+    pub fn read(mut self) -> WaveformResult<Waveform> {
+        let mut waveform = Waveform::new();
+        self.read_header(&mut waveform)?;
+        self.read_body(&mut waveform)?;
+        Ok(waveform)
+    }
+
+    /// Reads the hierarchy into the waveform, where signals are only
+    /// initialized once the body is read so a selection can be made from the
+    /// hierarchy first. The timescale is left unset if it is finer than
+    /// femtoseconds or coarser than 100s
+    pub fn read_header(&mut self, waveform: &mut Waveform) -> WaveformResult<()> {
+        waveform.set_timescale(WaveformTimescale::from_exponent(
+            self.get_timescale_exponent(),
+        ));
+        let hierarchy = waveform.get_hierarchy_mut();
+        let signals = &mut self.signals;
+        self.reader
+            .read_hierarchy(|entry| match entry {
+                FstHierarchyEntry::Scope { tpe, name, .. } => {
+                    hierarchy.push_scope(&name, get_scope_type(tpe));
+                }
+                FstHierarchyEntry::UpScope => {
+                    hierarchy.pop_scope();
+                }
+                // Variable length strings cannot be stored as signals
+                FstHierarchyEntry::Var { length: 0, .. } => {}
+                FstHierarchyEntry::Var {
+                    tpe,
+                    direction,
+                    name,
+                    length,
+                    handle,
+                    ..
+                } => {
+                    // The length of ports is already converted back from
+                    // their declared encoding to the width of their values
+                    let id = handle.get_index();
+                    let variable_type = get_variable_type(tpe);
+                    let width = if variable_type.is_real() {
+                        signals.entry(id).or_insert(None);
+                        64
+                    } else {
+                        signals.entry(id).or_insert(Some(length as usize));
+                        length as usize
+                    };
+                    let (name, range) = WaveformBitRange::split_name(&name);
+                    hierarchy.add_variable(WaveformVariable::new(
+                        name,
+                        id,
+                        variable_type,
+                        width,
+                        range,
+                        get_variable_direction(direction),
+                    ));
+                }
+                _ => {}
+            })
+            .map_err(fst_error)
+    }
+
+    /// Reads the value changes of the selected signals, inserting every
+    /// timestamp in the file
+    pub fn read_body(&mut self, waveform: &mut Waveform) -> WaveformResult<()> {
+        let mut ids = self
+            .signals
+            .keys()
+            .cloned()
+            .filter(|id| {
+                self.selected
+                    .as_ref()
+                    .map(|selected| selected.contains(id))
+                    .unwrap_or(true)
+            })
+            .collect::<Vec<usize>>();
+        ids.sort_unstable();
+        for id in &ids {
+            match self.signals[id] {
+                Some(width) => waveform.initialize_vector(*id, width),
+                None => waveform.initialize_real(*id),
+            }
+        }
+        let filter = FstFilter::filter_signals(
+            ids.iter()
+                .map(|id| FstSignalHandle::from_index(*id))
+                .collect(),
+        );
+        let timestamps = self
+            .reader
+            .get_time_table()
+            .map(|timestamps| timestamps.to_vec())
+            .unwrap_or_default();
+        let mut timestamps = timestamps.into_iter().peekable();
+        let mut buffer = Vec::new();
+        let mut result = Ok(());
+        self.reader
+            .read_signals(&filter, |timestamp, handle, value| {
+                if result.is_err() {
+                    return;
+                }
+                result = (|| {
+                    // Timestamps without any selected changes are still kept
+                    while let Some(next) = timestamps.next_if(|t| *t <= timestamp) {
+                        waveform.insert_timestamp(next)?;
+                    }
+                    if waveform.get_timestamps().last() != Some(&timestamp) {
+                        waveform.insert_timestamp(timestamp)?;
+                    }
+                    match value {
+                        FstSignalValue::String(value) => waveform
+                            .update_vector(handle.get_index(), get_bitvector(value, &mut buffer)),
+                        FstSignalValue::Real(value) => {
+                            waveform.update_real(handle.get_index(), value)
+                        }
+                    }
+                })();
+            })
+            .map_err(fst_error)?;
+        result?;
+        for timestamp in timestamps {
+            waveform.insert_timestamp(timestamp)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads an entire FST into a new waveform
+pub fn read_fst<R: BufRead + Seek>(reader: R) -> WaveformResult<Waveform> {
+    WaveformFstReader::new(reader)?.read()
+}
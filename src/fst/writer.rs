@@ -0,0 +1,651 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::ops::Range;
+
+use crate::bitvector::{BitVector, Logic};
+use crate::crc::crc32;
+use crate::errors::*;
+use crate::hierarchy::*;
+use crate::timescale::WaveformTimescale;
+use crate::{Waveform, WaveformSearchMode, WaveformSignalResult, WaveformValueResult};
+
+const BLOCK_HEADER: u8 = 0;
+const BLOCK_VALUE_CHANGE: u8 = 5;
+const BLOCK_GEOMETRY: u8 = 3;
+const BLOCK_HIERARCHY_GZIP: u8 = 4;
+const BLOCK_HIERARCHY_LZ4: u8 = 6;
+
+const HEADER_LENGTH: u64 = 329;
+const HEADER_VERSION_LENGTH: usize = 128;
+const HEADER_DATE_LENGTH: usize = 119;
+
+const HIERARCHY_SCOPE: u8 = 254;
+const HIERARCHY_UPSCOPE: u8 = 255;
+
+const ZLIB_LEVEL: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaveformFstCompression {
+    Zlib,
+    Lz4,
+}
+
+fn get_scope_type(scope_type: WaveformScopeType) -> u8 {
+    match scope_type {
+        WaveformScopeType::Module => 0,
+        WaveformScopeType::Task => 1,
+        WaveformScopeType::Function => 2,
+        WaveformScopeType::Begin => 3,
+        WaveformScopeType::Fork => 4,
+        WaveformScopeType::Generate => 5,
+        WaveformScopeType::Struct => 6,
+        WaveformScopeType::Union => 7,
+        WaveformScopeType::Class => 8,
+        WaveformScopeType::Interface => 9,
+        WaveformScopeType::Package => 10,
+        WaveformScopeType::Program => 11,
+        WaveformScopeType::VhdlArchitecture => 12,
+        WaveformScopeType::VhdlProcedure => 13,
+        WaveformScopeType::VhdlFunction => 14,
+        WaveformScopeType::VhdlRecord => 15,
+        WaveformScopeType::VhdlProcess => 16,
+        WaveformScopeType::VhdlBlock => 17,
+        WaveformScopeType::VhdlForGenerate => 18,
+        WaveformScopeType::VhdlIfGenerate => 19,
+        WaveformScopeType::VhdlGenerate => 20,
+        WaveformScopeType::VhdlPackage => 21,
+        WaveformScopeType::SvArray => 22,
+    }
+}
+
+fn get_variable_type(variable_type: WaveformVariableType) -> u8 {
+    match variable_type {
+        WaveformVariableType::Event => 0,
+        WaveformVariableType::Integer => 1,
+        WaveformVariableType::Parameter => 2,
+        WaveformVariableType::Real => 3,
+        WaveformVariableType::RealParameter => 4,
+        WaveformVariableType::Reg => 5,
+        WaveformVariableType::Supply0 => 6,
+        WaveformVariableType::Supply1 => 7,
+        WaveformVariableType::Time => 8,
+        WaveformVariableType::Tri => 9,
+        WaveformVariableType::TriAnd => 10,
+        WaveformVariableType::TriOr => 11,
+        WaveformVariableType::TriReg => 12,
+        WaveformVariableType::Tri0 => 13,
+        WaveformVariableType::Tri1 => 14,
+        WaveformVariableType::WAnd => 15,
+        WaveformVariableType::Wire => 16,
+        WaveformVariableType::WOr => 17,
+        WaveformVariableType::Port => 18,
+        WaveformVariableType::SparseArray => 19,
+        WaveformVariableType::RealTime => 20,
+        WaveformVariableType::String => 21,
+        WaveformVariableType::Bit => 22,
+        WaveformVariableType::Logic => 23,
+        WaveformVariableType::Int => 24,
+        WaveformVariableType::ShortInt => 25,
+        WaveformVariableType::LongInt => 26,
+        WaveformVariableType::Byte => 27,
+        WaveformVariableType::Enum => 28,
+        WaveformVariableType::ShortReal => 29,
+    }
+}
+
+fn get_variable_direction(direction: WaveformVariableDirection) -> u8 {
+    match direction {
+        WaveformVariableDirection::Implicit => 0,
+        WaveformVariableDirection::Input => 1,
+        WaveformVariableDirection::Output => 2,
+        WaveformVariableDirection::Inout => 3,
+        WaveformVariableDirection::Buffer => 4,
+        WaveformVariableDirection::Linkage => 5,
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend_from_slice(value.as_bytes());
+    bytes.push(0);
+}
+
+fn write_fixed_string(bytes: &mut Vec<u8>, value: &str, length: usize) {
+    let value = &value.as_bytes()[..value.len().min(length - 1)];
+    bytes.extend_from_slice(value);
+    bytes.resize(bytes.len() + length - value.len(), 0);
+}
+
+// Zlib compresses the bytes unless that would not make them any smaller, where
+// readers treat equal compressed and uncompressed lengths as not compressed
+fn compress_zlib(bytes: &[u8]) -> Vec<u8> {
+    let compressed = miniz_oxide::deflate::compress_to_vec_zlib(bytes, ZLIB_LEVEL);
+    if compressed.len() < bytes.len() {
+        compressed
+    } else {
+        bytes.to_vec()
+    }
+}
+
+fn compress_gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut compressed = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    compressed.extend(miniz_oxide::deflate::compress_to_vec(bytes, ZLIB_LEVEL));
+    compressed.extend_from_slice(&crc32(0, bytes).to_le_bytes());
+    compressed.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    compressed
+}
+
+// Values are written as the characters of each bit from the MSB down
+fn get_bitvector_chars(bv: &BitVector, chars: &mut Vec<u8>) -> bool {
+    chars.clear();
+    let mut two_state = true;
+    for i in (0..bv.get_bit_width()).rev() {
+        chars.push(match bv.get_bit(i) {
+            Logic::Zero => b'0',
+            Logic::One => b'1',
+            Logic::Unknown => {
+                two_state = false;
+                b'x'
+            }
+            Logic::HighImpedance => {
+                two_state = false;
+                b'z'
+            }
+        });
+    }
+    two_state
+}
+
+fn write_change(bytes: &mut Vec<u8>, value: &WaveformValueResult, delta: u64, chars: &mut Vec<u8>) {
+    match value {
+        WaveformValueResult::Vector(bv, _) => {
+            let two_state = get_bitvector_chars(bv, chars);
+            match (chars.len(), two_state) {
+                (1, true) => write_varint(bytes, (delta << 2) | (((chars[0] - b'0') as u64) << 1)),
+                (1, false) => {
+                    let state = if chars[0] == b'x' { 0 } else { 1 };
+                    write_varint(bytes, (delta << 4) | (state << 1) | 1);
+                }
+                (_, true) => {
+                    write_varint(bytes, delta << 1);
+                    let start = bytes.len();
+                    bytes.resize(start + chars.len().div_ceil(8), 0);
+                    for (i, c) in chars.iter().enumerate() {
+                        if *c == b'1' {
+                            bytes[start + i / 8] |= 0x80 >> (i % 8);
+                        }
+                    }
+                }
+                (_, false) => {
+                    write_varint(bytes, (delta << 1) | 1);
+                    bytes.extend_from_slice(chars);
+                }
+            }
+        }
+        WaveformValueResult::Real(r, _) => {
+            write_varint(bytes, (delta << 1) | 1);
+            bytes.extend_from_slice(&r.to_le_bytes());
+        }
+    }
+}
+
+pub struct WaveformFstWriter<'a> {
+    waveform: &'a Waveform,
+    range: Option<Range<u64>>,
+    selected: Option<HashSet<usize>>,
+    timescale: Option<WaveformTimescale>,
+    compression: WaveformFstCompression,
+    block_timestamps: usize,
+}
+
+impl<'a> WaveformFstWriter<'a> {
+    pub fn new(waveform: &'a Waveform) -> Self {
+        Self {
+            waveform,
+            range: None,
+            selected: None,
+            timescale: None,
+            compression: WaveformFstCompression::Zlib,
+            block_timestamps: 1 << 16,
+        }
+    }
+
+    /// Restricts the output to the timestamps inside the range, where the
+    /// values in effect at the first of them are written as changes to it
+    pub fn set_range(&mut self, range: Option<Range<u64>>) {
+        self.range = range;
+    }
+
+    /// Restricts the output to only the given signal ids, leaving out the
+    /// variables of every other signal
+    pub fn set_selected(&mut self, selected: Option<HashSet<usize>>) {
+        self.selected = selected;
+    }
+
+    /// Sets the timescale as a power of ten in seconds in place of the one of
+    /// the waveform, where every timestamp is converted to it. Writing fails if
+    /// it is coarser than the timescale of the waveform, as with
+    /// `Waveform::rescale`
+    pub fn set_timescale_exponent(&mut self, timescale_exponent: i8) {
+        let timescale = WaveformTimescale::from_exponent(timescale_exponent);
+        assert!(
+            timescale.is_some(),
+            "Timescale must be between femtoseconds and 100s"
+        );
+        self.timescale = timescale;
+    }
+
+    /// Sets the compression of the value changes and hierarchy, where the
+    /// smaller sections always use zlib
+    pub fn set_compression(&mut self, compression: WaveformFstCompression) {
+        self.compression = compression;
+    }
+
+    /// Sets the most timestamps written in each value change block, which
+    /// bounds the memory needed to read back one block
+    pub fn set_block_timestamps(&mut self, block_timestamps: usize) {
+        assert!(
+            block_timestamps > 0,
+            "Blocks must hold at least one timestamp"
+        );
+        self.block_timestamps = block_timestamps;
+    }
+
+    fn is_selected(&self, id: usize) -> bool {
+        self.waveform.get_stored_signal(id).is_some()
+            && self
+                .selected
+                .as_ref()
+                .map(|selected| selected.contains(&id))
+                .unwrap_or(true)
+    }
+
+    /// Writes the waveform as an FST, which should be given a buffered writer.
+    /// Only stored signals are written, leaving out derived signals
+    pub fn write<W: Write>(&self, writer: &mut W) -> WaveformResult<()> {
+        // Handles are given out in the order variables are declared, with any
+        // later variable for the same id becoming an alias
+        let mut handles = HashMap::new();
+        let mut ids = Vec::new();
+        let mut hierarchy = FstHierarchy::default();
+        let waveform_hierarchy = self.waveform.get_hierarchy();
+        for index in waveform_hierarchy.get_root_variables() {
+            let variable = waveform_hierarchy.get_variable(*index).unwrap();
+            self.write_variable(&mut hierarchy, variable, &mut handles, &mut ids);
+        }
+        for index in waveform_hierarchy.get_root_scopes() {
+            self.write_scope(&mut hierarchy, *index, &mut handles, &mut ids);
+        }
+        let mut missing = self
+            .waveform
+            .vector_signals
+            .keys()
+            .chain(self.waveform.real_signals.keys())
+            .cloned()
+            .filter(|id| self.is_selected(*id) && !handles.contains_key(id))
+            .collect::<Vec<usize>>();
+        missing.sort_unstable();
+        for id in missing {
+            let (variable_type, width) = match self.waveform.get_stored_signal(id) {
+                Some(WaveformSignalResult::Vector(signal)) => {
+                    (WaveformVariableType::Wire, signal.get_width())
+                }
+                _ => (WaveformVariableType::Real, 64),
+            };
+            let variable = WaveformVariable::new(
+                &format!("signal_{id}"),
+                id,
+                variable_type,
+                width,
+                None,
+                WaveformVariableDirection::Implicit,
+            );
+            self.write_variable(&mut hierarchy, &variable, &mut handles, &mut ids);
+        }
+
+        let timestamps = self.waveform.get_timestamps();
+        let (start, end) = match &self.range {
+            Some(range) => (
+                timestamps.partition_point(|t| *t < range.start),
+                timestamps.partition_point(|t| *t < range.end),
+            ),
+            None => (0, timestamps.len()),
+        };
+        let ratio = self.waveform.get_written_ratio(self.timescale, end)?;
+        let (start_time, end_time) = if start < end {
+            (timestamps[start] * ratio, timestamps[end - 1] * ratio)
+        } else {
+            (0, 0)
+        };
+        writer.write_all(&self.encode_header(
+            start_time,
+            end_time,
+            &hierarchy,
+            ids.len(),
+            end.saturating_sub(start).div_ceil(self.block_timestamps),
+        ))?;
+        // Blocks are encoded one at a time so only one is ever in memory
+        for block_start in (start..end).step_by(self.block_timestamps) {
+            writer.write_all(&self.encode_block(
+                &ids,
+                start,
+                block_start..end.min(block_start + self.block_timestamps),
+                ratio,
+            ))?;
+        }
+        writer.write_all(&self.encode_geometry(&ids))?;
+        writer.write_all(&self.encode_hierarchy(&hierarchy.bytes))?;
+        Ok(())
+    }
+
+    fn write_scope(
+        &self,
+        hierarchy: &mut FstHierarchy,
+        index: usize,
+        handles: &mut HashMap<usize, usize>,
+        ids: &mut Vec<usize>,
+    ) {
+        let waveform_hierarchy = self.waveform.get_hierarchy();
+        let scope = waveform_hierarchy.get_scope(index).unwrap();
+        hierarchy.bytes.push(HIERARCHY_SCOPE);
+        hierarchy.bytes.push(get_scope_type(scope.get_scope_type()));
+        write_string(&mut hierarchy.bytes, scope.get_name());
+        write_string(&mut hierarchy.bytes, "");
+        hierarchy.scopes += 1;
+        for index in scope.get_variables() {
+            let variable = waveform_hierarchy.get_variable(*index).unwrap();
+            self.write_variable(hierarchy, variable, handles, ids);
+        }
+        for index in scope.get_scopes() {
+            self.write_scope(hierarchy, *index, handles, ids);
+        }
+        hierarchy.bytes.push(HIERARCHY_UPSCOPE);
+    }
+
+    fn write_variable(
+        &self,
+        hierarchy: &mut FstHierarchy,
+        variable: &WaveformVariable,
+        handles: &mut HashMap<usize, usize>,
+        ids: &mut Vec<usize>,
+    ) {
+        let id = variable.get_id();
+        if !self.is_selected(id) {
+            return;
+        }
+        // The declared width always matches the stored signal, where reals
+        // are declared by their size in bytes
+        let width = match self.waveform.get_stored_signal(id) {
+            Some(WaveformSignalResult::Vector(signal)) => signal.get_width(),
+            _ => 8,
+        };
+        // Ports are declared in the extended VCD encoding of three digits per
+        // bit plus two delimiters, which readers turn back into the width of
+        // the values given in the geometry
+        let width = match variable.get_variable_type() {
+            WaveformVariableType::Port => 3 * width + 2,
+            _ => width,
+        };
+        let name = match variable.get_range() {
+            Some(WaveformBitRange { msb, lsb }) if msb == lsb => {
+                format!("{} [{msb}]", variable.get_name())
+            }
+            Some(WaveformBitRange { msb, lsb }) => {
+                format!("{} [{msb}:{lsb}]", variable.get_name())
+            }
+            None => variable.get_name().to_string(),
+        };
+        hierarchy
+            .bytes
+            .push(get_variable_type(variable.get_variable_type()));
+        hierarchy
+            .bytes
+            .push(get_variable_direction(variable.get_direction()));
+        write_string(&mut hierarchy.bytes, &name);
+        write_varint(&mut hierarchy.bytes, width as u64);
+        match handles.get(&id) {
+            Some(handle) => write_varint(&mut hierarchy.bytes, *handle as u64),
+            None => {
+                ids.push(id);
+                handles.insert(id, ids.len());
+                write_varint(&mut hierarchy.bytes, 0);
+            }
+        }
+        hierarchy.variables += 1;
+    }
+
+    fn encode_header(
+        &self,
+        start_time: u64,
+        end_time: u64,
+        hierarchy: &FstHierarchy,
+        handles: usize,
+        blocks: usize,
+    ) -> Vec<u8> {
+        let mut bytes = vec![BLOCK_HEADER];
+        bytes.extend_from_slice(&HEADER_LENGTH.to_be_bytes());
+        bytes.extend_from_slice(&start_time.to_be_bytes());
+        bytes.extend_from_slice(&end_time.to_be_bytes());
+        // Reals are written little endian, which readers detect from e
+        bytes.extend_from_slice(&std::f64::consts::E.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        bytes.extend_from_slice(&(hierarchy.scopes as u64).to_be_bytes());
+        bytes.extend_from_slice(&(hierarchy.variables as u64).to_be_bytes());
+        bytes.extend_from_slice(&(handles as u64).to_be_bytes());
+        bytes.extend_from_slice(&(blocks as u64).to_be_bytes());
+        // Waveforms without a timescale are written as nanoseconds
+        let timescale_exponent = self
+            .timescale
+            .or(self.waveform.get_timescale())
+            .map(|timescale| timescale.get_exponent())
+            .unwrap_or(-9);
+        bytes.push(timescale_exponent as u8);
+        let version = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        write_fixed_string(&mut bytes, &version, HEADER_VERSION_LENGTH);
+        write_fixed_string(&mut bytes, "", HEADER_DATE_LENGTH);
+        // Verilog file type and zero time offset
+        bytes.push(0);
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        debug_assert_eq!(bytes.len() as u64, HEADER_LENGTH + 1);
+        bytes
+    }
+
+    fn encode_block(
+        &self,
+        ids: &[usize],
+        start: usize,
+        range: Range<usize>,
+        ratio: u64,
+    ) -> Vec<u8> {
+        let timestamps = &self.waveform.get_timestamps()[range.clone()];
+        let mut chars = Vec::new();
+
+        // The frame holds every value in effect at the start of the block
+        let mut frame = Vec::new();
+        for id in ids {
+            match self
+                .waveform
+                .search_value(*id, range.start, WaveformSearchMode::Before)
+            {
+                Some(WaveformValueResult::Vector(bv, _)) => {
+                    get_bitvector_chars(&bv, &mut chars);
+                    frame.extend_from_slice(&chars);
+                }
+                Some(WaveformValueResult::Real(r, _)) => frame.extend_from_slice(&r.to_le_bytes()),
+                None => match self.waveform.get_stored_signal(*id) {
+                    Some(WaveformSignalResult::Vector(signal)) => {
+                        frame.resize(frame.len() + signal.get_width(), b'x')
+                    }
+                    _ => frame.extend_from_slice(&f64::NAN.to_le_bytes()),
+                },
+            }
+        }
+
+        // Each signal has its changes packed separately, starting with the
+        // values in effect at the very first timestamp written
+        let pack_type = match self.compression {
+            WaveformFstCompression::Zlib => b'Z',
+            WaveformFstCompression::Lz4 => b'4',
+        };
+        let mut data = vec![pack_type];
+        let mut chain = Vec::new();
+        let mut previous_offset = 0;
+        let mut empty = 0;
+        let mut memory = 0;
+        let mut changes = Vec::new();
+        for id in ids {
+            changes.clear();
+            let mut previous = 0;
+            let mut write = |value: WaveformValueResult| {
+                let timestamp_index = value.get_timestamp_index().max(range.start) - range.start;
+                write_change(
+                    &mut changes,
+                    &value,
+                    (timestamp_index - previous) as u64,
+                    &mut chars,
+                );
+                previous = timestamp_index;
+            };
+            if range.start == start {
+                if let Some(value) =
+                    self.waveform
+                        .search_value(*id, start, WaveformSearchMode::Before)
+                {
+                    write(value);
+                }
+            }
+            let changes_start = if range.start == start {
+                range.start + 1
+            } else {
+                range.start
+            };
+            match self.waveform.get_stored_signal(*id) {
+                Some(WaveformSignalResult::Vector(signal)) => {
+                    for (index, bv) in signal.iter_range(changes_start..range.end) {
+                        write(WaveformValueResult::Vector(bv, index));
+                    }
+                }
+                Some(WaveformSignalResult::Real(signal)) => {
+                    for (index, r) in signal.iter_range(changes_start..range.end) {
+                        write(WaveformValueResult::Real(r, index));
+                    }
+                }
+                None => {}
+            }
+            if changes.is_empty() {
+                empty += 1;
+                continue;
+            }
+            if empty > 0 {
+                write_varint(&mut chain, empty << 1);
+                empty = 0;
+            }
+            write_varint(
+                &mut chain,
+                (((data.len() - previous_offset) as u64) << 1) | 1,
+            );
+            previous_offset = data.len();
+            memory += changes.len();
+            let compressed = match self.compression {
+                WaveformFstCompression::Zlib => {
+                    miniz_oxide::deflate::compress_to_vec_zlib(&changes, ZLIB_LEVEL)
+                }
+                WaveformFstCompression::Lz4 => lz4_flex::compress(&changes),
+            };
+            if compressed.len() < changes.len() {
+                write_varint(&mut data, changes.len() as u64);
+                data.extend_from_slice(&compressed);
+            } else {
+                write_varint(&mut data, 0);
+                data.extend_from_slice(&changes);
+            }
+        }
+        if empty > 0 {
+            write_varint(&mut chain, empty << 1);
+        }
+
+        let mut time_table = Vec::new();
+        let mut previous = 0;
+        for timestamp in timestamps.iter().map(|timestamp| timestamp * ratio) {
+            write_varint(&mut time_table, timestamp - previous);
+            previous = timestamp;
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(timestamps[0] * ratio).to_be_bytes());
+        bytes.extend_from_slice(&(timestamps[timestamps.len() - 1] * ratio).to_be_bytes());
+        bytes.extend_from_slice(&(memory as u64).to_be_bytes());
+        let compressed = compress_zlib(&frame);
+        write_varint(&mut bytes, frame.len() as u64);
+        write_varint(&mut bytes, compressed.len() as u64);
+        write_varint(&mut bytes, ids.len() as u64);
+        bytes.extend_from_slice(&compressed);
+        write_varint(&mut bytes, ids.len() as u64);
+        bytes.extend_from_slice(&data);
+        bytes.extend_from_slice(&chain);
+        bytes.extend_from_slice(&(chain.len() as u64).to_be_bytes());
+        let compressed = compress_zlib(&time_table);
+        bytes.extend_from_slice(&compressed);
+        bytes.extend_from_slice(&(time_table.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&(compressed.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&(timestamps.len() as u64).to_be_bytes());
+        encode_section(BLOCK_VALUE_CHANGE, &bytes)
+    }
+
+    fn encode_geometry(&self, ids: &[usize]) -> Vec<u8> {
+        let mut geometry = Vec::new();
+        for id in ids {
+            match self.waveform.get_stored_signal(*id) {
+                Some(WaveformSignalResult::Vector(signal)) => {
+                    write_varint(&mut geometry, signal.get_width() as u64)
+                }
+                _ => write_varint(&mut geometry, 0),
+            }
+        }
+        let compressed = compress_zlib(&geometry);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(geometry.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&(ids.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&compressed);
+        encode_section(BLOCK_GEOMETRY, &bytes)
+    }
+
+    fn encode_hierarchy(&self, hierarchy: &[u8]) -> Vec<u8> {
+        let (block_type, compressed) = match self.compression {
+            WaveformFstCompression::Zlib => (BLOCK_HIERARCHY_GZIP, compress_gzip(hierarchy)),
+            WaveformFstCompression::Lz4 => (BLOCK_HIERARCHY_LZ4, lz4_flex::compress(hierarchy)),
+        };
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(hierarchy.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&compressed);
+        encode_section(block_type, &bytes)
+    }
+}
+
+// Sections start with their block type and then their length, which counts
+// itself but not the block type
+fn encode_section(block_type: u8, bytes: &[u8]) -> Vec<u8> {
+    let mut section = vec![block_type];
+    section.extend_from_slice(&(bytes.len() as u64 + 8).to_be_bytes());
+    section.extend_from_slice(bytes);
+    section
+}
+
+#[derive(Default)]
+struct FstHierarchy {
+    bytes: Vec<u8>,
+    scopes: usize,
+    variables: usize,
+}
+
+/// Writes an entire waveform as an FST
+pub fn write_fst<W: Write>(waveform: &Waveform, writer: &mut W) -> WaveformResult<()> {
+    WaveformFstWriter::new(waveform).write(writer)
+}
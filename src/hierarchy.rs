@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+
+use indiscriminant::*;
+
+// Signals in the waveform are only known by id, so the hierarchy maps the
+// scopes and variable names of the design onto those ids. Scopes and variables
+// are stored in flat lists and reference each other by index, and any number
+// of variables can refer to the same id since dumps alias identical nets.
+//
+// Full paths join the scope and variable names with dots, where any dot or
+// backslash inside a name, as allowed by escaped identifiers, is escaped with
+// a backslash so that `top.\\a\.b` is the variable `\a.b` inside `top`.
+
+#[indiscriminant()]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaveformScopeType {
+    Module = "module",
+    Task = "task",
+    Function = "function",
+    Begin = "begin",
+    Fork = "fork",
+    Generate = "generate",
+    Struct = "struct",
+    Union = "union",
+    Class = "class",
+    Interface = "interface",
+    Package = "package",
+    Program = "program",
+    VhdlArchitecture = "vhdl_architecture",
+    VhdlProcedure = "vhdl_procedure",
+    VhdlFunction = "vhdl_function",
+    VhdlRecord = "vhdl_record",
+    VhdlProcess = "vhdl_process",
+    VhdlBlock = "vhdl_block",
+    VhdlForGenerate = "vhdl_for_generate",
+    VhdlIfGenerate = "vhdl_if_generate",
+    VhdlGenerate = "vhdl_generate",
+    VhdlPackage = "vhdl_package",
+    SvArray = "sv_array",
+}
+
+#[indiscriminant()]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaveformVariableType {
+    Event = "event",
+    Integer = "integer",
+    Parameter = "parameter",
+    Real = "real",
+    RealTime = "realtime",
+    Reg = "reg",
+    Supply0 = "supply0",
+    Supply1 = "supply1",
+    Time = "time",
+    Tri = "tri",
+    TriAnd = "triand",
+    TriOr = "trior",
+    TriReg = "trireg",
+    Tri0 = "tri0",
+    Tri1 = "tri1",
+    WAnd = "wand",
+    Wire = "wire",
+    WOr = "wor",
+    Logic = "logic",
+    RealParameter = "real_parameter",
+    Port = "port",
+    SparseArray = "sparray",
+    String = "string",
+    Bit = "bit",
+    Int = "int",
+    ShortInt = "shortint",
+    LongInt = "longint",
+    Byte = "byte",
+    Enum = "enum",
+    ShortReal = "shortreal",
+}
+
+impl WaveformVariableType {
+    pub fn is_real(&self) -> bool {
+        matches!(
+            self,
+            Self::Real | Self::RealTime | Self::RealParameter | Self::ShortReal
+        )
+    }
+}
+
+#[indiscriminant()]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaveformVariableDirection {
+    Implicit = "implicit",
+    Input = "input",
+    Output = "output",
+    Inout = "inout",
+    Buffer = "buffer",
+    Linkage = "linkage",
+}
+
+/// The declared [msb:lsb] range of a variable, which can be ascending,
+/// descending, or a single bit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaveformBitRange {
+    pub msb: isize,
+    pub lsb: isize,
+}
+
+impl WaveformBitRange {
+    /// Parses a range written as [msb:lsb] or [bit]
+    pub fn parse(range: &str) -> Option<Self> {
+        let inner = range.strip_prefix('[')?.strip_suffix(']')?;
+        let (msb, lsb) = inner.split_once(':').unwrap_or((inner, inner));
+        Some(Self {
+            msb: msb.trim().parse().ok()?,
+            lsb: lsb.trim().parse().ok()?,
+        })
+    }
+
+    /// Splits a name with a range attached to the end of it, such as
+    /// "data[7:0]" or "data [7:0]", into the name and the range
+    pub fn split_name(name: &str) -> (&str, Option<Self>) {
+        if let Some(index) = name.rfind('[').filter(|_| name.ends_with(']')) {
+            if let Some(range) = Self::parse(&name[index..]) {
+                return (name[..index].trim_end(), Some(range));
+            }
+        }
+        (name, None)
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.msb.abs_diff(self.lsb) + 1
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaveformVariable {
+    name: String,
+    id: usize,
+    variable_type: WaveformVariableType,
+    width: usize,
+    range: Option<WaveformBitRange>,
+    direction: WaveformVariableDirection,
+    scope: Option<usize>,
+}
+
+impl WaveformVariable {
+    pub fn new(
+        name: &str,
+        id: usize,
+        variable_type: WaveformVariableType,
+        width: usize,
+        range: Option<WaveformBitRange>,
+        direction: WaveformVariableDirection,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            id,
+            variable_type,
+            width,
+            range,
+            direction,
+            scope: None,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_variable_type(&self) -> WaveformVariableType {
+        self.variable_type
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_range(&self) -> Option<WaveformBitRange> {
+        self.range
+    }
+
+    pub fn get_direction(&self) -> WaveformVariableDirection {
+        self.direction
+    }
+
+    /// Returns the index of the scope containing this variable, or None if it
+    /// is at the top level
+    pub fn get_scope(&self) -> Option<usize> {
+        self.scope
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaveformScope {
+    name: String,
+    scope_type: WaveformScopeType,
+    parent: Option<usize>,
+    scopes: Vec<usize>,
+    variables: Vec<usize>,
+}
+
+impl WaveformScope {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_scope_type(&self) -> WaveformScopeType {
+        self.scope_type
+    }
+
+    pub fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    /// Returns the indices of the scopes directly inside this scope
+    pub fn get_scopes(&self) -> &[usize] {
+        &self.scopes
+    }
+
+    /// Returns the indices of the variables directly inside this scope
+    pub fn get_variables(&self) -> &[usize] {
+        &self.variables
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WaveformHierarchy {
+    scopes: Vec<WaveformScope>,
+    variables: Vec<WaveformVariable>,
+    root_scopes: Vec<usize>,
+    root_variables: Vec<usize>,
+    // Scopes currently open while building the hierarchy
+    stack: Vec<usize>,
+    scope_paths: HashMap<String, usize>,
+    variable_paths: HashMap<String, Vec<usize>>,
+    ids: HashMap<usize, Vec<usize>>,
+}
+
+impl WaveformHierarchy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens a scope inside the currently open scope, reopening an existing
+    /// scope with the same name if there is one, and returns its index
+    pub fn push_scope(&mut self, name: &str, scope_type: WaveformScopeType) -> usize {
+        let parent = self.stack.last().cloned();
+        let path = self.join_path(parent, name);
+        let index = if let Some(index) = self.scope_paths.get(&path) {
+            *index
+        } else {
+            let index = self.scopes.len();
+            self.scopes.push(WaveformScope {
+                name: name.to_string(),
+                scope_type,
+                parent,
+                scopes: Vec::new(),
+                variables: Vec::new(),
+            });
+            match parent {
+                Some(parent) => self.scopes[parent].scopes.push(index),
+                None => self.root_scopes.push(index),
+            }
+            self.scope_paths.insert(path, index);
+            index
+        };
+        self.stack.push(index);
+        index
+    }
+
+    /// Closes the currently open scope, returning its index or None if no
+    /// scope was open
+    pub fn pop_scope(&mut self) -> Option<usize> {
+        self.stack.pop()
+    }
+
+    /// Reopens an existing scope along with every scope containing it, or
+    /// closes every scope if given None
+    pub fn open_scope(&mut self, index: Option<usize>) {
+        self.stack.clear();
+        let mut scope = index;
+        while let Some(index) = scope {
+            self.stack.push(index);
+            scope = self.scopes[index].parent;
+        }
+        self.stack.reverse();
+    }
+
+    /// Returns the index of the currently open scope
+    pub fn get_current_scope(&self) -> Option<usize> {
+        self.stack.last().cloned()
+    }
+
+    /// Adds a variable to the currently open scope and returns its index
+    pub fn add_variable(&mut self, mut variable: WaveformVariable) -> usize {
+        let index = self.variables.len();
+        variable.scope = self.get_current_scope();
+        match variable.scope {
+            Some(scope) => self.scopes[scope].variables.push(index),
+            None => self.root_variables.push(index),
+        }
+        self.ids.entry(variable.id).or_default().push(index);
+        self.variables.push(variable);
+        self.variable_paths
+            .entry(self.get_variable_path(index))
+            .or_default()
+            .push(index);
+        index
+    }
+
+    pub fn get_scope(&self, index: usize) -> Option<&WaveformScope> {
+        self.scopes.get(index)
+    }
+
+    pub fn get_variable(&self, index: usize) -> Option<&WaveformVariable> {
+        self.variables.get(index)
+    }
+
+    pub fn get_scopes(&self) -> &[WaveformScope] {
+        &self.scopes
+    }
+
+    pub fn get_variables(&self) -> &[WaveformVariable] {
+        &self.variables
+    }
+
+    /// Returns the indices of the top level scopes
+    pub fn get_root_scopes(&self) -> &[usize] {
+        &self.root_scopes
+    }
+
+    /// Returns the indices of the variables outside of any scope
+    pub fn get_root_variables(&self) -> &[usize] {
+        &self.root_variables
+    }
+
+    /// Returns the full path of a scope with the scope names separated by dots
+    pub fn get_scope_path(&self, index: usize) -> String {
+        let scope = &self.scopes[index];
+        self.join_path(scope.parent, &scope.name)
+    }
+
+    /// Returns the full path of a variable with the scope names separated by
+    /// dots
+    pub fn get_variable_path(&self, index: usize) -> String {
+        let variable = &self.variables[index];
+        self.join_path(variable.scope, &variable.name)
+    }
+
+    // Appends an escaped name to the path of a scope
+    fn join_path(&self, scope: Option<usize>, name: &str) -> String {
+        let mut path = match scope {
+            Some(scope) => self.get_scope_path(scope) + ".",
+            None => String::new(),
+        };
+        for c in name.chars() {
+            if c == '.' || c == '\\' {
+                path.push('\\');
+            }
+            path.push(c);
+        }
+        path
+    }
+
+    /// Looks up the index of a scope by its full path
+    pub fn find_scope(&self, path: &str) -> Option<usize> {
+        self.scope_paths.get(path).cloned()
+    }
+
+    /// Looks up the indices of the variables with the given full path, where
+    /// more than one variable can share a path if they differ in bit range
+    pub fn find_variables(&self, path: &str) -> &[usize] {
+        self.variable_paths
+            .get(path)
+            .map(|indices| indices.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Looks up the id of the first variable with the given full path
+    pub fn find_id(&self, path: &str) -> Option<usize> {
+        self.find_variables(path)
+            .first()
+            .map(|index| self.variables[*index].id)
+    }
+
+    /// Returns the indices of every variable aliasing the given signal id
+    pub fn get_variables_by_id(&self, id: usize) -> &[usize] {
+        self.ids
+            .get(&id)
+            .map(|indices| indices.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns the ids of every variable inside a scope, including the scopes
+    /// nested inside it, without duplicates
+    pub fn get_scope_ids(&self, index: usize) -> Vec<usize> {
+        let mut ids = Vec::new();
+        let mut scopes = vec![index];
+        while let Some(index) = scopes.pop() {
+            let scope = &self.scopes[index];
+            ids.extend(scope.variables.iter().map(|i| self.variables[*i].id));
+            scopes.extend(scope.scopes.iter().rev());
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Adds the scopes and variables of another hierarchy into this one, where
+    /// scopes with matching paths are combined and variables identical to an
+    /// existing one in the same scope are skipped
+    pub fn merge(&mut self, other: &Self) {
+        // Merging always starts from the top level
+        let stack = std::mem::take(&mut self.stack);
+        for index in &other.root_variables {
+            self.merge_variable(&other.variables[*index]);
+        }
+        for index in &other.root_scopes {
+            self.merge_scope(other, *index);
+        }
+        self.stack = stack;
+    }
+
+    fn merge_scope(&mut self, other: &Self, index: usize) {
+        let scope = &other.scopes[index];
+        self.push_scope(&scope.name, scope.scope_type);
+        for index in &scope.variables {
+            self.merge_variable(&other.variables[*index]);
+        }
+        for index in &scope.scopes {
+            self.merge_scope(other, *index);
+        }
+        self.pop_scope();
+    }
+
+    fn merge_variable(&mut self, variable: &WaveformVariable) {
+        let path = self.join_path(self.get_current_scope(), &variable.name);
+        let exists = self.find_variables(&path).iter().any(|index| {
+            let existing = &self.variables[*index];
+            existing.id == variable.id && existing.range == variable.range
+        });
+        if !exists {
+            self.add_variable(variable.clone());
+        }
+    }
+}
@@ -2,7 +2,9 @@ pub mod block;
 pub mod index;
 
 use std::cmp::Ordering;
+use std::ops::Range;
 
+use crate::errors::*;
 use crate::history::block::WaveformHistoryBlock;
 use crate::history::block::WaveformHistoryBlockIter;
 use crate::history::index::WaveformHistoryIndex;
@@ -11,9 +13,14 @@ use crate::WaveformSearchMode;
 pub const BLOCK_SIZE: usize = 512;
 pub const MAX_BLOCK_CHANGES: usize = BLOCK_SIZE * 128;
 
-pub struct WaveformHistory {
+// Iterating an empty block yields nothing, used when a history has no blocks
+static EMPTY_BLOCK: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+// Histories are built up in owned blocks, but any bytes can be queried so the
+// blocks of a saved waveform can be used without copying them
+pub struct WaveformHistory<B = Vec<u8>> {
     timestamp_index_last: isize,
-    blocks: Vec<u8>,
+    blocks: B,
     block_index: isize,
     block_offset: usize,
 }
@@ -75,10 +82,13 @@ impl WaveformHistory {
         self.block_index += 1;
         // Write timestamp and value index for this block
         let block_bytes = self.block_index as usize * BLOCK_SIZE;
-        self.blocks[block_bytes..block_bytes + 8]
-            .clone_from_slice(&(timestamp_index as u64).to_be_bytes());
-        self.blocks[block_bytes + 8..block_bytes + 16]
-            .clone_from_slice(&(value_index as u64).to_be_bytes());
+        self.write_block_header(
+            self.block_index as usize,
+            &WaveformHistoryIndex {
+                timestamp_index,
+                value_index,
+            },
+        );
         // Write a single byte indicating one change occurred
         self.blocks[block_bytes + 16] = 128;
         self.block_offset = 17;
@@ -105,18 +115,289 @@ impl WaveformHistory {
         self.timestamp_index_last = timestamp_index as isize;
     }
 
-    pub fn get_block(&self, block_index: usize) -> WaveformHistoryBlock {
+    /// Drops all changes before the one in effect at the given timestamp index
+    /// and rebases the remaining timestamp indices to start at that index,
+    /// clamping the change in effect to timestamp index zero. Whole blocks are
+    /// dropped and only the first remaining block is re-encoded. Value indices
+    /// are rebased by the returned number of dropped values, which is rounded
+    /// down to a multiple of the value alignment so packed value storage can
+    /// be dropped in whole bytes
+    pub fn truncate_before(&mut self, timestamp_index: usize, value_alignment: usize) -> usize {
+        if self.get_block_count() == 0 || timestamp_index == 0 {
+            return 0;
+        }
+        let mut history = Self::new();
+        let Some(first) = self.search_timestamp_index(timestamp_index, WaveformSearchMode::Before)
+        else {
+            // Every change is after the timestamp index, so only rebase
+            history.append_blocks(self, 0, |index| WaveformHistoryIndex {
+                timestamp_index: index.timestamp_index - timestamp_index,
+                value_index: index.value_index,
+            });
+            *self = history;
+            return 0;
+        };
+        let first_block_index = self
+            .search_timestamp_block_index(first.get_timestamp_index(), WaveformSearchMode::Before)
+            .unwrap();
+        let dropped_values = first.get_value_index() - first.get_value_index() % value_alignment;
+        // Re-encode the first block starting from the change in effect
+        history.add_change(0, first.get_value_index() - dropped_values);
+        for index in self.get_block(first_block_index) {
+            if index.get_timestamp_index() > first.get_timestamp_index() {
+                history.add_change(
+                    index.get_timestamp_index() - timestamp_index,
+                    index.get_value_index() - dropped_values,
+                );
+            }
+        }
+        // Keep the following blocks as they are, other than their headers
+        history.append_blocks(self, first_block_index + 1, |index| WaveformHistoryIndex {
+            timestamp_index: index.timestamp_index - timestamp_index,
+            value_index: index.value_index - dropped_values,
+        });
+        *self = history;
+        dropped_values
+    }
+
+    /// Re-encodes the history with every timestamp index replaced by its entry
+    /// in the given table, which must be strictly increasing
+    pub fn remap_timestamps(&mut self, timestamp_indices: &[usize]) {
+        let mut history = Self::new();
+        for index in &*self {
+            history.add_change(
+                timestamp_indices[index.get_timestamp_index()],
+                index.get_value_index(),
+            );
+        }
+        *self = history;
+    }
+
+    /// Drops the first change, re-encoding only the first block
+    pub fn remove_first_change(&mut self) {
+        if self.get_block_count() == 0 {
+            return;
+        }
+        let mut history = Self::new();
+        for index in self.get_block(0).into_iter().skip(1) {
+            history.add_change(index.get_timestamp_index(), index.get_value_index());
+        }
+        history.append_blocks(self, 1, |index| index);
+        *self = history;
+    }
+
+    /// Appends the changes of another history after the changes of this one,
+    /// offsetting its timestamp indices and rebasing its value indices to
+    /// continue from the given value index. Once offset, the changes of the
+    /// other history must all come after the last change of this one
+    pub fn append(&mut self, other: &WaveformHistory, timestamp_offset: usize, value_index: usize) {
+        if other.get_block_count() == 0 {
+            return;
+        }
+        let value_index_first = other.get_block(0).get_value_index();
+        self.append_blocks(other, 0, |index| WaveformHistoryIndex {
+            timestamp_index: index.timestamp_index + timestamp_offset,
+            value_index: index.value_index - value_index_first + value_index,
+        });
+    }
+
+    // Appends the blocks of another history starting at the given block index,
+    // rewriting their headers and continuing from the state of its last block
+    fn append_blocks<F>(&mut self, other: &Self, block_index: usize, rebase: F)
+    where
+        F: Fn(WaveformHistoryIndex) -> WaveformHistoryIndex,
+    {
+        if block_index >= other.get_block_count() {
+            return;
+        }
+        let block_count = self.get_block_count();
+        self.blocks
+            .extend_from_slice(&other.blocks[block_index * BLOCK_SIZE..]);
+        for block_index in block_count..self.get_block_count() {
+            let index = rebase(self.get_block(block_index).get_index());
+            self.write_block_header(block_index, &index);
+        }
+        // Every timestamp index in the other history is shifted the same amount
+        let last_block = other.get_block(other.get_block_count() - 1);
+        let last_block_rebased = self.get_block(self.get_block_count() - 1);
+        self.timestamp_index_last =
+            (other.timestamp_index_last as usize - last_block.get_timestamp_index()
+                + last_block_rebased.get_timestamp_index()) as isize;
+        self.block_index = self.get_block_count() as isize - 1;
+        self.block_offset = other.block_offset;
+    }
+
+    fn write_block_header(&mut self, block_index: usize, index: &WaveformHistoryIndex) {
+        let block_bytes = block_index * BLOCK_SIZE;
+        self.blocks[block_bytes..block_bytes + 8]
+            .clone_from_slice(&(index.get_timestamp_index() as u64).to_be_bytes());
+        self.blocks[block_bytes + 8..block_bytes + 16]
+            .clone_from_slice(&(index.get_value_index() as u64).to_be_bytes());
+    }
+}
+
+impl<B: AsRef<[u8]>> WaveformHistory<B> {
+    /// Creates a history from untrusted encoded blocks, validating every block
+    /// as well as the ordering of timestamp indices and the continuity of
+    /// value indices across blocks
+    pub fn from_blocks(blocks: B) -> WaveformResult<Self> {
+        let size = blocks.as_ref().len();
+        if !size.is_multiple_of(BLOCK_SIZE) {
+            return Err(WaveformError::InvalidBlockSize { size });
+        }
+        let mut history = Self {
+            timestamp_index_last: -1,
+            blocks,
+            block_index: -1,
+            block_offset: 16,
+        };
+        let mut last: Option<WaveformHistoryIndex> = None;
+        for (block_index, block) in history.blocks.as_ref().chunks(BLOCK_SIZE).enumerate() {
+            let block_bytes = block_index * BLOCK_SIZE;
+            let block = WaveformHistoryBlock::new(block);
+            // Every block must contain at least one change
+            let (block_last, block_offset) = block
+                .validate()
+                .map_err(|offset| WaveformError::InvalidBlockEncoding {
+                    offset: block_bytes + offset,
+                })?
+                .ok_or(WaveformError::InvalidBlockEncoding {
+                    offset: block_bytes + 16,
+                })?;
+            // The last timestamp index is tracked as an isize
+            if block_last.get_timestamp_index() > isize::MAX as usize {
+                return Err(WaveformError::InvalidBlockEncoding {
+                    offset: block_bytes + block_offset - 1,
+                });
+            }
+            if let Some(last) = &last {
+                if block.get_timestamp_index() <= last.get_timestamp_index() {
+                    return Err(WaveformError::InvalidBlockOrder { block_index });
+                }
+                if block.get_value_index() != last.get_value_index() + 1 {
+                    return Err(WaveformError::InvalidValueIndex {
+                        block_index,
+                        expected: last.get_value_index() + 1,
+                        actual: block.get_value_index(),
+                    });
+                }
+            }
+            history.timestamp_index_last = block_last.get_timestamp_index() as isize;
+            history.block_index = block_index as isize;
+            history.block_offset = block_offset;
+            last = Some(block_last);
+        }
+        Ok(history)
+    }
+
+    pub fn get_block(&self, block_index: usize) -> WaveformHistoryBlock<'_> {
         WaveformHistoryBlock::new(
-            &self.blocks[(block_index * BLOCK_SIZE)..((block_index + 1) * BLOCK_SIZE)],
+            &self.blocks.as_ref()[(block_index * BLOCK_SIZE)..((block_index + 1) * BLOCK_SIZE)],
         )
     }
 
+    /// Returns the timestamp index of the last change, if there are any
+    pub fn get_last_timestamp_index(&self) -> Option<usize> {
+        if self.timestamp_index_last >= 0 {
+            Some(self.timestamp_index_last as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_blocks(&self) -> &[u8] {
+        self.blocks.as_ref()
+    }
+
     pub fn get_block_count(&self) -> usize {
-        self.blocks.len() / BLOCK_SIZE
+        self.blocks.as_ref().len() / BLOCK_SIZE
     }
 
     pub fn get_block_size(&self) -> usize {
-        self.blocks.len()
+        self.blocks.as_ref().len()
+    }
+
+    /// Returns an iterator over the changes with timestamp indices inside the
+    /// given range, starting from the block containing the range start rather
+    /// than decoding every block before it
+    pub fn iter_range(&self, range: Range<usize>) -> WaveformHistoryRangeIter<'_> {
+        let block_index = if self.get_block_count() == 0 {
+            0
+        } else {
+            self.search_timestamp_block_index(range.start, WaveformSearchMode::Before)
+                .unwrap_or(0)
+        };
+        let mut iter = WaveformHistoryIter::new(self.get_blocks(), block_index);
+        if range.start > 0 {
+            iter.seek(range.start - 1);
+        }
+        WaveformHistoryRangeIter {
+            iter,
+            end: range.end,
+        }
+    }
+
+    /// Returns an iterator over the changes with timestamp indices before the
+    /// given one, from the latest to the earliest, decoding one block at a
+    /// time as it goes backwards
+    pub fn iter_before(&self, timestamp_index: usize) -> WaveformHistoryRevIter<'_> {
+        let mut iter = WaveformHistoryRevIter {
+            block_index: 0,
+            indices: Vec::new(),
+            blocks: self.get_blocks(),
+        };
+        if self.get_block_count() == 0 || timestamp_index == 0 {
+            return iter;
+        }
+        if let Some(block_index) =
+            self.search_timestamp_block_index(timestamp_index - 1, WaveformSearchMode::Before)
+        {
+            iter.block_index = block_index;
+            iter.indices = self
+                .get_block(block_index)
+                .into_iter()
+                .take_while(|index| index.get_timestamp_index() < timestamp_index)
+                .collect();
+        }
+        iter
+    }
+
+    /// Returns the number of changes with timestamp indices inside the given
+    /// range. Blocks fully covered by the range are counted from their headers
+    /// without being decoded, which relies on value indices increasing by one
+    /// for every change
+    pub fn count_changes(&self, range: Range<usize>) -> usize {
+        if self.get_block_count() == 0 || range.start >= range.end {
+            return 0;
+        }
+        let Some(end_block_index) =
+            self.search_timestamp_block_index(range.end - 1, WaveformSearchMode::Before)
+        else {
+            // The range ends before the first change
+            return 0;
+        };
+        let start_block_index = self
+            .search_timestamp_block_index(range.start, WaveformSearchMode::Before)
+            .unwrap_or(0);
+        let count_block = |block_index: usize| {
+            self.get_block(block_index)
+                .into_iter()
+                .filter(|index| range.contains(&index.get_timestamp_index()))
+                .count()
+        };
+        if start_block_index == end_block_index {
+            return count_block(start_block_index);
+        }
+        // Only the first and last blocks can be partially covered by the range
+        let interior = self.get_block(end_block_index).get_value_index()
+            - self.get_block(start_block_index + 1).get_value_index();
+        count_block(start_block_index) + interior + count_block(end_block_index)
+    }
+
+    /// Returns true if there are any changes with timestamp indices inside the
+    /// given range, decoding at most the block containing the range start
+    pub fn has_change(&self, range: Range<usize>) -> bool {
+        self.iter_range(range).next().is_some()
     }
 
     fn search_timestamp_block_index(
@@ -124,6 +405,9 @@ impl WaveformHistory {
         timestamp_index: usize,
         search_mode: WaveformSearchMode,
     ) -> Option<usize> {
+        if self.get_block_count() == 0 {
+            return None;
+        }
         // https://stackoverflow.com/questions/30245166/find-the-nearest-closest-value-in-a-sorted-list
         let (mut start, mut end) = (0, self.get_block_count() - 1);
         // If the search timestamp is outside of the range of timestamps
@@ -171,18 +455,16 @@ impl WaveformHistory {
         timestamp_index: usize,
         search_mode: WaveformSearchMode,
     ) -> Option<WaveformHistoryIndex> {
-        let Some(block_index) = self.search_timestamp_block_index(
-            timestamp_index, WaveformSearchMode::Before
-        ) else {
-            return None
-        };
+        let block_index =
+            self.search_timestamp_block_index(timestamp_index, WaveformSearchMode::Before)?;
         // Determine if there are changes before the timestamp
         let mut iter = self.get_block(block_index).into_iter();
         let Some(index_before) = iter.seek(timestamp_index) else {
             // No timestamp is before the given timestamp
             return match search_mode {
-                WaveformSearchMode::After | WaveformSearchMode::Closest =>
-                        self.get_block(0).into_iter().next(),
+                WaveformSearchMode::After | WaveformSearchMode::Closest => {
+                    self.get_block(0).into_iter().next()
+                }
                 _ => None,
             };
         };
@@ -227,7 +509,7 @@ impl Default for WaveformHistory {
 pub struct WaveformHistoryIter<'a> {
     block_index: usize,
     block_iter: WaveformHistoryBlockIter<'a>,
-    history: &'a WaveformHistory,
+    blocks: &'a [u8],
 }
 
 impl<'a> Iterator for WaveformHistoryIter<'a> {
@@ -235,7 +517,7 @@ impl<'a> Iterator for WaveformHistoryIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if self.block_index >= self.history.get_block_count() {
+            if self.block_index >= self.get_block_count() {
                 return None;
             } else if let Some(index) = self.block_iter.next() {
                 return Some(index);
@@ -246,50 +528,105 @@ impl<'a> Iterator for WaveformHistoryIter<'a> {
     }
 }
 
-impl<'a> IntoIterator for &'a WaveformHistory {
+impl<'a, B: AsRef<[u8]>> IntoIterator for &'a WaveformHistory<B> {
     type Item = WaveformHistoryIndex;
 
     type IntoIter = WaveformHistoryIter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        WaveformHistoryIter {
-            block_index: 0,
-            block_iter: self.get_block(0).into_iter(),
-            history: self,
-        }
+        WaveformHistoryIter::new(self.get_blocks(), 0)
     }
 }
 
 impl<'a> WaveformHistoryIter<'a> {
+    fn new(blocks: &'a [u8], block_index: usize) -> Self {
+        let mut iter = Self {
+            block_index,
+            block_iter: WaveformHistoryBlock::new(&EMPTY_BLOCK).into_iter(),
+            blocks,
+        };
+        if block_index < iter.get_block_count() {
+            iter.block_iter = iter.get_block(block_index).into_iter();
+        }
+        iter
+    }
+
+    fn get_block_count(&self) -> usize {
+        self.blocks.len() / BLOCK_SIZE
+    }
+
+    fn get_block(&self, block_index: usize) -> WaveformHistoryBlock<'a> {
+        WaveformHistoryBlock::new(
+            &self.blocks[(block_index * BLOCK_SIZE)..((block_index + 1) * BLOCK_SIZE)],
+        )
+    }
+
     fn next_block(&mut self) {
         self.block_index += 1;
-        if self.block_index < self.history.get_block_count() {
-            self.block_iter = self.history.get_block(self.block_index).into_iter();
+        if self.block_index < self.get_block_count() {
+            self.block_iter = self.get_block(self.block_index).into_iter();
         }
     }
 
+    /// Moves past every change at or before the given timestamp index,
+    /// returning the last of them. Blocks whose following block starts at or
+    /// before the timestamp index are skipped from their headers without being
+    /// decoded, so seeking to increasing timestamp indices walks the history
+    /// once. Returns None if there are no changes between the current position
+    /// and the timestamp index
     pub fn seek(&mut self, timestamp_index: usize) -> Option<WaveformHistoryIndex> {
-        let mut last_block_index = self.block_index;
-        let mut last_block_iter = self.block_iter.clone();
-        let mut last_index = None;
+        if self.block_index >= self.get_block_count() {
+            return None;
+        }
+        while self.block_index + 1 < self.get_block_count()
+            && self.get_block(self.block_index + 1).get_timestamp_index() <= timestamp_index
+        {
+            self.next_block();
+        }
+        self.block_iter.seek(timestamp_index)
+    }
+}
+
+pub struct WaveformHistoryRangeIter<'a> {
+    iter: WaveformHistoryIter<'a>,
+    end: usize,
+}
+
+impl<'a> Iterator for WaveformHistoryRangeIter<'a> {
+    type Item = WaveformHistoryIndex;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.iter.next()?;
+        if index.get_timestamp_index() < self.end {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct WaveformHistoryRevIter<'a> {
+    // Blocks before this one are still to be decoded
+    block_index: usize,
+    indices: Vec<WaveformHistoryIndex>,
+    blocks: &'a [u8],
+}
+
+impl<'a> Iterator for WaveformHistoryRevIter<'a> {
+    type Item = WaveformHistoryIndex;
+
+    fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if self.block_index >= self.history.get_block_count() {
-                self.block_index = last_block_index;
-                self.block_iter = last_block_iter;
-                return last_index;
-            }
-            // Check if the current block has a result
-            if let Some(index) = self.block_iter.seek(timestamp_index) {
-                last_block_index = self.block_index;
-                last_block_iter = self.block_iter.clone();
-                last_index = Some(index);
-            } else {
-                self.block_index = last_block_index;
-                self.block_iter = last_block_iter;
-                return last_index;
+            if let Some(index) = self.indices.pop() {
+                return Some(index);
+            } else if self.block_index == 0 {
+                return None;
             }
-            // Go to next block if nothing found in last one
-            self.next_block();
+            self.block_index -= 1;
+            let start = self.block_index * BLOCK_SIZE;
+            self.indices = WaveformHistoryBlock::new(&self.blocks[start..start + BLOCK_SIZE])
+                .into_iter()
+                .collect();
         }
     }
 }
@@ -1,8 +1,12 @@
 use std::cmp::Ordering;
 
+use crate::errors::*;
 use crate::history::index::WaveformHistoryIndex;
 use crate::history::BLOCK_SIZE;
 
+// Skips are encoded as 7-bit groups, so a full usize takes this many bytes
+const MAX_SKIP_BYTES: usize = (usize::BITS as usize).div_ceil(7);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WaveformHistoryBlock<'a> {
     block: &'a [u8],
@@ -14,6 +18,86 @@ impl<'a> WaveformHistoryBlock<'a> {
         Self { block }
     }
 
+    /// Creates a block from untrusted bytes, checking that the skip and change
+    /// encoding can be decoded without overflowing the timestamp or value
+    /// indices and that all bytes after the last change are zero
+    pub fn try_new(block: &'a [u8]) -> WaveformResult<Self> {
+        if block.len() != BLOCK_SIZE {
+            return Err(WaveformError::InvalidBlockSize { size: block.len() });
+        }
+        let block = Self { block };
+        block
+            .validate()
+            .map_err(|offset| WaveformError::InvalidBlockEncoding { offset })?;
+        Ok(block)
+    }
+
+    /// Walks the encoding of the block without trusting it, returning the
+    /// last change and the offset right after it if there were any changes,
+    /// or the offset of the first invalid byte
+    pub(crate) fn validate(&self) -> Result<Option<(WaveformHistoryIndex, usize)>, usize> {
+        let mut index = self.get_index();
+        let mut last = None;
+        let mut offset = 16;
+        while offset < BLOCK_SIZE {
+            // Find the run of skip bytes before the next change byte
+            let skip_start = offset;
+            while offset < BLOCK_SIZE && self.block[offset] & 0x80 == 0 {
+                offset += 1;
+            }
+            if offset == BLOCK_SIZE {
+                // Trailing bytes are padding and must be empty
+                return match self.block[skip_start..].iter().position(|b| *b != 0) {
+                    Some(position) => Err(skip_start + position),
+                    None => Ok(last),
+                };
+            }
+            // The header is the first change, so a block cannot start with
+            // skips
+            if skip_start == 16 && offset > skip_start {
+                return Err(skip_start);
+            }
+            if offset - skip_start > MAX_SKIP_BYTES {
+                return Err(skip_start);
+            }
+            if offset > skip_start {
+                // Shifting out any set bits would wrap the skips silently
+                let skips = self.block[skip_start..offset]
+                    .iter()
+                    .try_fold(0usize, |skips, b| {
+                        Some(skips.checked_mul(1 << 7)? | *b as usize)
+                    })
+                    .ok_or(skip_start)?;
+                if skips == 0 {
+                    return Err(skip_start);
+                }
+                index.timestamp_index =
+                    index.timestamp_index.checked_add(skips).ok_or(skip_start)?;
+            }
+            // Every change advances both the timestamp and value index
+            let changes = ((self.block[offset] & 0x7F) + 1) as usize;
+            let (Some(timestamp_index), Some(value_index)) = (
+                index.timestamp_index.checked_add(changes),
+                index.value_index.checked_add(changes),
+            ) else {
+                return Err(offset);
+            };
+            offset += 1;
+            last = Some((
+                WaveformHistoryIndex {
+                    timestamp_index: timestamp_index - 1,
+                    value_index: value_index - 1,
+                },
+                offset,
+            ));
+            index = WaveformHistoryIndex {
+                timestamp_index,
+                value_index,
+            };
+        }
+        Ok(last)
+    }
+
     pub fn get_timestamp_index(&self) -> usize {
         u64::from_be_bytes((&self.block[0..8]).try_into().unwrap()) as usize
     }
@@ -30,22 +114,16 @@ impl<'a> WaveformHistoryBlock<'a> {
     }
 
     pub(crate) fn get_skips(&self, offset: usize) -> (usize, usize) {
-        let (mut skip_bytes, mut skips, mut skips_partial) = (0usize, 0usize, 0usize);
+        let (mut skip_bytes, mut skips) = (0usize, 0usize);
         debug_assert!(offset >= 16);
         for i in offset..BLOCK_SIZE {
             if self.block[i] & 0x80 == 0x80 {
                 break;
             }
-            skips_partial <<= 7;
-            skips_partial |= self.block[i] as usize;
+            // Trailing padding is all zero so it never shifts out set bits
+            skips = (skips << 7) | self.block[i] as usize;
             skip_bytes += 1;
-            // Add partial sum to total sum after 8 bytes
-            if skip_bytes & 0b111 == 0 {
-                skips += skips_partial;
-                skips_partial = 0;
-            }
         }
-        skips += skips_partial;
         debug_assert!(skips > 0 || offset + skip_bytes == BLOCK_SIZE || skip_bytes == 0);
         (skip_bytes, skips)
     }
@@ -33,17 +33,38 @@
 //    indices, not the timestamps themselves.
 
 pub mod bitvector;
+mod crc;
+pub mod derived;
 pub mod errors;
+pub mod events;
+#[cfg(feature = "fst")]
+pub mod fst;
+pub mod hierarchy;
 pub mod history;
+pub mod live;
+pub mod native;
+pub mod property;
+pub mod query;
 pub mod real;
+pub mod sample;
+pub mod search;
+pub mod timescale;
+pub mod trigger;
+pub mod vcd;
 pub mod vector;
 
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
 use crate::bitvector::BitVector;
+use crate::derived::WaveformDerivedSignal;
 use crate::errors::*;
+use crate::hierarchy::WaveformHierarchy;
+use crate::history::index::WaveformHistoryIndex;
+use crate::history::WaveformHistory;
+use crate::query::WaveformQuery;
 use crate::real::*;
+use crate::timescale::WaveformTimescale;
 use crate::vector::*;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -54,9 +75,65 @@ pub enum WaveformSearchMode {
     Exact,
 }
 
-pub enum WaveformSignalResult<'a> {
-    Vector(&'a WaveformSignalVector),
-    Real(&'a WaveformSignalReal),
+pub enum WaveformSignalResult<'a, B = Vec<u8>> {
+    Vector(&'a WaveformSignalVector<B>),
+    Real(&'a WaveformSignalReal<B>),
+}
+
+// Only references are held, so results can be copied for any storage
+impl<B> Clone for WaveformSignalResult<'_, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for WaveformSignalResult<'_, B> {}
+
+impl<'a, B: AsRef<[u8]>> WaveformSignalResult<'a, B> {
+    pub fn get_history(&self) -> &'a WaveformHistory<B> {
+        match self {
+            Self::Vector(signal) => signal.get_history(),
+            Self::Real(signal) => signal.get_history(),
+        }
+    }
+
+    /// Searches for the value of this signal at the given timestamp index,
+    /// optionally selecting only a single bit of vector signals
+    pub fn search_value(
+        &self,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        let index = self
+            .get_history()
+            .search_timestamp_index(timestamp_index, search_mode)?;
+        Some(self.get_value(index, bit_index))
+    }
+
+    // Returns the value of a change, optionally selecting only a single bit of
+    // vector signals
+    fn get_value(
+        &self,
+        index: WaveformHistoryIndex,
+        bit_index: Option<usize>,
+    ) -> WaveformValueResult {
+        match self {
+            Self::Vector(signal) => {
+                let bv = signal.get_bitvector(index.get_value_index());
+                let bv = if let Some(index) = bit_index {
+                    BitVector::from(bv.get_bit(index))
+                } else {
+                    bv
+                };
+                WaveformValueResult::Vector(bv, index.get_timestamp_index())
+            }
+            Self::Real(signal) => {
+                let r = signal.get_real(index.get_value_index());
+                WaveformValueResult::Real(r, index.get_timestamp_index())
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -87,10 +164,23 @@ impl WaveformValueResult {
     }
 }
 
+// Returns the finest of the given timescales, ignoring any that are missing
+fn get_finest_timescale(
+    timescales: impl Iterator<Item = Option<WaveformTimescale>>,
+) -> Option<WaveformTimescale> {
+    timescales
+        .flatten()
+        .min_by_key(|timescale| timescale.get_femtoseconds())
+}
+
 pub struct Waveform {
     timestamps: Vec<u64>,
     vector_signals: HashMap<usize, WaveformSignalVector>,
     real_signals: HashMap<usize, WaveformSignalReal>,
+    max_timestamps: Option<usize>,
+    hierarchy: WaveformHierarchy,
+    timescale: Option<WaveformTimescale>,
+    derived_signals: HashMap<usize, WaveformDerivedSignal>,
 }
 
 impl Waveform {
@@ -99,7 +189,106 @@ impl Waveform {
             timestamps: Vec::new(),
             vector_signals: HashMap::default(),
             real_signals: HashMap::default(),
+            max_timestamps: None,
+            hierarchy: WaveformHierarchy::new(),
+            timescale: None,
+            derived_signals: HashMap::default(),
+        }
+    }
+
+    /// Creates a waveform that only keeps the most recent timestamps, where
+    /// older timestamps are dropped once there are twice as many as requested
+    /// so that the cost of truncating is spread across many insertions
+    pub fn new_bounded(max_timestamps: usize) -> Self {
+        assert!(
+            max_timestamps > 0,
+            "Waveform must keep at least one timestamp"
+        );
+        Self {
+            max_timestamps: Some(max_timestamps),
+            ..Self::new()
+        }
+    }
+
+    pub fn get_max_timestamps(&self) -> Option<usize> {
+        self.max_timestamps
+    }
+
+    pub fn get_timescale(&self) -> Option<WaveformTimescale> {
+        self.timescale
+    }
+
+    /// Sets the timescale without changing any timestamps, see `rescale` to
+    /// convert them
+    pub fn set_timescale(&mut self, timescale: Option<WaveformTimescale>) {
+        self.timescale = timescale;
+    }
+
+    /// Converts every timestamp to a finer or equal timescale, returning an
+    /// error if the timescale is coarser or a timestamp would overflow. A
+    /// waveform without a timescale just takes the new one
+    pub fn rescale(&mut self, timescale: WaveformTimescale) -> WaveformResult<()> {
+        if let Some(timestamps) = self.get_rescaled_timestamps(timescale)? {
+            self.timestamps = timestamps;
+        }
+        self.timescale = Some(timescale);
+        Ok(())
+    }
+
+    // Returns the timestamps converted to another timescale, or None if they
+    // would stay the same
+    fn get_rescaled_timestamps(
+        &self,
+        timescale: WaveformTimescale,
+    ) -> WaveformResult<Option<Vec<u64>>> {
+        let ratio = self.get_timescale_ratio(timescale)?;
+        if ratio == 1 {
+            return Ok(None);
         }
+        self.timestamps
+            .iter()
+            .map(|timestamp| timestamp.checked_mul(ratio))
+            .collect::<Option<Vec<u64>>>()
+            .map(Some)
+            .ok_or(WaveformError::InvalidRescale { timescale })
+    }
+
+    // Returns how many ticks of another timescale make up one tick of the
+    // waveform, where a waveform without a timescale takes on the other as is
+    pub(crate) fn get_timescale_ratio(&self, timescale: WaveformTimescale) -> WaveformResult<u64> {
+        match self.timescale {
+            Some(current) => timescale
+                .get_ratio(&current)
+                .ok_or(WaveformError::InvalidRescale { timescale }),
+            None => Ok(1),
+        }
+    }
+
+    // Returns the ratio for writing the timestamps before the given index in
+    // another timescale, failing if the last of them would overflow
+    pub(crate) fn get_written_ratio(
+        &self,
+        timescale: Option<WaveformTimescale>,
+        end: usize,
+    ) -> WaveformResult<u64> {
+        let Some(timescale) = timescale else {
+            return Ok(1);
+        };
+        let ratio = self.get_timescale_ratio(timescale)?;
+        match end.checked_sub(1).map(|last| self.timestamps[last]) {
+            Some(timestamp) if timestamp.checked_mul(ratio).is_none() => {
+                Err(WaveformError::InvalidRescale { timescale })
+            }
+            _ => Ok(ratio),
+        }
+    }
+
+    pub fn get_hierarchy(&self) -> &WaveformHierarchy {
+        &self.hierarchy
+    }
+
+    pub fn get_hierarchy_mut(&mut self) -> &mut WaveformHierarchy {
+        &mut self.hierarchy
     }
 
     pub fn shard(self, num_shards: usize) -> Vec<Self> {
@@ -107,6 +296,9 @@ impl Waveform {
         for _ in 0..num_shards {
             let mut shard = Self::new();
             shard.timestamps = self.timestamps.clone();
+            shard.max_timestamps = self.max_timestamps;
+            shard.hierarchy = self.hierarchy.clone();
+            shard.timescale = self.timescale;
             shards.push(shard);
         }
         for (id, signal) in self.vector_signals {
@@ -115,14 +307,28 @@ impl Waveform {
         for (id, signal) in self.real_signals {
             shards[id % num_shards].real_signals.insert(id, signal);
         }
+        // Sources can end up in another shard, so nothing materialised is kept
+        for (id, derived) in self.derived_signals {
+            shards[id % num_shards].derived_signals.insert(id, derived);
+        }
+        for shard in &mut shards {
+            shard.invalidate_derived();
+        }
         shards
     }
 
     pub fn unshard(shards: Vec<Self>) -> WaveformResult<Self> {
-        let timestamps = if let Some(shard) = shards.first() {
-            shard.timestamps.clone()
+        // Shards all share the hierarchy of the waveform they came from
+        let (timestamps, max_timestamps, hierarchy, timescale) = if let Some(shard) = shards.first()
+        {
+            (
+                shard.timestamps.clone(),
+                shard.max_timestamps,
+                shard.hierarchy.clone(),
+                shard.timescale,
+            )
         } else {
-            Vec::new()
+            (Vec::new(), None, WaveformHierarchy::new(), None)
         };
         for shard in &shards {
             if shard.timestamps != timestamps {
@@ -131,31 +337,216 @@ impl Waveform {
         }
         let mut merged = Self::new();
         merged.timestamps = timestamps;
+        merged.max_timestamps = max_timestamps;
+        merged.hierarchy = hierarchy;
+        merged.timescale = timescale;
         for shard in shards {
-            merged.vector_signals.extend(shard.vector_signals);
-            merged.real_signals.extend(shard.real_signals);
+            merged.insert_signals(shard)?;
+        }
+        Ok(merged)
+    }
+
+    /// Merges waveforms holding different signals whose timestamps can differ,
+    /// using the union of all timestamps and remapping the timestamp indices
+    /// of every signal onto it. Waveforms with different timescales are all
+    /// rescaled to the finest of them first, and the merged waveform keeps the
+    /// smallest bound of any of them
+    pub fn merge(mut waveforms: Vec<Self>) -> WaveformResult<Self> {
+        let mut merged = Self::new();
+        merged.timescale = get_finest_timescale(waveforms.iter().map(|w| w.timescale));
+        if let Some(timescale) = merged.timescale {
+            for waveform in &mut waveforms {
+                if waveform.timescale.is_some() {
+                    waveform.rescale(timescale)?;
+                }
+            }
+        }
+        merged.max_timestamps = waveforms.iter().filter_map(|w| w.max_timestamps).min();
+        let mut timestamps = waveforms
+            .iter()
+            .flat_map(|w| w.timestamps.iter().cloned())
+            .collect::<Vec<u64>>();
+        timestamps.sort_unstable();
+        timestamps.dedup();
+        merged.timestamps = timestamps;
+        for mut waveform in waveforms {
+            if waveform.timestamps.len() != merged.timestamps.len() {
+                // Both timestamp lists are sorted so they can be walked together
+                let mut timestamp_indices = Vec::with_capacity(waveform.timestamps.len());
+                let mut merged_index = 0;
+                for timestamp in &waveform.timestamps {
+                    while merged.timestamps[merged_index] != *timestamp {
+                        merged_index += 1;
+                    }
+                    timestamp_indices.push(merged_index);
+                }
+                for signal in waveform.vector_signals.values_mut() {
+                    signal.remap_timestamps(&timestamp_indices);
+                }
+                for signal in waveform.real_signals.values_mut() {
+                    signal.remap_timestamps(&timestamp_indices);
+                }
+            }
+            merged.hierarchy.merge(&waveform.hierarchy);
+            merged.insert_signals(waveform)?;
         }
+        merged.truncate_bounded();
         Ok(merged)
     }
 
+    // Moves the signals of another waveform into this one, returning an error
+    // for the first id that exists in both
+    fn insert_signals(&mut self, other: Self) -> WaveformResult<()> {
+        for id in other
+            .vector_signals
+            .keys()
+            .chain(other.real_signals.keys())
+            .chain(other.derived_signals.keys())
+        {
+            if self.contains_id(*id) {
+                return Err(WaveformError::DuplicateId { id: *id });
+            }
+        }
+        self.vector_signals.extend(other.vector_signals);
+        self.real_signals.extend(other.real_signals);
+        self.derived_signals.extend(other.derived_signals);
+        self.invalidate_derived();
+        Ok(())
+    }
+
+    // Returns true if the id is taken by a stored or derived signal, without
+    // materialising anything
+    fn contains_id(&self, id: usize) -> bool {
+        self.vector_signals.contains_key(&id)
+            || self.real_signals.contains_key(&id)
+            || self.derived_signals.contains_key(&id)
+    }
+
+    /// Appends another waveform whose timestamps all come after the timestamps
+    /// of this one, joining the histories of signals with matching ids. Changes
+    /// at the start of the other waveform that only repeat the last value of a
+    /// signal are dropped. If the timescales differ, both are rescaled to the
+    /// finer one first
+    pub fn append(&mut self, other: Self) -> WaveformResult<()> {
+        self.append_changes(other, true)
+    }
+
+    // Appends another waveform, keeping changes that repeat the last value of
+    // a signal unless asked to drop them
+    pub(crate) fn append_changes(
+        &mut self,
+        mut other: Self,
+        drop_repeated: bool,
+    ) -> WaveformResult<()> {
+        let timescale = get_finest_timescale([self.timescale, other.timescale].into_iter());
+        let mut timestamps = None;
+        if let Some(timescale) = timescale {
+            if other.timescale.is_some() {
+                other.rescale(timescale)?;
+            }
+            timestamps = self.get_rescaled_timestamps(timescale)?;
+        }
+        let last = timestamps.as_ref().unwrap_or(&self.timestamps).last();
+        if let (Some(last), Some(first)) = (last, other.timestamps.first()) {
+            if first <= last {
+                return Err(WaveformError::OverlappingTimestamps { timestamp: *first });
+            }
+        }
+        // Check every signal before modifying anything
+        for (id, signal) in &other.vector_signals {
+            if self.real_signals.contains_key(id) {
+                return Err(WaveformError::MismatchedSignalType { id: *id });
+            }
+            if let Some(existing) = self.vector_signals.get(id) {
+                if existing.get_width() != signal.get_width() {
+                    return Err(WaveformError::InvalidWidth {
+                        id: *id,
+                        expected: existing.get_width(),
+                        actual: signal.get_width(),
+                    });
+                }
+            }
+        }
+        for id in other.real_signals.keys() {
+            if self.vector_signals.contains_key(id) {
+                return Err(WaveformError::MismatchedSignalType { id: *id });
+            }
+        }
+        // Derived signals can only be joined with derived signals
+        for id in other.vector_signals.keys().chain(other.real_signals.keys()) {
+            if self.derived_signals.contains_key(id) {
+                return Err(WaveformError::MismatchedSignalType { id: *id });
+            }
+        }
+        for id in other.derived_signals.keys() {
+            if self.contains_id(*id) {
+                return Err(WaveformError::MismatchedSignalType { id: *id });
+            }
+        }
+        if let Some(timestamps) = timestamps {
+            self.timestamps = timestamps;
+        }
+        self.timescale = timescale;
+        self.hierarchy.merge(&other.hierarchy);
+        let timestamp_offset = self.timestamps.len();
+        self.timestamps.extend(other.timestamps);
+        for (id, signal) in other.vector_signals {
+            self.vector_signals
+                .entry(id)
+                .or_insert_with(|| WaveformSignalVector::new(signal.get_width()))
+                .append_changes(signal, timestamp_offset, drop_repeated);
+        }
+        for (id, signal) in other.real_signals {
+            self.real_signals.entry(id).or_default().append_changes(
+                signal,
+                timestamp_offset,
+                drop_repeated,
+            );
+        }
+        for (id, derived) in other.derived_signals {
+            self.derived_signals.entry(id).or_insert(derived);
+        }
+        self.invalidate_derived();
+        self.truncate_bounded();
+        Ok(())
+    }
+
     pub fn initialize_vector(&mut self, id: usize, width: usize) {
         self.vector_signals
             .insert(id, WaveformSignalVector::new(width));
+        self.invalidate_derived();
     }
 
     pub fn initialize_real(&mut self, id: usize) {
         self.real_signals.insert(id, WaveformSignalReal::new());
+        self.invalidate_derived();
     }
 
+    /// Returns a stored or derived vector signal, where a derived signal is
+    /// materialised on its first access
     pub fn get_vector_signal(&self, id: usize) -> Option<&WaveformSignalVector> {
-        self.vector_signals.get(&id)
+        match self.vector_signals.get(&id) {
+            Some(signal) => Some(signal),
+            None => self.derived_signals.get(&id)?.get_signal(self),
+        }
     }
 
     pub fn get_real_signal(&self, id: usize) -> Option<&WaveformSignalReal> {
         self.real_signals.get(&id)
     }
 
+    /// Returns a stored or derived signal, see `get_vector_signal`
     pub fn get_signal(&self, id: usize) -> Option<WaveformSignalResult<'_>> {
+        if let Some(signal) = self.real_signals.get(&id) {
+            Some(WaveformSignalResult::Real(signal))
+        } else {
+            self.get_vector_signal(id).map(WaveformSignalResult::Vector)
+        }
+    }
+
+    // Returns a signal only if it is stored, for anything that writes out or
+    // moves the signals of the waveform
+    pub(crate) fn get_stored_signal(&self, id: usize) -> Option<WaveformSignalResult<'_>> {
         if let Some(signal) = self.vector_signals.get(&id) {
             Some(WaveformSignalResult::Vector(signal))
         } else {
@@ -177,9 +568,47 @@ impl Waveform {
             Ordering::Greater => self.timestamps.push(timestamp),
             Ordering::Equal => {}
         }
+        self.truncate_bounded();
         Ok(())
     }
 
+    fn truncate_bounded(&mut self) {
+        if let Some(max_timestamps) = self.max_timestamps {
+            if self.timestamps.len() > max_timestamps * 2 {
+                self.truncate_timestamp_index(self.timestamps.len() - max_timestamps);
+            }
+        }
+    }
+
+    /// Drops all timestamps before the given timestamp, always keeping at least
+    /// the last timestamp. Signal values in effect at the new first timestamp
+    /// are kept and reported as changing at that first timestamp
+    pub fn truncate_before(&mut self, timestamp: u64) {
+        if self.timestamps.is_empty() {
+            return;
+        }
+        let timestamp_index = self
+            .search_timestamp(timestamp, WaveformSearchMode::After)
+            .unwrap_or(self.timestamps.len() - 1);
+        self.truncate_timestamp_index(timestamp_index);
+    }
+
+    fn truncate_timestamp_index(&mut self, timestamp_index: usize) {
+        if timestamp_index == 0 {
+            return;
+        }
+        self.timestamps.drain(..timestamp_index);
+        for signal in self.vector_signals.values_mut() {
+            signal.truncate_before(timestamp_index);
+        }
+        for signal in self.real_signals.values_mut() {
+            signal.truncate_before(timestamp_index);
+        }
+        self.invalidate_derived();
+    }
+
+    /// Sets the value of a signal at the last timestamp, replacing any value
+    /// already set at that timestamp
     pub fn update_vector(&mut self, id: usize, value: BitVector) -> WaveformResult<()> {
         let signal = if let Some(signal) = self.vector_signals.get_mut(&id) {
             signal
@@ -194,9 +623,12 @@ impl Waveform {
             });
         }
         signal.update(self.timestamps.len() - 1, value);
+        self.invalidate_derived();
         Ok(())
     }
 
+    /// Sets the value of a signal at the last timestamp, replacing any value
+    /// already set at that timestamp
     pub fn update_real(&mut self, id: usize, value: f64) -> WaveformResult<()> {
         let signal = if let Some(signal) = self.real_signals.get_mut(&id) {
             signal
@@ -204,6 +636,7 @@ impl Waveform {
             return Err(WaveformError::InvalidId { id });
         };
         signal.update(self.timestamps.len() - 1, value);
+        self.invalidate_derived();
         Ok(())
     }
 
@@ -272,55 +705,16 @@ impl Waveform {
         }
     }
 
-    /// Binary search for the index of the requested timestamp, if the exact
-    /// timestamp exists. Otherwise, the search mode is used to determine where
-    /// else to look to look for a timestamp, either closest of any timestamp,
-    /// closest timestamp before, or closest timestamp after. If a timestamp
-    /// is found, this function returns a tuple (timestamp, timestamp index)
+    /// See `WaveformQuery::search_timestamp`
     pub fn search_timestamp(
         &self,
         timestamp: u64,
         search_mode: WaveformSearchMode,
     ) -> Option<usize> {
-        // https://stackoverflow.com/questions/30245166/find-the-nearest-closest-value-in-a-sorted-list
-        let (mut start, mut end) = (0, self.timestamps.len() - 1);
-        // If the search timestamp is outside of the range of timestamps
-        if timestamp < self.timestamps[start] {
-            return match search_mode {
-                WaveformSearchMode::Exact | WaveformSearchMode::Before => None,
-                WaveformSearchMode::After | WaveformSearchMode::Closest => Some(start),
-            };
-        } else if self.timestamps[end] < timestamp {
-            return match search_mode {
-                WaveformSearchMode::Exact | WaveformSearchMode::After => None,
-                WaveformSearchMode::Before | WaveformSearchMode::Closest => Some(end),
-            };
-        }
-        // Iterate through until start == end + 1
-        while start <= end {
-            let mid = (start + end) / 2;
-            let mid_value = self.timestamps[mid];
-            match timestamp.cmp(&mid_value) {
-                Ordering::Less => end = mid - 1,
-                Ordering::Greater => start = mid + 1,
-                Ordering::Equal => return Some(mid),
-            }
-        }
-        // Select result based on search mode
-        match search_mode {
-            WaveformSearchMode::Exact => None,
-            WaveformSearchMode::Before => Some(end),
-            WaveformSearchMode::After => Some(start),
-            WaveformSearchMode::Closest => {
-                if (self.timestamps[start] - timestamp) < (timestamp - self.timestamps[end]) {
-                    Some(start)
-                } else {
-                    Some(end)
-                }
-            }
-        }
+        WaveformQuery::search_timestamp(self, timestamp, search_mode)
     }
 
+    /// See `WaveformQuery::search_value_bit_index`
     pub fn search_value_bit_index(
         &self,
         idcode: usize,
@@ -328,35 +722,74 @@ impl Waveform {
         search_mode: WaveformSearchMode,
         bit_index: Option<usize>,
     ) -> Option<WaveformValueResult> {
-        if let Some(signal) = self.vector_signals.get(&idcode) {
-            let Some(index) = signal.get_history().search_timestamp_index(timestamp_index, search_mode) else {
-                return None
-            };
-            let bv = signal.get_bitvector(index.get_value_index());
-            let bv = if let Some(index) = bit_index {
-                BitVector::from(bv.get_bit(index))
-            } else {
-                bv
-            };
-            Some(WaveformValueResult::Vector(bv, index.get_timestamp_index()))
-        } else if let Some(signal) = self.real_signals.get(&idcode) {
-            let Some(index) = signal.get_history().search_timestamp_index(timestamp_index, search_mode) else {
-                return None
-            };
-            let r = signal.get_real(index.get_value_index());
-            Some(WaveformValueResult::Real(r, index.get_timestamp_index()))
-        } else {
-            None
-        }
+        WaveformQuery::search_value_bit_index(self, idcode, timestamp_index, search_mode, bit_index)
     }
 
+    /// See `WaveformQuery::search_value`
     pub fn search_value(
         &self,
         idcode: usize,
         timestamp_index: usize,
         search_mode: WaveformSearchMode,
     ) -> Option<WaveformValueResult> {
-        self.search_value_bit_index(idcode, timestamp_index, search_mode, None)
+        WaveformQuery::search_value(self, idcode, timestamp_index, search_mode)
+    }
+}
+
+// Binary search shared by every waveform holding sorted timestamps
+pub(crate) fn search_timestamps(
+    timestamps: &[u64],
+    timestamp: u64,
+    search_mode: WaveformSearchMode,
+) -> Option<usize> {
+    // https://stackoverflow.com/questions/30245166/find-the-nearest-closest-value-in-a-sorted-list
+    let (mut start, mut end) = (0, timestamps.len() - 1);
+    // If the search timestamp is outside of the range of timestamps
+    if timestamp < timestamps[start] {
+        return match search_mode {
+            WaveformSearchMode::Exact | WaveformSearchMode::Before => None,
+            WaveformSearchMode::After | WaveformSearchMode::Closest => Some(start),
+        };
+    } else if timestamps[end] < timestamp {
+        return match search_mode {
+            WaveformSearchMode::Exact | WaveformSearchMode::After => None,
+            WaveformSearchMode::Before | WaveformSearchMode::Closest => Some(end),
+        };
+    }
+    // Iterate through until start == end + 1
+    while start <= end {
+        let mid = (start + end) / 2;
+        let mid_value = timestamps[mid];
+        match timestamp.cmp(&mid_value) {
+            Ordering::Less => end = mid - 1,
+            Ordering::Greater => start = mid + 1,
+            Ordering::Equal => return Some(mid),
+        }
+    }
+    // Select result based on search mode
+    match search_mode {
+        WaveformSearchMode::Exact => None,
+        WaveformSearchMode::Before => Some(end),
+        WaveformSearchMode::After => Some(start),
+        WaveformSearchMode::Closest => {
+            if (timestamps[start] - timestamp) < (timestamp - timestamps[end]) {
+                Some(start)
+            } else {
+                Some(end)
+            }
+        }
+    }
+}
+
+impl WaveformQuery for Waveform {
+    type Bytes = Vec<u8>;
+
+    fn get_timestamps(&self) -> &[u64] {
+        &self.timestamps
+    }
+
+    fn get_signal(&self, id: usize) -> Option<WaveformSignalResult<'_>> {
+        Waveform::get_signal(self, id)
     }
 }
 
@@ -0,0 +1,250 @@
+// Live waveforms let a single writer keep appending to a waveform, such as
+// from a running simulation, while any number of readers query it. Histories
+// and values are only ever appended to, so everything before the committed
+// timestamp count stays the same while the writer works past it. Readers take
+// a snapshot of the committed count and only hold the lock for each query,
+// which means the writer is never stopped for longer than one query.
+//
+// The writer cannot change a timestamp once it has been committed, so it has
+// to insert a new timestamp after committing before updating any signal. Only
+// appending operations are exposed, where anything that would move or drop
+// timestamps, such as truncating or rescaling, is left out.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use crate::bitvector::BitVector;
+use crate::errors::*;
+use crate::hierarchy::WaveformHierarchy;
+use crate::query::WaveformQuery;
+use crate::{Waveform, WaveformSearchMode, WaveformSignalResult, WaveformValueResult};
+
+struct WaveformLiveShared {
+    waveform: RwLock<Waveform>,
+    committed: AtomicUsize,
+}
+
+impl WaveformLiveShared {
+    fn read(&self) -> RwLockReadGuard<'_, Waveform> {
+        // The writer never leaves the waveform partially updated
+        self.waveform
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+pub struct WaveformLiveWriter {
+    shared: Arc<WaveformLiveShared>,
+}
+
+impl WaveformLiveWriter {
+    /// Starts appending to a waveform, where every timestamp it already has is
+    /// committed. Bounded waveforms cannot be used since truncating them would
+    /// move timestamp indices out from under readers
+    pub fn new(waveform: Waveform) -> Self {
+        assert!(
+            waveform.get_max_timestamps().is_none(),
+            "Live waveforms cannot be bounded"
+        );
+        let committed = waveform.timestamps_count();
+        Self {
+            shared: Arc::new(WaveformLiveShared {
+                waveform: RwLock::new(waveform),
+                committed: AtomicUsize::new(committed),
+            }),
+        }
+    }
+
+    /// Returns a new reader of this waveform, which can be sent to another
+    /// thread
+    pub fn get_reader(&self) -> WaveformLiveReader {
+        WaveformLiveReader {
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn write<T>(&mut self, f: impl FnOnce(&mut Waveform) -> T) -> T {
+        let mut waveform = self
+            .shared
+            .waveform
+            .write()
+            .unwrap_or_else(|error| error.into_inner());
+        f(&mut waveform)
+    }
+
+    /// Adds a new vector signal, which fails if the id is already used since
+    /// replacing a signal would change its committed values
+    pub fn initialize_vector(&mut self, id: usize, width: usize) -> WaveformResult<()> {
+        self.write(|waveform| {
+            if waveform.get_signal(id).is_some() {
+                return Err(WaveformError::DuplicateId { id });
+            }
+            waveform.initialize_vector(id, width);
+            Ok(())
+        })
+    }
+
+    /// Adds a new real signal, see `initialize_vector`
+    pub fn initialize_real(&mut self, id: usize) -> WaveformResult<()> {
+        self.write(|waveform| {
+            if waveform.get_signal(id).is_some() {
+                return Err(WaveformError::DuplicateId { id });
+            }
+            waveform.initialize_real(id);
+            Ok(())
+        })
+    }
+
+    /// Extends the hierarchy under the lock, such as adding the scopes and
+    /// variables of new signals
+    pub fn write_hierarchy<T>(&mut self, f: impl FnOnce(&mut WaveformHierarchy) -> T) -> T {
+        self.write(|waveform| f(waveform.get_hierarchy_mut()))
+    }
+
+    pub fn insert_timestamp(&mut self, timestamp: u64) -> WaveformResult<()> {
+        let committed = self.get_committed();
+        self.write(|waveform| {
+            // Inserting the last committed timestamp again would reopen it
+            if waveform.timestamps_count() == committed
+                && waveform.get_timestamps().last() == Some(&timestamp)
+            {
+                return Err(WaveformError::CommittedTimestamp { timestamp });
+            }
+            waveform.insert_timestamp(timestamp)
+        })
+    }
+
+    pub fn update_vector(&mut self, id: usize, value: BitVector) -> WaveformResult<()> {
+        let committed = self.get_committed();
+        self.write(|waveform| {
+            check_uncommitted(waveform, committed)?;
+            waveform.update_vector(id, value)
+        })
+    }
+
+    pub fn update_real(&mut self, id: usize, value: f64) -> WaveformResult<()> {
+        let committed = self.get_committed();
+        self.write(|waveform| {
+            check_uncommitted(waveform, committed)?;
+            waveform.update_real(id, value)
+        })
+    }
+
+    /// Commits every timestamp inserted so far, making them and their values
+    /// visible to new snapshots
+    pub fn commit(&mut self) {
+        let count = self.shared.read().timestamps_count();
+        self.shared.committed.store(count, Ordering::Release);
+    }
+
+    /// Returns how many timestamps have been committed
+    pub fn get_committed(&self) -> usize {
+        self.shared.committed.load(Ordering::Acquire)
+    }
+}
+
+fn check_uncommitted(waveform: &Waveform, committed: usize) -> WaveformResult<()> {
+    if waveform.timestamps_count() <= committed {
+        return Err(WaveformError::CommittedTimestamp {
+            timestamp: waveform.get_timestamps().last().cloned().unwrap_or(0),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct WaveformLiveReader {
+    shared: Arc<WaveformLiveShared>,
+}
+
+impl WaveformLiveReader {
+    /// Takes a snapshot of every timestamp committed so far, which stays the
+    /// same no matter how much the writer appends afterwards
+    pub fn snapshot(&self) -> WaveformSnapshot {
+        WaveformSnapshot {
+            shared: self.shared.clone(),
+            committed: self.shared.committed.load(Ordering::Acquire),
+        }
+    }
+}
+
+pub struct WaveformSnapshot {
+    shared: Arc<WaveformLiveShared>,
+    committed: usize,
+}
+
+impl WaveformSnapshot {
+    /// Returns the number of timestamps in this snapshot
+    pub fn timestamps_count(&self) -> usize {
+        self.committed
+    }
+
+    pub fn get_timestamp(&self, timestamp_index: usize) -> Option<u64> {
+        if timestamp_index >= self.committed {
+            return None;
+        }
+        Some(self.shared.read().get_timestamps()[timestamp_index])
+    }
+
+    /// Runs queries against the snapshot while holding the lock, where the
+    /// lock should not be held for long
+    pub fn read<T>(&self, f: impl FnOnce(&WaveformSnapshotView<'_>) -> T) -> T {
+        f(&WaveformSnapshotView {
+            waveform: &self.shared.read(),
+            committed: self.committed,
+        })
+    }
+
+    pub fn search_timestamp(
+        &self,
+        timestamp: u64,
+        search_mode: WaveformSearchMode,
+    ) -> Option<usize> {
+        if self.committed == 0 {
+            return None;
+        }
+        self.read(|view| view.search_timestamp(timestamp, search_mode))
+    }
+
+    /// Searches for a value as `Waveform::search_value_bit_index` does, as if
+    /// the waveform ended at the last timestamp of the snapshot
+    pub fn search_value_bit_index(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        self.read(|view| {
+            view.search_value_bit_index(idcode, timestamp_index, search_mode, bit_index)
+        })
+    }
+
+    pub fn search_value(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+    ) -> Option<WaveformValueResult> {
+        self.search_value_bit_index(idcode, timestamp_index, search_mode, None)
+    }
+}
+
+/// The waveform of a snapshot while its lock is held, where every query stops
+/// at the last timestamp of the snapshot
+pub struct WaveformSnapshotView<'a> {
+    waveform: &'a Waveform,
+    committed: usize,
+}
+
+impl WaveformQuery for WaveformSnapshotView<'_> {
+    type Bytes = Vec<u8>;
+
+    fn get_timestamps(&self) -> &[u64] {
+        &self.waveform.get_timestamps()[..self.committed]
+    }
+
+    fn get_signal(&self, id: usize) -> Option<WaveformSignalResult<'_>> {
+        self.waveform.get_signal(id)
+    }
+}
@@ -0,0 +1,568 @@
+// Native binary format that saves a waveform close to its in-memory layout so
+// loading it is little more than copying bytes. All integers are little-endian
+// and every section is followed by a CRC-32 of its bytes.
+//
+//    Header:     magic, version, flags, timestamp count, signal count, maximum
+//                timestamps (or all ones if unbounded), hierarchy length and
+//                the timescale as its exponent plus 16 (or zero if not set),
+//                which also aligns the timestamps to eight bytes
+//    Timestamps: every timestamp as a u64
+//    Hierarchy:  every scope and then every variable in index order, so
+//                indices are the same once loaded
+//    Signals:    a fixed-size table entry for every signal in id order, giving
+//                its width (zero for reals), value count, the file offset of
+//                its data and the length of its history blocks and values
+//    Data:       the history blocks and then the packed values of each signal,
+//                each with their own checksum in the signal table
+//
+// Histories are validated as they are loaded since checksums only catch
+// corruption, not files that were written wrong in the first place.
+//
+// Saved waveforms can also be memory-mapped with the "mmap" feature, where
+// the history blocks and values of each signal are used in place and only
+// validated once the signal is first accessed.
+
+#[cfg(feature = "mmap")]
+pub mod mapped;
+
+use std::io::{Read, Write};
+
+use crate::crc::crc32;
+use crate::errors::*;
+use crate::hierarchy::*;
+use crate::history::WaveformHistory;
+use crate::real::WaveformSignalReal;
+use crate::timescale::WaveformTimescale;
+use crate::vector::WaveformSignalVector;
+use crate::{Waveform, WaveformSignalResult};
+
+pub const NATIVE_MAGIC: &[u8; 8] = b"MAKAIWDB";
+pub const NATIVE_VERSION: u32 = 1;
+
+pub const HEADER_SIZE: usize = 52;
+pub const SIGNAL_ENTRY_SIZE: usize = 52;
+
+fn native_error(error: WaveformNativeError) -> WaveformError {
+    WaveformError::InvalidNative { error }
+}
+
+// A signal table entry, describing where the data of a signal is in the file
+struct WaveformNativeSignal {
+    id: usize,
+    // Zero for real signals
+    width: usize,
+    len: usize,
+    offset: u64,
+    blocks_length: u64,
+    vectors_length: u64,
+    checksum: u32,
+}
+
+impl WaveformNativeSignal {
+    fn is_real(&self) -> bool {
+        self.width == 0
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&(self.id as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.width as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.len as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.offset.to_le_bytes());
+        bytes.extend_from_slice(&self.blocks_length.to_le_bytes());
+        bytes.extend_from_slice(&self.vectors_length.to_le_bytes());
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        let mut decoder = Decoder::new(bytes, 0);
+        Self {
+            id: decoder.u64().unwrap() as usize,
+            width: decoder.u64().unwrap() as usize,
+            len: decoder.u64().unwrap() as usize,
+            offset: decoder.u64().unwrap(),
+            blocks_length: decoder.u64().unwrap(),
+            vectors_length: decoder.u64().unwrap(),
+            checksum: decoder.u32().unwrap(),
+        }
+    }
+
+    // Creates the signal from its checksummed history blocks and values
+    fn load<B: AsRef<[u8]>>(
+        &self,
+        blocks: B,
+        vectors: B,
+        timestamps_count: usize,
+    ) -> WaveformResult<LoadedSignal<B>> {
+        let invalid = || native_error(WaveformNativeError::InvalidSignal { id: self.id });
+        if crc32(crc32(0, blocks.as_ref()), vectors.as_ref()) != self.checksum {
+            return Err(native_error(WaveformNativeError::InvalidChecksum {
+                offset: self.offset,
+            }));
+        }
+        let history = WaveformHistory::from_blocks(blocks)?;
+        // Every change must refer to a stored value and an existing timestamp
+        if history.get_block_count() > 0 {
+            let last = history
+                .get_block(history.get_block_count() - 1)
+                .into_iter()
+                .last()
+                .unwrap();
+            if last.get_value_index() >= self.len || last.get_timestamp_index() >= timestamps_count
+            {
+                return Err(invalid());
+            }
+        }
+        if self.is_real() {
+            WaveformSignalReal::from_parts(history, vectors, self.len)
+                .map(LoadedSignal::Real)
+                .ok_or_else(invalid)
+        } else {
+            WaveformSignalVector::from_parts(self.width, history, vectors, self.len)
+                .map(LoadedSignal::Vector)
+                .ok_or_else(invalid)
+        }
+    }
+}
+
+enum LoadedSignal<B = Vec<u8>> {
+    Vector(WaveformSignalVector<B>),
+    Real(WaveformSignalReal<B>),
+}
+
+// Everything in a native file other than the timestamps and signal data
+struct WaveformNativeHeader {
+    timestamps_count: usize,
+    max_timestamps: Option<usize>,
+    timescale: Option<WaveformTimescale>,
+    hierarchy: WaveformHierarchy,
+    signals: Vec<WaveformNativeSignal>,
+}
+
+impl WaveformNativeHeader {
+    fn get_timestamps_offset(&self) -> u64 {
+        HEADER_SIZE as u64 + 4
+    }
+
+    // Checks the timestamps section against its trailing checksum and that
+    // the timestamps are strictly increasing
+    fn validate_timestamps(&self, bytes: &[u8]) -> WaveformResult<()> {
+        let (bytes, checksum) = bytes.split_at(bytes.len() - 4);
+        check_checksum(bytes, checksum, self.get_timestamps_offset())?;
+        let mut last = None;
+        for timestamp in decode_timestamps(bytes) {
+            if last.is_some_and(|last| last >= timestamp) {
+                return Err(WaveformError::DecreasingTimestamp { timestamp });
+            }
+            last = Some(timestamp);
+        }
+        Ok(())
+    }
+
+    // Reads everything before the timestamps, returning the header along with
+    // the signal count and hierarchy length needed to read the rest
+    fn read_start<R: Read>(reader: &mut R) -> WaveformResult<(Self, u64, usize)> {
+        let mut bytes = vec![0; HEADER_SIZE + 4];
+        reader.read_exact(&mut bytes)?;
+        if &bytes[..8] != NATIVE_MAGIC {
+            return Err(native_error(WaveformNativeError::InvalidMagic));
+        }
+        let (header, checksum) = bytes.split_at(HEADER_SIZE);
+        check_checksum(header, checksum, 0)?;
+        let mut decoder = Decoder::new(header, 8);
+        let version = decoder.u32().unwrap();
+        if version != NATIVE_VERSION {
+            return Err(native_error(WaveformNativeError::UnsupportedVersion {
+                version,
+            }));
+        }
+        let _flags = decoder.u32().unwrap();
+        let timestamps_count = decoder.u64().unwrap();
+        let signals_count = decoder.u64().unwrap();
+        let max_timestamps = match decoder.u64().unwrap() {
+            u64::MAX => None,
+            // Bounded waveforms must keep at least one timestamp
+            0 => {
+                return Err(native_error(WaveformNativeError::InvalidOffset {
+                    offset: decoder.offset as u64 - 8,
+                }))
+            }
+            max_timestamps => Some(max_timestamps as usize),
+        };
+        let hierarchy_length = decoder.u64().unwrap();
+        let timescale = match decoder.u32().unwrap() {
+            0 => None,
+            timescale => Some(
+                i8::try_from(timescale as i64 - 16)
+                    .ok()
+                    .and_then(WaveformTimescale::from_exponent)
+                    .ok_or_else(|| {
+                        native_error(WaveformNativeError::InvalidOffset {
+                            offset: decoder.offset as u64 - 4,
+                        })
+                    })?,
+            ),
+        };
+        let header = Self {
+            timestamps_count: timestamps_count as usize,
+            max_timestamps,
+            timescale,
+            hierarchy: WaveformHierarchy::new(),
+            signals: Vec::new(),
+        };
+        Ok((header, signals_count, hierarchy_length as usize))
+    }
+
+    // Reads the sections after the timestamps, up to the start of the signal
+    // data
+    fn read_end<R: Read>(
+        &mut self,
+        reader: &mut R,
+        signals_count: u64,
+        hierarchy_length: usize,
+    ) -> WaveformResult<()> {
+        let mut offset = self.get_timestamps_offset() + self.timestamps_count as u64 * 8 + 4;
+        let bytes = read_section(reader, hierarchy_length, offset)?;
+        self.hierarchy = decode_hierarchy(&bytes, offset)?;
+        offset += hierarchy_length as u64 + 4;
+
+        let table_length = (signals_count as usize)
+            .checked_mul(SIGNAL_ENTRY_SIZE)
+            .ok_or_else(|| native_error(WaveformNativeError::InvalidOffset { offset }))?;
+        let bytes = read_section(reader, table_length, offset)?;
+        offset += table_length as u64 + 4;
+        // Signal data must follow on directly in table order
+        for entry in bytes.chunks_exact(SIGNAL_ENTRY_SIZE) {
+            let signal = WaveformNativeSignal::decode(entry);
+            let end = signal
+                .blocks_length
+                .checked_add(signal.vectors_length)
+                .and_then(|length| offset.checked_add(length));
+            let Some(end) = end.filter(|_| signal.offset == offset) else {
+                return Err(native_error(WaveformNativeError::InvalidOffset {
+                    offset: signal.offset,
+                }));
+            };
+            offset = end;
+            self.signals.push(signal);
+        }
+        Ok(())
+    }
+}
+
+fn decode_timestamps(bytes: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset }
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        self.u64().map(|value| value as i64)
+    }
+
+    // Indices are written one higher so zero can mean none
+    fn index(&mut self) -> Option<Option<usize>> {
+        self.u64()
+            .map(|index| index.checked_sub(1).map(|index| index as usize))
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let length = self.u64()? as usize;
+        std::str::from_utf8(self.take(length)?).ok()
+    }
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn write_index(bytes: &mut Vec<u8>, index: Option<usize>) {
+    let index = index.map(|index| index as u64 + 1).unwrap_or(0);
+    bytes.extend_from_slice(&index.to_le_bytes());
+}
+
+fn check_checksum(bytes: &[u8], checksum: &[u8], offset: u64) -> WaveformResult<()> {
+    if crc32(0, bytes).to_le_bytes() != checksum {
+        return Err(native_error(WaveformNativeError::InvalidChecksum {
+            offset,
+        }));
+    }
+    Ok(())
+}
+
+// Reads a section of the given length and checks its trailing checksum
+fn read_section<R: Read>(reader: &mut R, length: usize, offset: u64) -> WaveformResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64 + 4).read_to_end(&mut bytes)?;
+    if bytes.len() != length + 4 {
+        return Err(WaveformError::Io {
+            error: std::io::ErrorKind::UnexpectedEof.into(),
+        });
+    }
+    let checksum = bytes.split_off(length);
+    check_checksum(&bytes, &checksum, offset)?;
+    Ok(bytes)
+}
+
+fn encode_hierarchy(hierarchy: &WaveformHierarchy) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(hierarchy.get_scopes().len() as u64).to_le_bytes());
+    for scope in hierarchy.get_scopes() {
+        write_string(&mut bytes, scope.get_name());
+        write_string(&mut bytes, scope.get_scope_type().to_str());
+        write_index(&mut bytes, scope.get_parent());
+    }
+    bytes.extend_from_slice(&(hierarchy.get_variables().len() as u64).to_le_bytes());
+    for variable in hierarchy.get_variables() {
+        write_string(&mut bytes, variable.get_name());
+        bytes.extend_from_slice(&(variable.get_id() as u64).to_le_bytes());
+        write_string(&mut bytes, variable.get_variable_type().to_str());
+        bytes.extend_from_slice(&(variable.get_width() as u64).to_le_bytes());
+        match variable.get_range() {
+            Some(range) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(range.msb as i64).to_le_bytes());
+                bytes.extend_from_slice(&(range.lsb as i64).to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        write_string(&mut bytes, variable.get_direction().to_str());
+        write_index(&mut bytes, variable.get_scope());
+    }
+    bytes
+}
+
+fn decode_hierarchy(bytes: &[u8], offset: u64) -> WaveformResult<WaveformHierarchy> {
+    let mut decoder = Decoder::new(bytes, 0);
+    let mut hierarchy = WaveformHierarchy::new();
+    let invalid = |decoder: &Decoder| {
+        native_error(WaveformNativeError::InvalidHierarchy {
+            offset: offset + decoder.offset as u64,
+        })
+    };
+    // Parents always come before the scopes inside them
+    let scopes = decoder.u64().ok_or_else(|| invalid(&decoder))?;
+    for index in 0..scopes as usize {
+        let scope = (|| {
+            let name = decoder.string()?;
+            let scope_type = WaveformScopeType::from_str(decoder.string()?)?;
+            let parent = decoder.index()?;
+            if parent.is_some_and(|parent| parent >= index) {
+                return None;
+            }
+            Some((name, scope_type, parent))
+        })();
+        let Some((name, scope_type, parent)) = scope else {
+            return Err(invalid(&decoder));
+        };
+        hierarchy.open_scope(parent);
+        if hierarchy.push_scope(name, scope_type) != index {
+            return Err(invalid(&decoder));
+        }
+    }
+    let variables = decoder.u64().ok_or_else(|| invalid(&decoder))?;
+    for _ in 0..variables {
+        let variable = (|| {
+            let name = decoder.string()?;
+            let id = decoder.u64()? as usize;
+            let variable_type = WaveformVariableType::from_str(decoder.string()?)?;
+            let width = decoder.u64()? as usize;
+            let range = match decoder.u8()? {
+                0 => None,
+                _ => Some(WaveformBitRange {
+                    msb: decoder.i64()? as isize,
+                    lsb: decoder.i64()? as isize,
+                }),
+            };
+            let direction = WaveformVariableDirection::from_str(decoder.string()?)?;
+            let scope = decoder.index()?;
+            if scope.is_some_and(|scope| scope >= scopes as usize) {
+                return None;
+            }
+            let variable = WaveformVariable::new(name, id, variable_type, width, range, direction);
+            Some((variable, scope))
+        })();
+        let Some((variable, scope)) = variable else {
+            return Err(invalid(&decoder));
+        };
+        hierarchy.open_scope(scope);
+        hierarchy.add_variable(variable);
+    }
+    hierarchy.open_scope(None);
+    if decoder.offset != bytes.len() {
+        return Err(invalid(&decoder));
+    }
+    Ok(hierarchy)
+}
+
+impl Waveform {
+    /// Saves the waveform in the native format, which should be given a
+    /// buffered writer. Only stored signals are saved, leaving out derived
+    /// signals
+    pub fn save<W: Write>(&self, writer: &mut W) -> WaveformResult<()> {
+        let mut ids = self
+            .vector_signals
+            .keys()
+            .chain(self.real_signals.keys())
+            .cloned()
+            .collect::<Vec<usize>>();
+        ids.sort_unstable();
+        let hierarchy = encode_hierarchy(&self.hierarchy);
+
+        let mut header = Vec::with_capacity(HEADER_SIZE + 4);
+        header.extend_from_slice(NATIVE_MAGIC);
+        header.extend_from_slice(&NATIVE_VERSION.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&(self.timestamps.len() as u64).to_le_bytes());
+        header.extend_from_slice(&(ids.len() as u64).to_le_bytes());
+        let max_timestamps = self.max_timestamps.map(|max| max as u64);
+        header.extend_from_slice(&max_timestamps.unwrap_or(u64::MAX).to_le_bytes());
+        header.extend_from_slice(&(hierarchy.len() as u64).to_le_bytes());
+        let timescale = self
+            .timescale
+            .map(|timescale| (timescale.get_exponent() + 16) as u32);
+        header.extend_from_slice(&timescale.unwrap_or(0).to_le_bytes());
+        header.extend_from_slice(&crc32(0, &header).to_le_bytes());
+        writer.write_all(&header)?;
+
+        let mut crc = 0;
+        for chunk in self.timestamps.chunks(1 << 12) {
+            let bytes = chunk
+                .iter()
+                .flat_map(|timestamp| timestamp.to_le_bytes())
+                .collect::<Vec<u8>>();
+            crc = crc32(crc, &bytes);
+            writer.write_all(&bytes)?;
+        }
+        writer.write_all(&crc.to_le_bytes())?;
+        writer.write_all(&hierarchy)?;
+        writer.write_all(&crc32(0, &hierarchy).to_le_bytes())?;
+
+        // Signal data follows the table in the same order
+        let signal_data = |id: &usize| match self.get_stored_signal(*id).unwrap() {
+            WaveformSignalResult::Vector(signal) => (
+                signal.get_width(),
+                signal.len(),
+                signal.get_history().get_blocks(),
+                signal.get_vectors(),
+            ),
+            WaveformSignalResult::Real(signal) => (
+                0,
+                signal.len(),
+                signal.get_history().get_blocks(),
+                signal.get_vectors(),
+            ),
+        };
+        let mut offset = (HEADER_SIZE + 4 + self.timestamps.len() * 8 + 4) as u64
+            + (hierarchy.len() + 4) as u64
+            + (ids.len() * SIGNAL_ENTRY_SIZE + 4) as u64;
+        let mut table = Vec::with_capacity(ids.len() * SIGNAL_ENTRY_SIZE);
+        for id in &ids {
+            let (width, len, blocks, vectors) = signal_data(id);
+            let signal = WaveformNativeSignal {
+                id: *id,
+                width,
+                len,
+                offset,
+                blocks_length: blocks.len() as u64,
+                vectors_length: vectors.len() as u64,
+                checksum: crc32(crc32(0, blocks), vectors),
+            };
+            signal.encode(&mut table);
+            offset += (blocks.len() + vectors.len()) as u64;
+        }
+        writer.write_all(&table)?;
+        writer.write_all(&crc32(0, &table).to_le_bytes())?;
+        for id in &ids {
+            let (_, _, blocks, vectors) = signal_data(id);
+            writer.write_all(blocks)?;
+            writer.write_all(vectors)?;
+        }
+        Ok(())
+    }
+
+    /// Loads a waveform saved in the native format, which should be given a
+    /// buffered reader
+    pub fn load<R: Read>(mut reader: R) -> WaveformResult<Self> {
+        let (mut header, signals_count, hierarchy_length) =
+            WaveformNativeHeader::read_start(&mut reader)?;
+        let offset = header.get_timestamps_offset();
+        let length = header
+            .timestamps_count
+            .checked_mul(8)
+            .ok_or_else(|| native_error(WaveformNativeError::InvalidOffset { offset }))?;
+        let mut bytes = Vec::new();
+        (&mut reader)
+            .take(length as u64 + 4)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != length + 4 {
+            return Err(WaveformError::Io {
+                error: std::io::ErrorKind::UnexpectedEof.into(),
+            });
+        }
+        header.validate_timestamps(&bytes)?;
+        let timestamps = decode_timestamps(&bytes[..length]).collect();
+        header.read_end(&mut reader, signals_count, hierarchy_length)?;
+
+        let mut waveform = Self::new();
+        waveform.timestamps = timestamps;
+        waveform.max_timestamps = header.max_timestamps;
+        waveform.timescale = header.timescale;
+        for signal in &header.signals {
+            let length = signal.blocks_length + signal.vectors_length;
+            let mut blocks = Vec::new();
+            (&mut reader).take(length).read_to_end(&mut blocks)?;
+            if blocks.len() as u64 != length {
+                return Err(WaveformError::Io {
+                    error: std::io::ErrorKind::UnexpectedEof.into(),
+                });
+            }
+            if waveform.get_stored_signal(signal.id).is_some() {
+                return Err(WaveformError::DuplicateId { id: signal.id });
+            }
+            let vectors = blocks.split_off(signal.blocks_length as usize);
+            match signal.load(blocks, vectors, waveform.timestamps.len())? {
+                LoadedSignal::Vector(vector) => {
+                    waveform.vector_signals.insert(signal.id, vector);
+                }
+                LoadedSignal::Real(real) => {
+                    waveform.real_signals.insert(signal.id, real);
+                }
+            }
+        }
+        waveform.hierarchy = header.hierarchy;
+        Ok(waveform)
+    }
+}
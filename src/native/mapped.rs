@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+
+use memmap2::Mmap;
+
+use super::*;
+use crate::query::WaveformQuery;
+
+/// A range of bytes inside a memory-mapped file, which keeps the mapping alive
+/// for as long as any signal uses it
+#[derive(Clone)]
+pub struct WaveformMappedBytes {
+    mmap: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for WaveformMappedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap[self.range.clone()]
+    }
+}
+
+struct WaveformMappedSignal {
+    entry: WaveformNativeSignal,
+    loaded: OnceLock<LoadedSignal<WaveformMappedBytes>>,
+}
+
+pub struct WaveformMapped {
+    mmap: Arc<Mmap>,
+    timestamps_range: Range<usize>,
+    // Only used if the timestamps cannot be borrowed from the mapping as is
+    timestamps: Option<Vec<u64>>,
+    timescale: Option<WaveformTimescale>,
+    hierarchy: WaveformHierarchy,
+    signals: HashMap<usize, WaveformMappedSignal>,
+}
+
+impl WaveformMapped {
+    /// Maps a waveform saved in the native format, reading only the header,
+    /// timestamps, hierarchy and signal table. The file must not be modified
+    /// while it is mapped
+    pub fn open(file: &File) -> WaveformResult<Self> {
+        // SAFETY: The mapping is read-only and the caller keeps the file from
+        // being modified, as with any memory-mapped file
+        let mmap = unsafe { Mmap::map(file)? };
+        Self::from_mmap(Arc::new(mmap))
+    }
+
+    fn from_mmap(mmap: Arc<Mmap>) -> WaveformResult<Self> {
+        let mut reader = &mmap[..];
+        let (mut header, signals_count, hierarchy_length) =
+            WaveformNativeHeader::read_start(&mut reader)?;
+        let start = header.get_timestamps_offset() as usize;
+        let end = header
+            .timestamps_count
+            .checked_mul(8)
+            .and_then(|length| length.checked_add(start))
+            .filter(|end| end + 4 <= mmap.len())
+            .ok_or(WaveformError::Io {
+                error: std::io::ErrorKind::UnexpectedEof.into(),
+            })?;
+        header.validate_timestamps(&mmap[start..end + 4])?;
+        let mut reader = &mmap[end + 4..];
+        header.read_end(&mut reader, signals_count, hierarchy_length)?;
+        if let Some(signal) = header.signals.last() {
+            if signal.offset + signal.blocks_length + signal.vectors_length > mmap.len() as u64 {
+                return Err(WaveformError::Io {
+                    error: std::io::ErrorKind::UnexpectedEof.into(),
+                });
+            }
+        }
+
+        // Timestamps are borrowed in place when the host matches the file
+        let timestamps =
+            if cfg!(target_endian = "little") && mmap[start..].as_ptr().align_offset(8) == 0 {
+                None
+            } else {
+                Some(decode_timestamps(&mmap[start..end]).collect())
+            };
+        let mut signals = HashMap::new();
+        for entry in header.signals {
+            if signals.contains_key(&entry.id) {
+                return Err(WaveformError::DuplicateId { id: entry.id });
+            }
+            let signal = WaveformMappedSignal {
+                entry,
+                loaded: OnceLock::new(),
+            };
+            signals.insert(signal.entry.id, signal);
+        }
+        Ok(Self {
+            mmap,
+            timestamps_range: start..end,
+            timestamps,
+            timescale: header.timescale,
+            hierarchy: header.hierarchy,
+            signals,
+        })
+    }
+
+    pub fn get_timescale(&self) -> Option<WaveformTimescale> {
+        self.timescale
+    }
+
+    pub fn get_hierarchy(&self) -> &WaveformHierarchy {
+        &self.hierarchy
+    }
+
+    pub fn get_timestamps(&self) -> &[u64] {
+        match &self.timestamps {
+            Some(timestamps) => timestamps,
+            // SAFETY: The range was checked to be inside the mapping, aligned
+            // and in the byte order of the host when it was opened
+            None => unsafe {
+                std::slice::from_raw_parts(
+                    self.mmap[self.timestamps_range.clone()].as_ptr() as *const u64,
+                    self.timestamps_range.len() / 8,
+                )
+            },
+        }
+    }
+
+    pub fn timestamps_count(&self) -> usize {
+        self.timestamps_range.len() / 8
+    }
+
+    /// Returns the ids of every signal in the file
+    pub fn get_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.signals.keys().cloned()
+    }
+
+    /// Returns true if the signal has been accessed and paged in
+    pub fn is_loaded(&self, id: usize) -> bool {
+        self.signals
+            .get(&id)
+            .map(|signal| signal.loaded.get().is_some())
+            .unwrap_or(false)
+    }
+
+    /// Pages in a signal on its first access, validating its history and
+    /// values against the signal table. Returns None if there is no signal
+    /// with the given id
+    pub fn load_signal(
+        &self,
+        id: usize,
+    ) -> WaveformResult<Option<WaveformSignalResult<'_, WaveformMappedBytes>>> {
+        let Some(signal) = self.signals.get(&id) else {
+            return Ok(None);
+        };
+        if signal.loaded.get().is_none() {
+            let entry = &signal.entry;
+            let start = entry.offset as usize;
+            let middle = start + entry.blocks_length as usize;
+            let end = middle + entry.vectors_length as usize;
+            let bytes = |range| WaveformMappedBytes {
+                mmap: self.mmap.clone(),
+                range,
+            };
+            let loaded = entry.load(
+                bytes(start..middle),
+                bytes(middle..end),
+                self.timestamps_count(),
+            )?;
+            // Another thread may have loaded the same signal in the meantime
+            let _ = signal.loaded.set(loaded);
+        }
+        Ok(match signal.loaded.get().unwrap() {
+            LoadedSignal::Vector(signal) => Some(WaveformSignalResult::Vector(signal)),
+            LoadedSignal::Real(signal) => Some(WaveformSignalResult::Real(signal)),
+        })
+    }
+
+    /// Pages in a signal on its first access, returning None if there is no
+    /// signal with the given id or it is invalid, see `load_signal` for why
+    pub fn get_signal(&self, id: usize) -> Option<WaveformSignalResult<'_, WaveformMappedBytes>> {
+        self.load_signal(id).ok().flatten()
+    }
+
+    /// Pages in every signal, returning the first error found
+    pub fn validate(&self) -> WaveformResult<()> {
+        for id in self.signals.keys() {
+            self.load_signal(*id)?;
+        }
+        Ok(())
+    }
+}
+
+impl WaveformQuery for WaveformMapped {
+    type Bytes = WaveformMappedBytes;
+
+    fn get_timestamps(&self) -> &[u64] {
+        WaveformMapped::get_timestamps(self)
+    }
+
+    fn get_signal(&self, id: usize) -> Option<WaveformSignalResult<'_, WaveformMappedBytes>> {
+        WaveformMapped::get_signal(self, id)
+    }
+}
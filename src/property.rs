@@ -0,0 +1,324 @@
+// Checking SVA-like properties after the fact, such as
+// `disable iff (rst) $rose(req) |-> ##[1:3] ack ##1 $stable(data)`. Signals
+// are sampled at every edge of the property clock just before the edge, as an
+// SVA would see them, and an attempt of the property starts at every edge.
+//
+// A sequence is a list of conditions, each delayed by a range of clock edges
+// after the one before it, and is followed as every possible thread at once.
+// An attempt fails once every thread of the consequent has failed, and is
+// left out if it could still match after the last clock edge, as with a weak
+// property at the end of a simulation. Attempts only look as far ahead as the
+// longest delays of their sequences, so the clock edges are sampled as a
+// stream and only the edges the current attempt can reach are kept.
+
+use std::collections::VecDeque;
+
+use crate::sample::WaveformCycle;
+use crate::search::WaveformPredicate;
+use crate::trigger::WaveformCondition;
+use crate::WaveformValueResult;
+
+#[derive(Clone, Debug, PartialEq)]
+struct WaveformSequenceStep {
+    min: usize,
+    max: usize,
+    condition: WaveformCondition,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WaveformSequence {
+    steps: Vec<WaveformSequenceStep>,
+}
+
+// Result of following a sequence from a clock edge
+enum WaveformSequenceResult {
+    // Every clock edge where the sequence can end
+    Matched(Vec<usize>),
+    // The furthest clock edge checked before every thread failed
+    Failed(usize),
+    // Some thread ran past the last clock edge without any thread matching
+    Pending,
+}
+
+impl WaveformSequence {
+    /// Creates an empty sequence, which matches at the clock edge it starts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a condition that must hold between `min` and `max` clock edges
+    /// after the condition before it, as with SVA `##[min:max]`, where the
+    /// delay of the first condition counts from the start of the sequence
+    pub fn push(&mut self, min: usize, max: usize, condition: WaveformCondition) {
+        assert!(min <= max, "Sequence delay must not be an empty range");
+        self.steps.push(WaveformSequenceStep {
+            min,
+            max,
+            condition,
+        });
+    }
+
+    // Returns the most clock edges after its start the sequence can end at
+    fn get_length(&self) -> usize {
+        self.steps
+            .iter()
+            .fold(0, |length, step| length.saturating_add(step.max))
+    }
+
+    fn push_ids(&self, ids: &mut Vec<usize>) {
+        for step in &self.steps {
+            step.condition.push_ids(ids);
+        }
+    }
+
+    // Follows every thread of the sequence from a clock edge, given a function
+    // checking a condition at a clock edge
+    fn evaluate(
+        &self,
+        start: usize,
+        len: usize,
+        holds: &impl Fn(&WaveformCondition, usize) -> bool,
+    ) -> WaveformSequenceResult {
+        let mut threads = vec![start];
+        let mut last = start;
+        let mut pending = false;
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for thread in threads {
+                // Edges past the last one are cut at the first of them, so
+                // that unbounded delays such as `##[1:$]` end in time
+                let min = thread.saturating_add(step.min).min(len);
+                let max = thread.saturating_add(step.max).min(len);
+                for edge in min..=max {
+                    if edge >= len {
+                        pending = true;
+                        break;
+                    }
+                    last = last.max(edge);
+                    if holds(&step.condition, edge) {
+                        next.push(edge);
+                    }
+                }
+            }
+            next.sort_unstable();
+            next.dedup();
+            threads = next;
+            if threads.is_empty() {
+                break;
+            }
+        }
+        if !threads.is_empty() {
+            WaveformSequenceResult::Matched(threads)
+        } else if pending {
+            WaveformSequenceResult::Pending
+        } else {
+            WaveformSequenceResult::Failed(last)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaveformImplication {
+    /// The consequent starts at the clock edge the antecedent ends, as with
+    /// SVA `|->`
+    Overlapping,
+    /// The consequent starts at the clock edge after the antecedent ends, as
+    /// with SVA `|=>`
+    NonOverlapping,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveformProperty {
+    clock: usize,
+    edge: WaveformPredicate,
+    antecedent: Option<(WaveformSequence, WaveformImplication)>,
+    consequent: WaveformSequence,
+    disable: Option<WaveformCondition>,
+}
+
+impl WaveformProperty {
+    /// Creates a property where the sequence must match from every change of
+    /// a clock matching the edge predicate, such as `WaveformPredicate::Rising`
+    pub fn new(clock: usize, edge: WaveformPredicate, consequent: WaveformSequence) -> Self {
+        Self {
+            clock,
+            edge,
+            antecedent: None,
+            consequent,
+            disable: None,
+        }
+    }
+
+    /// Sets a sequence that must match before the consequent is checked, where
+    /// the consequent is checked after every clock edge the antecedent can end
+    pub fn set_antecedent(&mut self, antecedent: Option<(WaveformSequence, WaveformImplication)>) {
+        self.antecedent = antecedent;
+    }
+
+    /// Sets a condition that drops any attempt it holds during, as with SVA
+    /// `disable iff`, checked at every clock edge from the start of the
+    /// attempt up to the one where it fails
+    pub fn set_disable(&mut self, disable: Option<WaveformCondition>) {
+        self.disable = disable;
+    }
+
+    pub fn get_clock(&self) -> usize {
+        self.clock
+    }
+
+    pub fn get_edge(&self) -> &WaveformPredicate {
+        &self.edge
+    }
+
+    /// Returns the sorted ids of every signal referenced by the property
+    pub fn get_ids(&self) -> Vec<usize> {
+        let mut ids = Vec::new();
+        if let Some((antecedent, _)) = &self.antecedent {
+            antecedent.push_ids(&mut ids);
+        }
+        self.consequent.push_ids(&mut ids);
+        if let Some(disable) = &self.disable {
+            disable.push_ids(&mut ids);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    // Returns the most clock edges after its start an attempt can look at
+    fn get_span(&self) -> usize {
+        let antecedent = match &self.antecedent {
+            Some((antecedent, WaveformImplication::Overlapping)) => antecedent.get_length(),
+            Some((antecedent, WaveformImplication::NonOverlapping)) => {
+                antecedent.get_length().saturating_add(1)
+            }
+            None => 0,
+        };
+        antecedent.saturating_add(self.consequent.get_length())
+    }
+
+    // Checks the attempt starting at every clock edge, given the values of the
+    // sorted ids sampled at every clock edge. Only the clock edges from the
+    // one before an attempt up to the furthest it can look at are held at once
+    pub(crate) fn check(
+        &self,
+        ids: &[usize],
+        mut cycles: impl Iterator<Item = WaveformCycle>,
+    ) -> Vec<WaveformPropertyFailure> {
+        let span = self.get_span();
+        let initial = vec![None; ids.len()];
+        // Clock edges from the index of the front of the window onwards
+        let mut window = VecDeque::new();
+        let mut first = 0;
+        let mut done = false;
+        let mut failures = Vec::new();
+        for start in 0usize.. {
+            while !done && first + window.len() <= start.saturating_add(span) {
+                match cycles.next() {
+                    Some(cycle) => window.push_back(cycle),
+                    None => done = true,
+                }
+            }
+            if start >= first + window.len() {
+                break;
+            }
+            while first + 1 < start {
+                window.pop_front();
+                first += 1;
+            }
+            if let Some(failure) = self.check_attempt(ids, &initial, &window, first, start) {
+                failures.push(failure);
+            }
+        }
+        failures
+    }
+
+    // Checks the attempt starting at a clock edge, given a window of clock
+    // edges holding every edge the attempt can look at and the one before it
+    fn check_attempt(
+        &self,
+        ids: &[usize],
+        initial: &[Option<WaveformValueResult>],
+        window: &VecDeque<WaveformCycle>,
+        first: usize,
+        start: usize,
+    ) -> Option<WaveformPropertyFailure> {
+        let len = first + window.len();
+        let holds = |condition: &WaveformCondition, edge: usize| {
+            let previous = match edge {
+                0 => initial,
+                _ => window[edge - 1 - first].get_values(),
+            };
+            condition.evaluate(ids, previous, window[edge - first].get_values())
+        };
+        let consequent_starts = match &self.antecedent {
+            Some((antecedent, implication)) => {
+                let WaveformSequenceResult::Matched(ends) = antecedent.evaluate(start, len, &holds)
+                else {
+                    // The property holds vacuously
+                    return None;
+                };
+                match implication {
+                    WaveformImplication::Overlapping => ends,
+                    WaveformImplication::NonOverlapping => {
+                        ends.into_iter().map(|end| end + 1).collect()
+                    }
+                }
+            }
+            None => vec![start],
+        };
+        // The attempt fails at the earliest failure of any consequent
+        let end = consequent_starts
+            .into_iter()
+            .filter(|consequent_start| *consequent_start < len)
+            .filter_map(|consequent_start| {
+                match self.consequent.evaluate(consequent_start, len, &holds) {
+                    WaveformSequenceResult::Failed(end) => Some(end),
+                    _ => None,
+                }
+            })
+            .min()?;
+        if let Some(disable) = &self.disable {
+            if (start..=end).any(|edge| holds(disable, edge)) {
+                return None;
+            }
+        }
+        let (start, end) = (&window[start - first], &window[end - first]);
+        Some(WaveformPropertyFailure {
+            start_index: start.get_timestamp_index(),
+            end_index: end.get_timestamp_index(),
+            start: start.get_timestamp(),
+            end: end.get_timestamp(),
+        })
+    }
+}
+
+/// Clock edges where a failing attempt of a property starts and fails, both
+/// inclusive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaveformPropertyFailure {
+    start_index: usize,
+    end_index: usize,
+    start: u64,
+    end: u64,
+}
+
+impl WaveformPropertyFailure {
+    /// Returns the timestamp index of the clock edge the attempt started at
+    pub fn get_start_index(&self) -> usize {
+        self.start_index
+    }
+
+    /// Returns the timestamp index of the clock edge where the attempt failed
+    pub fn get_end_index(&self) -> usize {
+        self.end_index
+    }
+
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn get_end(&self) -> u64 {
+        self.end
+    }
+}
@@ -0,0 +1,227 @@
+// Queries shared by every kind of waveform, whether it is held in memory or
+// memory-mapped from a saved file. A waveform only has to hand out its sorted
+// timestamps and each of its signals by id, and every search, sample and
+// iterator is built on top of those.
+//
+// Signals may hold changes past the last timestamp, as a snapshot of a live
+// waveform does while the writer keeps appending, so every query stops at the
+// last timestamp as if nothing came after it.
+
+use std::ops::Range;
+
+use crate::events::WaveformEventIter;
+use crate::property::{WaveformProperty, WaveformPropertyFailure};
+use crate::sample::{WaveformCycle, WaveformCycleIter};
+use crate::search::WaveformPredicate;
+use crate::trigger::{WaveformTrigger, WaveformTriggerIter};
+use crate::{search_timestamps, WaveformSearchMode, WaveformSignalResult, WaveformValueResult};
+
+pub trait WaveformQuery {
+    /// Storage of the history blocks and values of each signal
+    type Bytes: AsRef<[u8]>;
+
+    fn get_timestamps(&self) -> &[u64];
+
+    /// Returns the signal with the given id, or None if there is no such
+    /// signal or it cannot be loaded
+    fn get_signal(&self, id: usize) -> Option<WaveformSignalResult<'_, Self::Bytes>>;
+
+    /// Binary search for the index of the requested timestamp, if the exact
+    /// timestamp exists. Otherwise, the search mode is used to determine where
+    /// else to look to look for a timestamp, either closest of any timestamp,
+    /// closest timestamp before, or closest timestamp after. If a timestamp
+    /// is found, this function returns a tuple (timestamp, timestamp index)
+    fn search_timestamp(&self, timestamp: u64, search_mode: WaveformSearchMode) -> Option<usize> {
+        search_timestamps(self.get_timestamps(), timestamp, search_mode)
+    }
+
+    fn search_value_bit_index(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        let signal = self.get_signal(idcode)?;
+        search_before_end(
+            self.get_timestamps().len(),
+            timestamp_index,
+            search_mode,
+            |timestamp_index, search_mode| {
+                signal.search_value(timestamp_index, search_mode, bit_index)
+            },
+        )
+    }
+
+    fn search_value(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+    ) -> Option<WaveformValueResult> {
+        self.search_value_bit_index(idcode, timestamp_index, search_mode, None)
+    }
+
+    /// Finds the first change of a signal after the given timestamp index
+    /// where the predicate starts to hold, optionally looking at only a single
+    /// bit of vector signals
+    fn find_next(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        predicate: &WaveformPredicate,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        self.get_signal(idcode)?
+            .find_next(timestamp_index, predicate, bit_index)
+            .filter(|value| value.get_timestamp_index() < self.get_timestamps().len())
+    }
+
+    /// Finds the last change of a signal before the given timestamp index
+    /// where the predicate starts to hold, see `find_next`
+    fn find_prev(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        predicate: &WaveformPredicate,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        let timestamp_index = timestamp_index.min(self.get_timestamps().len());
+        self.get_signal(idcode)?
+            .find_prev(timestamp_index, predicate, bit_index)
+    }
+
+    /// Searches for the change of the selected bits of a vector signal nearest
+    /// to the given timestamp index, ignoring changes to any other bits
+    fn search_bit_change(
+        &self,
+        idcode: usize,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+        bits: Range<usize>,
+    ) -> Option<WaveformValueResult> {
+        let signal = self.get_signal(idcode)?;
+        search_before_end(
+            self.get_timestamps().len(),
+            timestamp_index,
+            search_mode,
+            |timestamp_index, search_mode| {
+                signal.search_bit_change(timestamp_index, search_mode, bits.clone())
+            },
+        )
+    }
+
+    /// Returns a table of the values in effect for each id at each of the
+    /// given timestamp indices, with a row for every id in order. Ids without
+    /// a signal have rows of None. The timestamp indices must be sorted
+    fn sample(
+        &self,
+        ids: &[usize],
+        timestamp_indices: &[usize],
+    ) -> Vec<Vec<Option<WaveformValueResult>>> {
+        let end = self.get_timestamps().len();
+        ids.iter()
+            .map(|id| match self.get_signal(*id) {
+                Some(signal) if end > 0 => {
+                    let last = |index: &usize| (*index).min(end - 1);
+                    signal.sample(&timestamp_indices.iter().map(last).collect::<Vec<_>>())
+                }
+                _ => vec![None; timestamp_indices.len()],
+            })
+            .collect()
+    }
+
+    /// Returns every change of the given signals with a timestamp index inside
+    /// the range as a single stream in timestamp order, skipping ids without
+    /// a signal
+    fn iter_events(
+        &self,
+        ids: &[usize],
+        range: Range<usize>,
+    ) -> WaveformEventIter<'_, Self::Bytes> {
+        let signals = ids
+            .iter()
+            .filter_map(|id| Some((*id, self.get_signal(*id)?)))
+            .collect();
+        let end = range.end.min(self.get_timestamps().len());
+        WaveformEventIter::new(self.get_timestamps(), signals, range.start..end)
+    }
+
+    /// Returns an iterator over every edge of a clock, where the edge is any
+    /// change of the clock matching the predicate, such as
+    /// `WaveformPredicate::Rising`. Each cycle holds the values of the given
+    /// ids in effect at the last timestamp before the edge timestamp minus the
+    /// setup time, so changes at the same time as the edge are not seen
+    fn iter_cycles(
+        &self,
+        clock: usize,
+        edge: &WaveformPredicate,
+        ids: &[usize],
+        setup: u64,
+    ) -> WaveformCycleIter<'_, Self::Bytes> {
+        let signals = ids.iter().map(|id| self.get_signal(*id)).collect();
+        WaveformCycleIter::new(
+            self.get_timestamps(),
+            self.get_signal(clock),
+            edge,
+            signals,
+            setup,
+        )
+    }
+
+    /// Returns every cycle of a clock as a table, see `iter_cycles`
+    fn sample_cycles(
+        &self,
+        clock: usize,
+        edge: &WaveformPredicate,
+        ids: &[usize],
+        setup: u64,
+    ) -> Vec<WaveformCycle> {
+        self.iter_cycles(clock, edge, ids, setup).collect()
+    }
+
+    /// Returns an iterator over every match of a trigger in timestamp order
+    fn iter_triggers<'a>(
+        &'a self,
+        trigger: &'a WaveformTrigger,
+    ) -> WaveformTriggerIter<'a, Self::Bytes> {
+        let ids = trigger.get_ids();
+        let cycles = self.iter_cycles(trigger.get_clock(), trigger.get_edge(), &ids, 0);
+        WaveformTriggerIter::new(trigger, ids, cycles)
+    }
+
+    /// Checks a property from every edge of its clock, returning every failing
+    /// attempt in order of the clock edge it started at
+    fn check_property(&self, property: &WaveformProperty) -> Vec<WaveformPropertyFailure> {
+        let ids = property.get_ids();
+        let cycles = self.iter_cycles(property.get_clock(), property.get_edge(), &ids, 0);
+        property.check(&ids, cycles)
+    }
+}
+
+// Searches a signal as if the waveform ended at the given number of
+// timestamps, where anything closer past the end does not exist yet
+fn search_before_end(
+    end: usize,
+    timestamp_index: usize,
+    search_mode: WaveformSearchMode,
+    search: impl Fn(usize, WaveformSearchMode) -> Option<WaveformValueResult>,
+) -> Option<WaveformValueResult> {
+    let last = end.checked_sub(1)?;
+    let search = |timestamp_index, search_mode| {
+        search(timestamp_index, search_mode).filter(|value| value.get_timestamp_index() <= last)
+    };
+    if timestamp_index > last {
+        return match search_mode {
+            WaveformSearchMode::Before | WaveformSearchMode::Closest => {
+                search(last, WaveformSearchMode::Before)
+            }
+            WaveformSearchMode::After | WaveformSearchMode::Exact => None,
+        };
+    }
+    match search_mode {
+        WaveformSearchMode::Closest => search(timestamp_index, WaveformSearchMode::Closest)
+            .or_else(|| search(timestamp_index, WaveformSearchMode::Before)),
+        search_mode => search(timestamp_index, search_mode),
+    }
+}
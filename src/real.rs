@@ -1,10 +1,11 @@
 use std::convert::TryInto;
+use std::ops::Range;
 
 use crate::history::WaveformHistory;
 
-pub struct WaveformSignalReal {
-    history: WaveformHistory,
-    vectors: Vec<u8>,
+pub struct WaveformSignalReal<B = Vec<u8>> {
+    history: WaveformHistory<B>,
+    vectors: B,
     vector_index: usize,
 }
 
@@ -17,23 +18,108 @@ impl WaveformSignalReal {
         }
     }
 
-    pub fn get_history(&self) -> &WaveformHistory {
-        &self.history
-    }
-
+    /// Adds a value change at the given timestamp index, replacing the last
+    /// value instead if it was also at that timestamp index
     pub fn update(&mut self, timestamp_index: usize, value: f64) {
+        if self.history.get_last_timestamp_index() == Some(timestamp_index) {
+            let offset = self.vectors.len() - 8;
+            self.vectors[offset..].copy_from_slice(&value.to_be_bytes());
+            return;
+        }
         self.history.add_change(timestamp_index, self.vector_index);
         self.vectors.append(&mut value.to_be_bytes().to_vec());
         self.vector_index += 1;
     }
 
+    /// Appends the changes of another signal, offsetting its timestamp indices.
+    /// The first change of the other signal is dropped if it only repeats the
+    /// last value of this signal
+    pub fn append(&mut self, other: WaveformSignalReal, timestamp_offset: usize) {
+        self.append_changes(other, timestamp_offset, true);
+    }
+
+    // Appends the changes of another signal, only dropping a repeated first
+    // value when asked to
+    pub(crate) fn append_changes(
+        &mut self,
+        mut other: WaveformSignalReal,
+        timestamp_offset: usize,
+        drop_repeated: bool,
+    ) {
+        if other.history.get_block_count() == 0 {
+            return;
+        }
+        let mut start = other.history.get_block(0).get_value_index();
+        if drop_repeated
+            && !self.is_empty()
+            && self.get_real(self.len() - 1) == other.get_real(start)
+        {
+            other.history.remove_first_change();
+            start += 1;
+        }
+        self.history
+            .append(&other.history, timestamp_offset, self.vector_index);
+        self.vectors.extend_from_slice(&other.vectors[start * 8..]);
+        self.vector_index += other.len() - start;
+    }
+
+    /// Replaces every timestamp index of this signal with its entry in the
+    /// given table, which must be strictly increasing
+    pub fn remap_timestamps(&mut self, timestamp_indices: &[usize]) {
+        self.history.remap_timestamps(timestamp_indices);
+    }
+
+    /// Drops the history and values before the change in effect at the given
+    /// timestamp index, rebasing the remaining timestamp indices to start there
+    pub fn truncate_before(&mut self, timestamp_index: usize) {
+        let dropped = self.history.truncate_before(timestamp_index, 1);
+        self.vectors.drain(..dropped * 8);
+        self.vector_index -= dropped;
+    }
+}
+
+impl<B: AsRef<[u8]>> WaveformSignalReal<B> {
+    /// Creates a signal from its history and values, returning None if there
+    /// are not eight bytes for every value
+    pub(crate) fn from_parts(history: WaveformHistory<B>, vectors: B, len: usize) -> Option<Self> {
+        if Some(vectors.as_ref().len()) != len.checked_mul(8) {
+            return None;
+        }
+        Some(Self {
+            history,
+            vectors,
+            vector_index: len,
+        })
+    }
+
+    pub fn get_history(&self) -> &WaveformHistory<B> {
+        &self.history
+    }
+
     pub fn get_real(&self, index: usize) -> f64 {
-        let range = (index * 8)..(index * 8) + 1;
-        f64::from_be_bytes((&self.vectors[range]).try_into().unwrap())
+        let range = (index * 8)..(index * 8) + 8;
+        f64::from_be_bytes((&self.vectors.as_ref()[range]).try_into().unwrap())
+    }
+
+    /// Returns the (timestamp index, value) pairs for every change of this
+    /// signal with a timestamp index inside the given range
+    pub fn iter_range(&self, range: Range<usize>) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.history.iter_range(range).map(|index| {
+            (
+                index.get_timestamp_index(),
+                self.get_real(index.get_value_index()),
+            )
+        })
+    }
+
+    /// Returns the big-endian values, which are indexed by the value indices
+    /// of the history
+    pub fn get_vectors(&self) -> &[u8] {
+        self.vectors.as_ref()
     }
 
     pub fn get_vector_size(&self) -> usize {
-        self.vectors.len()
+        self.vectors.as_ref().len()
     }
 
     pub fn get_width(&self) -> usize {
@@ -0,0 +1,215 @@
+// Sampling many signals at many times at once. Searching each value on its own
+// binary searches the history blocks every time, whereas the timestamp indices
+// here are sorted so each history is walked once from start to end.
+//
+// Snapshots sample every signal at a single time instead, where each value is
+// found from a binary search of the block headers and decoding one block.
+//
+// Cycles sample signals at every edge of a clock, as a synchronous circuit
+// would see them, where each value is the one in effect just before the edge
+// or before a setup time ahead of it. Edges only move forwards in time, so
+// the sampled histories are walked once alongside the clock.
+
+use crate::history::index::WaveformHistoryIndex;
+use crate::history::WaveformHistoryIter;
+use crate::search::WaveformPredicate;
+use crate::{Waveform, WaveformSearchMode, WaveformSignalResult, WaveformValueResult};
+
+// Walks a history forwards, keeping the change in effect at the latest
+// timestamp index sampled
+struct WaveformSampler<'a, B> {
+    signal: WaveformSignalResult<'a, B>,
+    iter: WaveformHistoryIter<'a>,
+    last: Option<WaveformHistoryIndex>,
+}
+
+impl<'a, B: AsRef<[u8]>> WaveformSampler<'a, B> {
+    fn new(signal: WaveformSignalResult<'a, B>) -> Self {
+        Self {
+            signal,
+            iter: signal.get_history().into_iter(),
+            last: None,
+        }
+    }
+
+    // Returns the value in effect at a timestamp index, which must not be
+    // before the last one sampled
+    fn sample(&mut self, timestamp_index: usize) -> Option<WaveformValueResult> {
+        // Nothing new between samples leaves the last change in effect
+        if let Some(index) = self.iter.seek(timestamp_index) {
+            self.last = Some(index);
+        }
+        let index = self.last.clone()?;
+        Some(self.signal.get_value(index, None))
+    }
+}
+
+impl<B: AsRef<[u8]>> WaveformSignalResult<'_, B> {
+    /// Returns the value in effect at each of the given timestamp indices, as
+    /// `search_value` does with `WaveformSearchMode::Before`, walking the
+    /// history once. The timestamp indices must be sorted
+    pub fn sample(&self, timestamp_indices: &[usize]) -> Vec<Option<WaveformValueResult>> {
+        assert!(
+            timestamp_indices.is_sorted(),
+            "Sampled timestamp indices must be sorted"
+        );
+        let mut sampler = WaveformSampler::new(*self);
+        timestamp_indices
+            .iter()
+            .map(|timestamp_index| sampler.sample(*timestamp_index))
+            .collect()
+    }
+}
+
+impl Waveform {
+    /// Returns the value in effect for every signal at the given timestamp
+    /// index, or only the signals under a scope and the scopes nested inside
+    /// it. Values are sorted by id and signals without a value yet are left out
+    pub fn snapshot(
+        &self,
+        timestamp_index: usize,
+        scope: Option<usize>,
+    ) -> Vec<(usize, WaveformValueResult)> {
+        self.snapshot_ids(&self.get_snapshot_ids(scope), timestamp_index)
+    }
+
+    /// Takes a snapshot as `snapshot` does, splitting the signals across the
+    /// given number of threads
+    pub fn snapshot_parallel(
+        &self,
+        timestamp_index: usize,
+        scope: Option<usize>,
+        num_threads: usize,
+    ) -> Vec<(usize, WaveformValueResult)> {
+        assert!(num_threads > 0, "Must snapshot with at least one thread");
+        let ids = self.get_snapshot_ids(scope);
+        let chunk_size = ids.len().div_ceil(num_threads).max(1);
+        std::thread::scope(|scope| {
+            let handles = ids
+                .chunks(chunk_size)
+                .map(|ids| scope.spawn(move || self.snapshot_ids(ids, timestamp_index)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("Snapshot thread panicked"))
+                .collect()
+        })
+    }
+
+    // Returns the sorted ids of every signal in the snapshot
+    fn get_snapshot_ids(&self, scope: Option<usize>) -> Vec<usize> {
+        let mut ids = match scope {
+            Some(index) => self.hierarchy.get_scope_ids(index),
+            None => self
+                .vector_signals
+                .keys()
+                .chain(self.real_signals.keys())
+                .chain(self.derived_signals.keys())
+                .cloned()
+                .collect(),
+        };
+        ids.retain(|id| self.get_signal(*id).is_some());
+        ids.sort_unstable();
+        ids
+    }
+
+    fn snapshot_ids(
+        &self,
+        ids: &[usize],
+        timestamp_index: usize,
+    ) -> Vec<(usize, WaveformValueResult)> {
+        ids.iter()
+            .filter_map(|id| {
+                let value = self.search_value(*id, timestamp_index, WaveformSearchMode::Before)?;
+                Some((*id, value))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveformCycle {
+    timestamp_index: usize,
+    timestamp: u64,
+    values: Vec<Option<WaveformValueResult>>,
+}
+
+impl WaveformCycle {
+    /// Returns the timestamp index of the clock edge
+    pub fn get_timestamp_index(&self) -> usize {
+        self.timestamp_index
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the sampled value of each id in order, or None if a signal does
+    /// not exist or has no value yet
+    pub fn get_values(&self) -> &[Option<WaveformValueResult>] {
+        &self.values
+    }
+}
+
+pub struct WaveformCycleIter<'a, B = Vec<u8>> {
+    timestamps: &'a [u64],
+    clock: Option<(WaveformSignalResult<'a, B>, WaveformHistoryIter<'a>)>,
+    previous: Option<WaveformHistoryIndex>,
+    edge: WaveformPredicate,
+    samplers: Vec<Option<WaveformSampler<'a, B>>>,
+    setup: u64,
+}
+
+impl<'a, B: AsRef<[u8]>> WaveformCycleIter<'a, B> {
+    pub(crate) fn new(
+        timestamps: &'a [u64],
+        clock: Option<WaveformSignalResult<'a, B>>,
+        edge: &WaveformPredicate,
+        signals: Vec<Option<WaveformSignalResult<'a, B>>>,
+        setup: u64,
+    ) -> Self {
+        Self {
+            timestamps,
+            clock: clock.map(|clock| (clock, clock.get_history().into_iter())),
+            previous: None,
+            edge: edge.clone(),
+            samplers: signals
+                .into_iter()
+                .map(|signal| signal.map(WaveformSampler::new))
+                .collect(),
+            setup,
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Iterator for WaveformCycleIter<'_, B> {
+    type Item = WaveformCycle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (clock, iter) = self.clock.as_mut()?;
+        loop {
+            let index = iter.next()?;
+            let previous = self.previous.replace(index.clone());
+            if !clock.matches(previous.as_ref(), &index, &self.edge, None) {
+                continue;
+            }
+            let timestamp_index = index.get_timestamp_index();
+            let timestamp = *self.timestamps.get(timestamp_index)?;
+            // Sample at the last timestamp strictly before the setup time
+            let sample_time = timestamp.saturating_sub(self.setup);
+            let sample_index = self.timestamps[..timestamp_index]
+                .partition_point(|t| *t < sample_time)
+                .checked_sub(1);
+            let values = self
+                .samplers
+                .iter_mut()
+                .map(|sampler| sampler.as_mut()?.sample(sample_index?))
+                .collect();
+            return Some(WaveformCycle {
+                timestamp_index,
+                timestamp,
+                values,
+            });
+        }
+    }
+}
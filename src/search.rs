@@ -0,0 +1,150 @@
+// Searching across time for the next or previous change of a signal where a
+// condition starts to hold, such as a rising edge or a value being reached.
+// Each change is compared against the change before it, so a predicate only
+// matches where it goes from not holding to holding, and changes that leave
+// the selected bit the same never match.
+
+use std::ops::Range;
+
+use crate::bitvector::{BitVector, Logic};
+use crate::history::index::WaveformHistoryIndex;
+use crate::{WaveformSearchMode, WaveformSignalResult, WaveformValueResult};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WaveformPredicate {
+    /// The least significant bit goes from 0 to 1, X or Z, or from X or Z to
+    /// 1, as with a Verilog posedge
+    Rising,
+    /// The least significant bit goes from 1 to 0, X or Z, or from X or Z to
+    /// 0, as with a Verilog negedge
+    Falling,
+    /// Any change to the value
+    Change,
+    /// Any bit becomes X or Z
+    Unknown,
+    /// The value becomes equal to the given value
+    Equals(BitVector),
+    /// The bits set to one in the mask become equal to the same bits of the
+    /// given value, where every other bit is a don't-care
+    Masked { value: BitVector, mask: BitVector },
+}
+
+impl WaveformPredicate {
+    /// Returns true if the predicate starts to hold going from the previous
+    /// value to the given value, where a missing previous value is treated as
+    /// unknown
+    pub fn matches(&self, previous: Option<&BitVector>, value: &BitVector) -> bool {
+        let is_unknown = |bv: &BitVector| bv.is_unknown() || bv.is_high_impedance();
+        match self {
+            Self::Rising | Self::Falling => {
+                let (low, high) = match self {
+                    Self::Rising => (Logic::Zero, Logic::One),
+                    _ => (Logic::One, Logic::Zero),
+                };
+                let before = previous.map_or(Logic::Unknown, |bv| bv.get_bit(0));
+                let after = value.get_bit(0);
+                (before == low && after != low)
+                    || (before != high && before != low && after == high)
+            }
+            Self::Change => previous != Some(value),
+            Self::Unknown => is_unknown(value) && !previous.is_some_and(is_unknown),
+            Self::Equals(expected) => value == expected && previous != Some(expected),
+            Self::Masked {
+                value: expected,
+                mask,
+            } => {
+                let is_match = |bv: &BitVector| {
+                    (0..mask.get_bit_width()).all(|i| {
+                        mask.get_bit(i) != Logic::One || bv.get_bit(i) == expected.get_bit(i)
+                    })
+                };
+                is_match(value) && !previous.is_some_and(is_match)
+            }
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> WaveformSignalResult<'_, B> {
+    /// Finds the first change after the given timestamp index where the
+    /// predicate starts to hold, optionally looking at only a single bit of
+    /// vector signals. Real signals only ever match `WaveformPredicate::Change`
+    pub fn find_next(
+        &self,
+        timestamp_index: usize,
+        predicate: &WaveformPredicate,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        let history = self.get_history();
+        let mut previous =
+            history.search_timestamp_index(timestamp_index, WaveformSearchMode::Before);
+        for index in history.iter_range(timestamp_index.saturating_add(1)..usize::MAX) {
+            if self.matches(previous.as_ref(), &index, predicate, bit_index) {
+                return Some(self.get_value(index, bit_index));
+            }
+            previous = Some(index);
+        }
+        None
+    }
+
+    /// Finds the last change before the given timestamp index where the
+    /// predicate starts to hold, see `find_next`
+    pub fn find_prev(
+        &self,
+        timestamp_index: usize,
+        predicate: &WaveformPredicate,
+        bit_index: Option<usize>,
+    ) -> Option<WaveformValueResult> {
+        let mut iter = self.get_history().iter_before(timestamp_index).peekable();
+        while let Some(index) = iter.next() {
+            if self.matches(iter.peek(), &index, predicate, bit_index) {
+                return Some(self.get_value(index, bit_index));
+            }
+        }
+        None
+    }
+
+    /// Searches for the change of the selected bits of a vector signal as
+    /// `WaveformSignalVector::search_bit_change` does, returning None for real
+    /// signals
+    pub fn search_bit_change(
+        &self,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+        bits: Range<usize>,
+    ) -> Option<WaveformValueResult> {
+        let Self::Vector(signal) = self else {
+            return None;
+        };
+        let (timestamp_index, bv) = signal.search_bit_change(timestamp_index, search_mode, bits)?;
+        Some(WaveformValueResult::Vector(bv, timestamp_index))
+    }
+
+    // Returns true if the predicate starts to hold at a change, given the
+    // change before it
+    pub(crate) fn matches(
+        &self,
+        previous: Option<&WaveformHistoryIndex>,
+        index: &WaveformHistoryIndex,
+        predicate: &WaveformPredicate,
+        bit_index: Option<usize>,
+    ) -> bool {
+        match self {
+            Self::Vector(signal) => {
+                let get_value = |index: &WaveformHistoryIndex| {
+                    let bv = signal.get_bitvector(index.get_value_index());
+                    match bit_index {
+                        Some(bit_index) => BitVector::from(bv.get_bit(bit_index)),
+                        None => bv,
+                    }
+                };
+                predicate.matches(previous.map(get_value).as_ref(), &get_value(index))
+            }
+            Self::Real(signal) => {
+                let value = signal.get_real(index.get_value_index());
+                *predicate == WaveformPredicate::Change
+                    && previous.map(|previous| signal.get_real(previous.get_value_index()))
+                        != Some(value)
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+
+use indiscriminant::*;
+
+// Timestamps in a waveform are bare ticks, and the timescale gives the length
+// of one tick as a magnitude of 1, 10 or 100 of a unit, as declared in a VCD.
+// Physical times are handled in whole femtoseconds, the smallest unit, so
+// converting between timescales never loses precision.
+
+#[indiscriminant()]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaveformTimeUnit {
+    Second = "s",
+    Millisecond = "ms",
+    Microsecond = "us",
+    Nanosecond = "ns",
+    Picosecond = "ps",
+    Femtosecond = "fs",
+}
+
+impl WaveformTimeUnit {
+    const UNITS: [Self; 6] = [
+        Self::Second,
+        Self::Millisecond,
+        Self::Microsecond,
+        Self::Nanosecond,
+        Self::Picosecond,
+        Self::Femtosecond,
+    ];
+
+    /// Returns the unit as a power of ten in seconds
+    pub fn get_exponent(&self) -> i8 {
+        match self {
+            Self::Second => 0,
+            Self::Millisecond => -3,
+            Self::Microsecond => -6,
+            Self::Nanosecond => -9,
+            Self::Picosecond => -12,
+            Self::Femtosecond => -15,
+        }
+    }
+
+    pub fn get_femtoseconds(&self) -> u64 {
+        10u64.pow((self.get_exponent() + 15) as u32)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaveformTimescale {
+    magnitude: u64,
+    unit: WaveformTimeUnit,
+}
+
+impl WaveformTimescale {
+    /// Creates a timescale, returning None unless the magnitude is 1, 10 or
+    /// 100
+    pub fn new(magnitude: u64, unit: WaveformTimeUnit) -> Option<Self> {
+        matches!(magnitude, 1 | 10 | 100).then_some(Self { magnitude, unit })
+    }
+
+    /// Parses a timescale written as "10ps" or "10 ps"
+    pub fn parse(timescale: &str) -> Option<Self> {
+        let timescale = timescale.trim();
+        let split = timescale
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(timescale.len());
+        let (magnitude, unit) = timescale.split_at(split);
+        Self::new(
+            magnitude.parse().ok()?,
+            WaveformTimeUnit::from_str(unit.trim_start())?,
+        )
+    }
+
+    /// Creates a timescale from a power of ten in seconds, as used by FST,
+    /// returning None if it is finer than femtoseconds or coarser than 100s
+    pub fn from_exponent(exponent: i8) -> Option<Self> {
+        let unit = WaveformTimeUnit::UNITS
+            .into_iter()
+            .find(|unit| (0..3).contains(&(exponent - unit.get_exponent())))?;
+        Self::new(10u64.pow((exponent - unit.get_exponent()) as u32), unit)
+    }
+
+    pub fn get_magnitude(&self) -> u64 {
+        self.magnitude
+    }
+
+    pub fn get_unit(&self) -> WaveformTimeUnit {
+        self.unit
+    }
+
+    /// Returns the timescale as a power of ten in seconds
+    pub fn get_exponent(&self) -> i8 {
+        self.unit.get_exponent() + self.magnitude.ilog10() as i8
+    }
+
+    /// Returns the length of one tick in femtoseconds
+    pub fn get_femtoseconds(&self) -> u64 {
+        self.magnitude * self.unit.get_femtoseconds()
+    }
+
+    pub fn ticks_to_femtoseconds(&self, ticks: u64) -> u128 {
+        ticks as u128 * self.get_femtoseconds() as u128
+    }
+
+    /// Converts femtoseconds to the nearest tick, saturating if it does not fit
+    pub fn femtoseconds_to_ticks(&self, femtoseconds: u128) -> u64 {
+        let tick = self.get_femtoseconds() as u128;
+        ((femtoseconds + tick / 2) / tick)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+
+    pub fn ticks_to_seconds(&self, ticks: u64) -> f64 {
+        ticks as f64 * 10f64.powi(self.get_exponent() as i32)
+    }
+
+    /// Returns how many ticks of this timescale make up one tick of another,
+    /// or None if the other timescale is finer and so does not divide evenly
+    pub fn get_ratio(&self, other: &Self) -> Option<u64> {
+        let (tick, other_tick) = (self.get_femtoseconds(), other.get_femtoseconds());
+        other_tick.is_multiple_of(tick).then(|| other_tick / tick)
+    }
+
+    /// Formats ticks as a physical time such as "12.5 ns"
+    pub fn format_ticks(&self, ticks: u64) -> String {
+        format_time(self.ticks_to_femtoseconds(ticks))
+    }
+
+    /// Parses a physical time such as "12.5 ns" into the nearest tick
+    pub fn parse_ticks(&self, time: &str) -> Option<u64> {
+        parse_time(time).map(|femtoseconds| self.femtoseconds_to_ticks(femtoseconds))
+    }
+}
+
+impl fmt::Display for WaveformTimescale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.magnitude, self.unit.to_str())
+    }
+}
+
+/// Formats femtoseconds in the largest unit that keeps the value at least one,
+/// such as "12.5 ns", without any trailing zeros
+pub fn format_time(femtoseconds: u128) -> String {
+    let unit = WaveformTimeUnit::UNITS
+        .into_iter()
+        .find(|unit| femtoseconds >= unit.get_femtoseconds() as u128)
+        .unwrap_or(WaveformTimeUnit::Femtosecond);
+    let scale = unit.get_femtoseconds() as u128;
+    let (whole, fraction) = (femtoseconds / scale, femtoseconds % scale);
+    if fraction == 0 {
+        return format!("{whole} {}", unit.to_str());
+    }
+    let digits = scale.ilog10() as usize;
+    let fraction = format!("{fraction:0digits$}");
+    format!(
+        "{whole}.{} {}",
+        fraction.trim_end_matches('0'),
+        unit.to_str()
+    )
+}
+
+/// Parses a physical time such as "12.5 ns", "3us" or "0.25 ps" into
+/// femtoseconds, returning None if it is not a whole number of femtoseconds
+pub fn parse_time(time: &str) -> Option<u128> {
+    let time = time.trim();
+    let split = time
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(time.len());
+    let (value, unit) = time.split_at(split);
+    let unit = WaveformTimeUnit::from_str(unit.trim_start())?;
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if (whole.is_empty() && fraction.is_empty()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits = unit.get_femtoseconds().ilog10() as usize;
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > digits {
+        return None;
+    }
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole.parse::<u128>().ok()?
+    };
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u128>().ok()? * 10u128.pow((digits - fraction.len()) as u32)
+    };
+    whole
+        .checked_mul(unit.get_femtoseconds() as u128)?
+        .checked_add(fraction)
+}
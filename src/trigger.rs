@@ -0,0 +1,419 @@
+// Triggers find where a sequence of conditions holds on a clock, as a logic
+// analyzer would, such as "A rises, then within 100 cycles B == 0x3F while C
+// is not X". Every condition is checked against the values sampled at each
+// clock edge, so the whole search walks each history once alongside the clock.
+//
+// The first stage starts an attempt wherever it holds, and each later stage
+// must then hold after the stage before it. Many attempts can be in progress
+// at once, and the first attempt to finish every stage is the match, dropping
+// every other attempt so matches never overlap. Attempts in the same state
+// finish on the same edge from then on, so only the earliest started of them
+// is kept, which bounds the attempts by the holds and withins of the stages.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::bitvector::Logic;
+use crate::sample::WaveformCycleIter;
+use crate::search::WaveformPredicate;
+use crate::WaveformValueResult;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WaveformCondition {
+    /// The sampled value of a signal satisfies the predicate, where
+    /// `WaveformPredicate::Rising` and `WaveformPredicate::Falling` hold while
+    /// the least significant bit is 1 or 0 and `WaveformPredicate::Change`
+    /// always holds
+    Is(usize, WaveformPredicate),
+    /// The predicate starts to hold from the value sampled at the previous
+    /// clock edge to this one, as with `WaveformPredicate::matches`
+    Becomes(usize, WaveformPredicate),
+    /// The least significant bit is 1 and was not 1 at the previous clock
+    /// edge, as with SVA `$rose`
+    Rose(usize),
+    /// The least significant bit is 0 and was not 0 at the previous clock
+    /// edge, as with SVA `$fell`
+    Fell(usize),
+    /// The value is the same as at the previous clock edge, as with SVA
+    /// `$stable`
+    Stable(usize),
+    Not(Box<Self>),
+    All(Vec<Self>),
+    Any(Vec<Self>),
+}
+
+impl WaveformCondition {
+    /// Returns the sorted ids of every signal referenced by the condition
+    pub fn get_ids(&self) -> Vec<usize> {
+        let mut ids = Vec::new();
+        self.push_ids(&mut ids);
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    pub(crate) fn push_ids(&self, ids: &mut Vec<usize>) {
+        match self {
+            Self::Is(id, _)
+            | Self::Becomes(id, _)
+            | Self::Rose(id)
+            | Self::Fell(id)
+            | Self::Stable(id) => ids.push(*id),
+            Self::Not(condition) => condition.push_ids(ids),
+            Self::All(conditions) | Self::Any(conditions) => conditions
+                .iter()
+                .for_each(|condition| condition.push_ids(ids)),
+        }
+    }
+
+    // Checks the condition given the sampled values of the sorted ids at this
+    // clock edge and the one before it. Signals without a value never satisfy
+    // a predicate
+    pub(crate) fn evaluate(
+        &self,
+        ids: &[usize],
+        previous: &[Option<WaveformValueResult>],
+        values: &[Option<WaveformValueResult>],
+    ) -> bool {
+        fn get<'a>(
+            ids: &[usize],
+            values: &'a [Option<WaveformValueResult>],
+            id: &usize,
+        ) -> Option<&'a WaveformValueResult> {
+            values.get(ids.binary_search(id).ok()?)?.as_ref()
+        }
+        match self {
+            Self::Is(id, predicate) => matches(predicate, None, get(ids, values, id)),
+            Self::Becomes(id, predicate) => {
+                matches(predicate, get(ids, previous, id), get(ids, values, id))
+            }
+            Self::Rose(id) | Self::Fell(id) => {
+                let bit = if let Self::Rose(_) = self {
+                    Logic::One
+                } else {
+                    Logic::Zero
+                };
+                let get_bit = |value: Option<&WaveformValueResult>| match value {
+                    Some(WaveformValueResult::Vector(bv, _)) => Some(bv.get_bit(0)),
+                    _ => None,
+                };
+                get_bit(get(ids, values, id)) == Some(bit)
+                    && get_bit(get(ids, previous, id)) != Some(bit)
+            }
+            Self::Stable(id) => {
+                // Compare the values without the timestamp index of the change
+                match (get(ids, previous, id), get(ids, values, id)) {
+                    (
+                        Some(WaveformValueResult::Vector(a, _)),
+                        Some(WaveformValueResult::Vector(b, _)),
+                    ) => a == b,
+                    (
+                        Some(WaveformValueResult::Real(a, _)),
+                        Some(WaveformValueResult::Real(b, _)),
+                    ) => a == b,
+                    (a, b) => a.is_none() && b.is_none(),
+                }
+            }
+            Self::Not(condition) => !condition.evaluate(ids, previous, values),
+            Self::All(conditions) => conditions
+                .iter()
+                .all(|condition| condition.evaluate(ids, previous, values)),
+            Self::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.evaluate(ids, previous, values)),
+        }
+    }
+}
+
+// Matches a predicate between two sampled values, where real values only ever
+// match `WaveformPredicate::Change`
+fn matches(
+    predicate: &WaveformPredicate,
+    previous: Option<&WaveformValueResult>,
+    value: Option<&WaveformValueResult>,
+) -> bool {
+    match value {
+        Some(WaveformValueResult::Vector(bv, _)) => {
+            let previous = match previous {
+                Some(WaveformValueResult::Vector(previous, _)) => Some(previous),
+                _ => None,
+            };
+            predicate.matches(previous, bv)
+        }
+        Some(WaveformValueResult::Real(value, _)) => {
+            let previous = match previous {
+                Some(WaveformValueResult::Real(previous, _)) => Some(previous),
+                _ => None,
+            };
+            *predicate == WaveformPredicate::Change && previous != Some(value)
+        }
+        None => false,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveformTriggerStage {
+    condition: WaveformCondition,
+    hold: usize,
+    within: Option<usize>,
+    guard: Option<WaveformCondition>,
+}
+
+impl WaveformTriggerStage {
+    /// Creates a stage that completes at the first clock edge where the
+    /// condition holds
+    pub fn new(condition: WaveformCondition) -> Self {
+        Self {
+            condition,
+            hold: 1,
+            within: None,
+            guard: None,
+        }
+    }
+
+    /// Sets how many consecutive clock edges the condition must hold for
+    pub fn set_hold(&mut self, hold: usize) {
+        assert!(hold > 0, "Stage must hold for at least one clock edge");
+        self.hold = hold;
+    }
+
+    /// Sets how many clock edges after the previous stage this stage must
+    /// complete within, or else the attempt fails. Ignored on the first stage
+    pub fn set_within(&mut self, within: Option<usize>) {
+        self.within = within;
+    }
+
+    /// Sets a condition that must hold at every clock edge after the previous
+    /// stage up to and including the one completing this stage, or else the
+    /// attempt fails. Ignored on the first stage
+    pub fn set_guard(&mut self, guard: Option<WaveformCondition>) {
+        self.guard = guard;
+    }
+
+    pub fn get_condition(&self) -> &WaveformCondition {
+        &self.condition
+    }
+
+    pub fn get_hold(&self) -> usize {
+        self.hold
+    }
+
+    pub fn get_within(&self) -> Option<usize> {
+        self.within
+    }
+
+    pub fn get_guard(&self) -> Option<&WaveformCondition> {
+        self.guard.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveformTrigger {
+    clock: usize,
+    edge: WaveformPredicate,
+    stages: Vec<WaveformTriggerStage>,
+}
+
+impl WaveformTrigger {
+    /// Creates a trigger checked at every change of a clock matching the
+    /// edge predicate, such as `WaveformPredicate::Rising`
+    pub fn new(clock: usize, edge: WaveformPredicate) -> Self {
+        Self {
+            clock,
+            edge,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Adds a stage that must complete after every stage added before it
+    pub fn push_stage(&mut self, stage: WaveformTriggerStage) {
+        self.stages.push(stage);
+    }
+
+    pub fn get_clock(&self) -> usize {
+        self.clock
+    }
+
+    pub fn get_edge(&self) -> &WaveformPredicate {
+        &self.edge
+    }
+
+    pub fn get_stages(&self) -> &[WaveformTriggerStage] {
+        &self.stages
+    }
+
+    /// Returns the sorted ids of every signal referenced by the stages
+    pub fn get_ids(&self) -> Vec<usize> {
+        let mut ids = Vec::new();
+        for stage in &self.stages {
+            stage.condition.push_ids(&mut ids);
+            if let Some(guard) = &stage.guard {
+                guard.push_ids(&mut ids);
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+}
+
+/// Clock edges where a trigger starts and finishes matching, both inclusive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaveformTriggerMatch {
+    start_index: usize,
+    end_index: usize,
+    start: u64,
+    end: u64,
+}
+
+impl WaveformTriggerMatch {
+    /// Returns the timestamp index of the first clock edge of the first stage
+    pub fn get_start_index(&self) -> usize {
+        self.start_index
+    }
+
+    /// Returns the timestamp index of the clock edge completing the last stage
+    pub fn get_end_index(&self) -> usize {
+        self.end_index
+    }
+
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn get_end(&self) -> u64 {
+        self.end
+    }
+}
+
+// An attempt that has completed the stages before the current one
+struct WaveformTriggerAttempt {
+    start_index: usize,
+    start: u64,
+    stage: usize,
+    // Consecutive clock edges the current stage has held for
+    held: usize,
+    // Clock edges since the previous stage completed
+    waited: usize,
+}
+
+pub struct WaveformTriggerIter<'a, B = Vec<u8>> {
+    trigger: &'a WaveformTrigger,
+    ids: Vec<usize>,
+    cycles: WaveformCycleIter<'a, B>,
+    previous: Vec<Option<WaveformValueResult>>,
+    // Latest clock edges the first stage has held for, up to its hold, as
+    // (timestamp index, timestamp)
+    first: VecDeque<(usize, u64)>,
+    attempts: Vec<WaveformTriggerAttempt>,
+}
+
+impl<'a, B: AsRef<[u8]>> WaveformTriggerIter<'a, B> {
+    pub(crate) fn new(
+        trigger: &'a WaveformTrigger,
+        ids: Vec<usize>,
+        cycles: WaveformCycleIter<'a, B>,
+    ) -> Self {
+        Self {
+            trigger,
+            previous: vec![None; ids.len()],
+            ids,
+            cycles,
+            first: VecDeque::new(),
+            attempts: Vec::new(),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> Iterator for WaveformTriggerIter<'_, B> {
+    type Item = WaveformTriggerMatch;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stages = &self.trigger.stages;
+        let first_stage = stages.first()?;
+        for cycle in self.cycles.by_ref() {
+            let values = cycle.get_values();
+            let holds = |condition: &WaveformCondition| {
+                condition.evaluate(&self.ids, &self.previous, values)
+            };
+            let mut finished = None;
+            // States of the attempts kept on this edge, where waiting only
+            // matters to stages that must complete within some edges
+            let mut states = HashSet::new();
+            let mut is_new = |attempt: &WaveformTriggerAttempt| {
+                let waited = stages[attempt.stage].within.map(|_| attempt.waited);
+                states.insert((attempt.stage, attempt.held, waited))
+            };
+            // Advance attempts from the earliest start so the first one to
+            // finish on this edge is the longest match
+            self.attempts.retain_mut(|attempt| {
+                if finished.is_some() {
+                    return false;
+                }
+                let stage = &stages[attempt.stage];
+                attempt.waited += 1;
+                if stage.guard.as_ref().is_some_and(|guard| !holds(guard)) {
+                    return false;
+                }
+                attempt.held = if holds(&stage.condition) {
+                    attempt.held + 1
+                } else {
+                    0
+                };
+                if attempt.held < stage.hold {
+                    // Completing later would be too late
+                    return stage.within.is_none_or(|within| attempt.waited < within)
+                        && is_new(attempt);
+                }
+                attempt.stage += 1;
+                attempt.held = 0;
+                attempt.waited = 0;
+                if attempt.stage == stages.len() {
+                    finished = Some((attempt.start_index, attempt.start));
+                    return true;
+                }
+                is_new(attempt)
+            });
+            // The first stage starts a new attempt after holding long enough
+            if finished.is_none() {
+                if holds(&first_stage.condition) {
+                    self.first
+                        .push_back((cycle.get_timestamp_index(), cycle.get_timestamp()));
+                    if self.first.len() > first_stage.hold {
+                        self.first.pop_front();
+                    }
+                } else {
+                    self.first.clear();
+                }
+                if self.first.len() == first_stage.hold {
+                    let (start_index, start) = self.first[0];
+                    if stages.len() == 1 {
+                        finished = Some((start_index, start));
+                    } else {
+                        let attempt = WaveformTriggerAttempt {
+                            start_index,
+                            start,
+                            stage: 1,
+                            held: 0,
+                            waited: 0,
+                        };
+                        if is_new(&attempt) {
+                            self.attempts.push(attempt);
+                        }
+                    }
+                }
+            }
+            self.previous = values.to_vec();
+            if let Some((start_index, start)) = finished {
+                self.attempts.clear();
+                self.first.clear();
+                return Some(WaveformTriggerMatch {
+                    start_index,
+                    end_index: cycle.get_timestamp_index(),
+                    start,
+                    end: cycle.get_timestamp(),
+                });
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,17 @@
+// Value Change Dump (IEEE 1364) support for reading waveforms into the
+// database and writing them back out. The header declares the scopes and
+// variables of the design, which populate the hierarchy of the waveform, and
+// each unique identifier code is given the next signal id. The body is a
+// stream of timestamps and value changes that are inserted into the waveform
+// as they are read.
+//
+// Large dumps can be read in parallel by cutting the body into chunks at
+// lines starting with a new timestamp, where every chunk is tokenized once
+// into a waveform of its own and the chunks are appended in order.
+//
+// Writing assigns each signal the next of the shortest identifier codes and
+// starts the body with the values in effect at the first timestamp written.
+
+pub mod parallel;
+pub mod reader;
+pub mod writer;
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+use crate::errors::*;
+use crate::vcd::reader::WaveformVcdReader;
+use crate::Waveform;
+
+const DEFAULT_CHUNK_SIZE: usize = 1 << 22;
+
+// A piece of the body starting at a timestamp, along with the number of lines
+// before it in the whole file
+struct VcdChunk {
+    data: Vec<u8>,
+    line: usize,
+}
+
+// Cuts the body into chunks at lines starting with a timestamp later than any
+// timestamp line before it, so that each chunk can be read into a waveform of
+// its own and appended after the chunks before it
+struct VcdChunker<R: BufRead> {
+    reader: R,
+    chunk_size: usize,
+    next: Vec<u8>,
+    line: usize,
+    last: Option<u64>,
+}
+
+impl<R: BufRead> VcdChunker<R> {
+    fn next_chunk(&mut self) -> WaveformResult<Option<VcdChunk>> {
+        let mut data = std::mem::take(&mut self.next);
+        let line = self.line;
+        if !data.is_empty() {
+            self.line += 1;
+        }
+        loop {
+            let start = data.len();
+            if self.reader.read_until(b'\n', &mut data)? == 0 {
+                break;
+            }
+            if let Some(timestamp) = Self::get_timestamp(&data[start..]) {
+                let later = self.last.is_none_or(|last| timestamp > last);
+                self.last = self.last.max(Some(timestamp));
+                if start >= self.chunk_size && later {
+                    self.next = data.split_off(start);
+                    break;
+                }
+            }
+            self.line += 1;
+        }
+        Ok((!data.is_empty()).then_some(VcdChunk { data, line }))
+    }
+
+    fn get_timestamp(line: &[u8]) -> Option<u64> {
+        let timestamp = line.strip_prefix(b"#")?;
+        let end = timestamp
+            .iter()
+            .position(|b| b.is_ascii_whitespace())
+            .unwrap_or(timestamp.len());
+        std::str::from_utf8(&timestamp[..end]).ok()?.parse().ok()
+    }
+}
+
+/// Reads a VCD by cutting the body into chunks that each start at a
+/// timestamp, where the header is read once and then every chunk is read into
+/// a waveform of its own on its own thread before they are appended in order
+pub struct WaveformVcdParallelReader<R: BufRead> {
+    reader: R,
+    num_threads: usize,
+    chunk_size: usize,
+}
+
+impl<R: BufRead> WaveformVcdParallelReader<R> {
+    pub fn new(reader: R, num_threads: usize) -> Self {
+        assert!(num_threads > 0, "Must read with at least one thread");
+        Self {
+            reader,
+            num_threads,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the number of bytes read into a chunk before it is cut at the next
+    /// timestamp
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size;
+    }
+
+    /// Reads an entire VCD into a new waveform, reporting the first error in
+    /// the order of the file
+    pub fn read(self) -> WaveformResult<Waveform> {
+        let mut waveform = Waveform::new();
+        let mut reader = WaveformVcdReader::new(self.reader);
+        reader.read_header(&mut waveform)?;
+        let identifiers = reader.get_identifiers().clone();
+        let signals = identifiers
+            .values()
+            .map(|id| (*id, waveform.get_vector_signal(*id).map(|s| s.get_width())))
+            .collect::<Vec<(usize, Option<usize>)>>();
+        let (next, reader, line) = reader.into_remaining();
+        let mut chunker = VcdChunker {
+            reader,
+            chunk_size: self.chunk_size,
+            next,
+            line,
+            last: None,
+        };
+        std::thread::scope(|scope| {
+            let mut handles = VecDeque::new();
+            loop {
+                let (chunk, error) = match chunker.next_chunk() {
+                    Ok(chunk) => (chunk, None),
+                    Err(error) => (None, Some(error)),
+                };
+                let done = chunk.is_none();
+                if let Some(chunk) = chunk {
+                    let (identifiers, signals) = (&identifiers, &signals);
+                    handles.push_back(scope.spawn(move || {
+                        let mut waveform = Waveform::new();
+                        for (id, width) in signals {
+                            match width {
+                                Some(width) => waveform.initialize_vector(*id, *width),
+                                None => waveform.initialize_real(*id),
+                            }
+                        }
+                        let mut reader = WaveformVcdReader::with_identifiers(
+                            chunk.data.as_slice(),
+                            identifiers.clone(),
+                        );
+                        reader.set_line(chunk.line);
+                        reader.read_body(&mut waveform)?;
+                        WaveformResult::Ok(waveform)
+                    }));
+                }
+                // Chunks before a failed read still report their own errors
+                while handles.len() >= self.num_threads || (done && !handles.is_empty()) {
+                    let handle = handles.pop_front().unwrap();
+                    let chunk = handle.join().expect("VCD chunk thread panicked")?;
+                    // Repeated values are kept as changes, as when reading
+                    // the body in one piece
+                    waveform.append_changes(chunk, false)?;
+                }
+                if done {
+                    return error.map_or(Ok(()), Err);
+                }
+            }
+        })?;
+        Ok(waveform)
+    }
+}
+
+/// Reads a VCD with its body split across the given number of threads
+pub fn read_vcd_parallel<R: BufRead>(reader: R, num_threads: usize) -> WaveformResult<Waveform> {
+    WaveformVcdParallelReader::new(reader, num_threads).read()
+}
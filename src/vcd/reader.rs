@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::bitvector::BitVector;
+use crate::errors::*;
+use crate::hierarchy::*;
+use crate::timescale::WaveformTimescale;
+use crate::Waveform;
+
+// Splits the input into whitespace separated tokens one line at a time so the
+// whole file never has to be in memory
+struct VcdTokenizer<R: BufRead> {
+    reader: R,
+    buffer: Vec<u8>,
+    offset: usize,
+    line: usize,
+    token: Vec<u8>,
+}
+
+impl<R: BufRead> VcdTokenizer<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+            offset: 0,
+            line: 0,
+            token: Vec::new(),
+        }
+    }
+
+    // Reads the next token, returning false at the end of the input
+    fn next(&mut self) -> WaveformResult<bool> {
+        loop {
+            while self.offset < self.buffer.len() && self.buffer[self.offset].is_ascii_whitespace()
+            {
+                self.offset += 1;
+            }
+            if self.offset < self.buffer.len() {
+                let start = self.offset;
+                while self.offset < self.buffer.len()
+                    && !self.buffer[self.offset].is_ascii_whitespace()
+                {
+                    self.offset += 1;
+                }
+                self.token.clear();
+                self.token
+                    .extend_from_slice(&self.buffer[start..self.offset]);
+                return Ok(true);
+            }
+            self.buffer.clear();
+            self.offset = 0;
+            if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+                return Ok(false);
+            }
+            self.line += 1;
+        }
+    }
+
+    fn get_token(&self) -> &[u8] {
+        &self.token
+    }
+
+    fn get_token_string(&self) -> String {
+        String::from_utf8_lossy(&self.token).to_string()
+    }
+}
+
+pub struct WaveformVcdReader<R: BufRead> {
+    tokenizer: VcdTokenizer<R>,
+    identifiers: HashMap<Vec<u8>, usize>,
+    timescale: Option<WaveformTimescale>,
+    value: Vec<u8>,
+}
+
+impl<R: BufRead> WaveformVcdReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_identifiers(reader, HashMap::new())
+    }
+
+    /// Creates a reader for a body without a header, using the identifier
+    /// codes to signal ids mapping from an already read header
+    pub fn with_identifiers(reader: R, identifiers: HashMap<Vec<u8>, usize>) -> Self {
+        Self {
+            tokenizer: VcdTokenizer::new(reader),
+            identifiers,
+            timescale: None,
+            value: Vec::new(),
+        }
+    }
+
+    /// Returns the mapping from identifier codes to signal ids
+    pub fn get_identifiers(&self) -> &HashMap<Vec<u8>, usize> {
+        &self.identifiers
+    }
+
+    /// Returns the timescale if one was declared in the header, which is also
+    /// set on the waveform it was read into
+    pub fn get_timescale(&self) -> Option<WaveformTimescale> {
+        self.timescale
+    }
+
+    /// Returns the line number of the last token read
+    pub fn get_line(&self) -> usize {
+        self.tokenizer.line
+    }
+
+    /// Reads an entire VCD into a new waveform
+    pub fn read(mut self) -> WaveformResult<Waveform> {
+        let mut waveform = Waveform::new();
+        self.read_header(&mut waveform)?;
+        self.read_body(&mut waveform)?;
+        Ok(waveform)
+    }
+
+    // Returns the unread rest of the current line, the input after it, and
+    // the line number to continue from, used to hand the body off to other
+    // readers once the header has been read
+    pub(crate) fn into_remaining(mut self) -> (Vec<u8>, R, usize) {
+        let remaining = self.tokenizer.buffer.split_off(self.tokenizer.offset);
+        // The rest of the current line is counted again by the next reader
+        let line = if remaining.is_empty() {
+            self.tokenizer.line
+        } else {
+            self.tokenizer.line - 1
+        };
+        (remaining, self.tokenizer.reader, line)
+    }
+
+    // Continues line numbering from where a previous reader stopped
+    pub(crate) fn set_line(&mut self, line: usize) {
+        self.tokenizer.line = line;
+    }
+
+    fn error(&self, error: WaveformVcdError) -> WaveformError {
+        WaveformError::InvalidVcd {
+            line: self.tokenizer.line,
+            error,
+        }
+    }
+
+    fn next_token(&mut self) -> WaveformResult<()> {
+        if self.tokenizer.next()? {
+            Ok(())
+        } else {
+            Err(self.error(WaveformVcdError::UnexpectedEnd))
+        }
+    }
+
+    // Collects the tokens of a command up until its $end
+    fn read_command(&mut self) -> WaveformResult<Vec<String>> {
+        let mut tokens = Vec::new();
+        loop {
+            self.next_token()?;
+            if self.tokenizer.get_token() == b"$end" {
+                return Ok(tokens);
+            }
+            tokens.push(self.tokenizer.get_token_string());
+        }
+    }
+
+    fn skip_command(&mut self) -> WaveformResult<()> {
+        loop {
+            self.next_token()?;
+            if self.tokenizer.get_token() == b"$end" {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads the declarations up to and including $enddefinitions, adding the
+    /// scopes and variables to the hierarchy and initializing a signal for
+    /// each new identifier code
+    pub fn read_header(&mut self, waveform: &mut Waveform) -> WaveformResult<()> {
+        loop {
+            self.next_token()?;
+            match self.tokenizer.get_token() {
+                b"$enddefinitions" => return self.skip_command(),
+                b"$scope" => {
+                    let tokens = self.read_command()?;
+                    let [scope_type, name] = tokens.as_slice() else {
+                        return Err(self.error(WaveformVcdError::UnexpectedToken {
+                            token: tokens.join(" "),
+                        }));
+                    };
+                    // Scope types added by other tools are kept as modules
+                    let scope_type = WaveformScopeType::from_str(scope_type)
+                        .unwrap_or(WaveformScopeType::Module);
+                    waveform.get_hierarchy_mut().push_scope(name, scope_type);
+                }
+                b"$upscope" => {
+                    self.skip_command()?;
+                    if waveform.get_hierarchy_mut().pop_scope().is_none() {
+                        return Err(self.error(WaveformVcdError::UnbalancedScope));
+                    }
+                }
+                b"$var" => {
+                    let tokens = self.read_command()?;
+                    self.read_variable(waveform, &tokens)?;
+                }
+                b"$timescale" => {
+                    let tokens = self.read_command()?;
+                    self.read_timescale(waveform, &tokens.concat())?;
+                }
+                token if token.starts_with(b"$") => self.skip_command()?,
+                _ => {
+                    return Err(self.error(WaveformVcdError::UnexpectedToken {
+                        token: self.tokenizer.get_token_string(),
+                    }))
+                }
+            }
+        }
+    }
+
+    fn read_timescale(&mut self, waveform: &mut Waveform, timescale: &str) -> WaveformResult<()> {
+        let Some(parsed) = WaveformTimescale::parse(timescale) else {
+            return Err(self.error(WaveformVcdError::InvalidTimescale {
+                timescale: timescale.to_string(),
+            }));
+        };
+        self.timescale = Some(parsed);
+        waveform.set_timescale(Some(parsed));
+        Ok(())
+    }
+
+    fn read_variable(&mut self, waveform: &mut Waveform, tokens: &[String]) -> WaveformResult<()> {
+        let [variable_type, width, code, name, range @ ..] = tokens else {
+            return Err(self.error(WaveformVcdError::UnexpectedToken {
+                token: tokens.join(" "),
+            }));
+        };
+        // Variable types added by other tools are kept as wires, unless they
+        // are named as a kind of real
+        let variable_type = WaveformVariableType::from_str(variable_type).unwrap_or(
+            if variable_type.to_ascii_lowercase().contains("real") {
+                WaveformVariableType::Real
+            } else {
+                WaveformVariableType::Wire
+            },
+        );
+        let width = match width.parse::<usize>() {
+            Ok(width) if width > 0 => width,
+            _ => {
+                return Err(self.error(WaveformVcdError::InvalidWidth {
+                    width: width.clone(),
+                }))
+            }
+        };
+        // The bit range is either a separate token or attached to the name
+        let (name, range) = if !range.is_empty() {
+            let range = range.concat();
+            let Some(range) = WaveformBitRange::parse(&range) else {
+                return Err(self.error(WaveformVcdError::InvalidRange { range }));
+            };
+            (name.as_str(), Some(range))
+        } else {
+            WaveformBitRange::split_name(name)
+        };
+        let id = if let Some(id) = self.identifiers.get(code.as_bytes()) {
+            // Aliases of an existing signal must agree on the signal kind
+            match (waveform.get_vector_signal(*id), variable_type.is_real()) {
+                (Some(signal), false) if signal.get_width() != width => {
+                    return Err(self.error(WaveformVcdError::InvalidWidth {
+                        width: width.to_string(),
+                    }));
+                }
+                (Some(_), true) | (None, false) => {
+                    return Err(self.error(WaveformVcdError::MismatchedSignalType {
+                        identifier: code.clone(),
+                    }));
+                }
+                _ => {}
+            }
+            *id
+        } else {
+            let id = self.identifiers.len();
+            self.identifiers.insert(code.as_bytes().to_vec(), id);
+            if variable_type.is_real() {
+                waveform.initialize_real(id);
+            } else {
+                waveform.initialize_vector(id, width);
+            }
+            id
+        };
+        waveform
+            .get_hierarchy_mut()
+            .add_variable(WaveformVariable::new(
+                name,
+                id,
+                variable_type,
+                width,
+                range,
+                WaveformVariableDirection::Implicit,
+            ));
+        Ok(())
+    }
+
+    /// Reads the entire body, inserting every timestamp and value change
+    pub fn read_body(&mut self, waveform: &mut Waveform) -> WaveformResult<()> {
+        while self.read_next(waveform)? {}
+        Ok(())
+    }
+
+    /// Reads the next timestamp, value change, or command from the body,
+    /// returning false once the end of the input is reached
+    pub fn read_next(&mut self, waveform: &mut Waveform) -> WaveformResult<bool> {
+        if !self.tokenizer.next()? {
+            return Ok(false);
+        }
+        let token = self.tokenizer.get_token();
+        match token[0] {
+            b'#' => {
+                let timestamp = std::str::from_utf8(&token[1..])
+                    .ok()
+                    .and_then(|t| t.parse::<u64>().ok())
+                    .ok_or_else(|| {
+                        self.error(WaveformVcdError::InvalidTimestamp {
+                            timestamp: self.tokenizer.get_token_string(),
+                        })
+                    })?;
+                waveform.insert_timestamp(timestamp)?;
+            }
+            b'$' => match token {
+                // Value changes inside these are handled like any other
+                b"$dumpvars" | b"$dumpoff" | b"$dumpon" | b"$dumpall" | b"$end" => {}
+                b"$comment" => self.skip_command()?,
+                _ => {
+                    return Err(self.error(WaveformVcdError::UnknownCommand {
+                        command: self.tokenizer.get_token_string(),
+                    }))
+                }
+            },
+            b'0' | b'1' | b'x' | b'X' | b'z' | b'Z' => {
+                self.value.clear();
+                self.value.push(token[0]);
+                let id = self.get_id(&token[1..])?;
+                Self::insert_initial_timestamp(waveform)?;
+                self.update_vector(waveform, id)?;
+            }
+            b'b' | b'B' => {
+                self.value.clear();
+                self.value.extend_from_slice(&token[1..]);
+                self.next_token()?;
+                let id = self.get_id(self.tokenizer.get_token())?;
+                Self::insert_initial_timestamp(waveform)?;
+                self.update_vector(waveform, id)?;
+            }
+            b'r' | b'R' => {
+                self.value.clear();
+                self.value.extend_from_slice(&token[1..]);
+                self.next_token()?;
+                let id = self.get_id(self.tokenizer.get_token())?;
+                Self::insert_initial_timestamp(waveform)?;
+                let value = std::str::from_utf8(&self.value)
+                    .ok()
+                    .and_then(|v| v.parse::<f64>().ok());
+                match (value, waveform.get_real_signal(id)) {
+                    (Some(value), Some(_)) => waveform.update_real(id, value)?,
+                    _ => return Err(self.invalid_value()),
+                }
+            }
+            _ => {
+                return Err(self.error(WaveformVcdError::UnexpectedToken {
+                    token: self.tokenizer.get_token_string(),
+                }))
+            }
+        }
+        Ok(true)
+    }
+
+    fn get_id(&self, code: &[u8]) -> WaveformResult<usize> {
+        self.identifiers.get(code).cloned().ok_or_else(|| {
+            self.error(WaveformVcdError::UnknownIdentifier {
+                identifier: String::from_utf8_lossy(code).to_string(),
+            })
+        })
+    }
+
+    fn invalid_value(&self) -> WaveformError {
+        self.error(WaveformVcdError::InvalidValue {
+            value: String::from_utf8_lossy(&self.value).to_string(),
+        })
+    }
+
+    // Values before the first timestamp are treated as being at time zero
+    fn insert_initial_timestamp(waveform: &mut Waveform) -> WaveformResult<()> {
+        if waveform.timestamps_count() == 0 {
+            waveform.insert_timestamp(0)?;
+        }
+        Ok(())
+    }
+
+    fn update_vector(&mut self, waveform: &mut Waveform, id: usize) -> WaveformResult<()> {
+        let Some(signal) = waveform.get_vector_signal(id) else {
+            return Err(self.invalid_value());
+        };
+        let width = signal.get_width();
+        if self.value.is_empty() || self.value.len() > width {
+            return Err(self.invalid_value());
+        }
+        let mut four_state = false;
+        for b in &self.value {
+            match b {
+                b'0' | b'1' => {}
+                b'x' | b'X' | b'z' | b'Z' => four_state = true,
+                _ => return Err(self.invalid_value()),
+            }
+        }
+        // Shorter values are extended with X or Z when that is their leftmost
+        // bit, otherwise the missing bits are zero
+        let bv = if !four_state {
+            BitVector::from_ascii(&self.value)
+        } else if self.value.len() < width && matches!(self.value[0], b'x' | b'X' | b'z' | b'Z') {
+            let mut extended = vec![self.value[0]; width - self.value.len()];
+            extended.extend_from_slice(&self.value);
+            BitVector::from_ascii_four_state(&extended)
+        } else {
+            BitVector::from_ascii_four_state(&self.value)
+        };
+        waveform.update_vector(id, bv)
+    }
+}
+
+/// Reads an entire VCD into a new waveform
+pub fn read_vcd<R: BufRead>(reader: R) -> WaveformResult<Waveform> {
+    WaveformVcdReader::new(reader).read()
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::ops::Range;
+
+use crate::bitvector::BitVector;
+use crate::errors::*;
+use crate::hierarchy::*;
+use crate::query::WaveformQuery;
+use crate::timescale::WaveformTimescale;
+use crate::{Waveform, WaveformSearchMode, WaveformSignalResult, WaveformValueResult};
+
+// Identifier codes are made from the printable ASCII characters
+const CODE_FIRST: u8 = b'!';
+const CODE_COUNT: usize = (b'~' - b'!' + 1) as usize;
+
+/// Returns the shortest identifier code for the index, with the first 94
+/// indices getting a single character
+pub fn get_identifier_code(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((CODE_FIRST + (index % CODE_COUNT) as u8) as char);
+        index /= CODE_COUNT;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+// IEEE 1364 only knows a few scope types, so the others are written as the
+// closest of them
+fn get_vcd_scope_type(scope_type: WaveformScopeType) -> WaveformScopeType {
+    use WaveformScopeType::*;
+    match scope_type {
+        Module | Task | Function | Begin | Fork => scope_type,
+        Class | Interface | Package | Program | VhdlArchitecture | VhdlPackage => Module,
+        VhdlProcedure => Task,
+        VhdlFunction => Function,
+        Generate | Struct | Union | VhdlRecord | VhdlProcess | VhdlBlock | VhdlForGenerate
+        | VhdlIfGenerate | VhdlGenerate | SvArray => Begin,
+    }
+}
+
+// Likewise for the variable types, where SystemVerilog variables become regs
+// and the integer types keep their declared width
+fn get_vcd_variable_type(variable_type: WaveformVariableType) -> WaveformVariableType {
+    use WaveformVariableType::*;
+    match variable_type {
+        Logic | Bit | Enum | String | SparseArray => Reg,
+        Int | ShortInt | LongInt | Byte => Integer,
+        RealParameter | ShortReal => Real,
+        Port => Wire,
+        _ => variable_type,
+    }
+}
+
+pub struct WaveformVcdWriter<'a> {
+    waveform: &'a Waveform,
+    range: Option<Range<u64>>,
+    selected: Option<HashSet<usize>>,
+    timescale: Option<WaveformTimescale>,
+}
+
+impl<'a> WaveformVcdWriter<'a> {
+    pub fn new(waveform: &'a Waveform) -> Self {
+        Self {
+            waveform,
+            range: None,
+            selected: None,
+            timescale: None,
+        }
+    }
+
+    /// Restricts the body to the timestamps inside the range, where the values
+    /// in effect at the first of them are written out in $dumpvars
+    pub fn set_range(&mut self, range: Option<Range<u64>>) {
+        self.range = range;
+    }
+
+    /// Restricts the output to only the given signal ids, leaving out the
+    /// variables of every other signal
+    pub fn set_selected(&mut self, selected: Option<HashSet<usize>>) {
+        self.selected = selected;
+    }
+
+    /// Sets the timescale declared in place of the one of the waveform, where
+    /// every timestamp is converted to it. Writing fails if it is coarser than
+    /// the timescale of the waveform, as with `Waveform::rescale`
+    pub fn set_timescale(&mut self, timescale: Option<WaveformTimescale>) {
+        self.timescale = timescale;
+    }
+
+    fn is_selected(&self, id: usize) -> bool {
+        self.waveform.get_stored_signal(id).is_some()
+            && self
+                .selected
+                .as_ref()
+                .map(|selected| selected.contains(&id))
+                .unwrap_or(true)
+    }
+
+    /// Writes the waveform as a VCD, which should be given a buffered writer.
+    /// Only stored signals are written, leaving out derived signals
+    pub fn write<W: Write>(&self, writer: &mut W) -> WaveformResult<()> {
+        let mut ids = self
+            .waveform
+            .vector_signals
+            .keys()
+            .chain(self.waveform.real_signals.keys())
+            .cloned()
+            .filter(|id| self.is_selected(*id))
+            .collect::<Vec<usize>>();
+        ids.sort_unstable();
+        let range = self.get_timestamp_range();
+        let ratio = self.waveform.get_written_ratio(self.timescale, range.end)?;
+        let codes = ids
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (*id, get_identifier_code(index)))
+            .collect::<HashMap<usize, String>>();
+        self.write_header(writer, &ids, &codes)?;
+        self.write_body(writer, &ids, &codes, range, ratio)
+    }
+
+    fn get_timestamp_range(&self) -> Range<usize> {
+        let timestamps = self.waveform.get_timestamps();
+        match &self.range {
+            Some(range) => {
+                timestamps.partition_point(|t| *t < range.start)
+                    ..timestamps.partition_point(|t| *t < range.end)
+            }
+            None => 0..timestamps.len(),
+        }
+    }
+
+    fn write_header<W: Write>(
+        &self,
+        writer: &mut W,
+        ids: &[usize],
+        codes: &HashMap<usize, String>,
+    ) -> WaveformResult<()> {
+        writeln!(
+            writer,
+            "$version {} {} $end",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        if let Some(timescale) = self.timescale.or(self.waveform.get_timescale()) {
+            writeln!(writer, "$timescale {timescale} $end")?;
+        }
+        let hierarchy = self.waveform.get_hierarchy();
+        for index in hierarchy.get_root_variables() {
+            self.write_variable(writer, hierarchy.get_variable(*index).unwrap(), codes)?;
+        }
+        for index in hierarchy.get_root_scopes() {
+            self.write_scope(writer, hierarchy, *index, codes)?;
+        }
+        // Signals missing from the hierarchy still need a declaration
+        for id in ids {
+            if hierarchy.get_variables_by_id(*id).is_empty() {
+                let (variable_type, width) = match self.waveform.get_stored_signal(*id) {
+                    Some(WaveformSignalResult::Vector(signal)) => {
+                        (WaveformVariableType::Wire, signal.get_width())
+                    }
+                    _ => (WaveformVariableType::Real, 64),
+                };
+                let variable = WaveformVariable::new(
+                    &format!("signal_{id}"),
+                    *id,
+                    variable_type,
+                    width,
+                    None,
+                    WaveformVariableDirection::Implicit,
+                );
+                self.write_variable(writer, &variable, codes)?;
+            }
+        }
+        writeln!(writer, "$enddefinitions $end")?;
+        Ok(())
+    }
+
+    fn write_scope<W: Write>(
+        &self,
+        writer: &mut W,
+        hierarchy: &WaveformHierarchy,
+        index: usize,
+        codes: &HashMap<usize, String>,
+    ) -> WaveformResult<()> {
+        let scope = hierarchy.get_scope(index).unwrap();
+        writeln!(
+            writer,
+            "$scope {} {} $end",
+            get_vcd_scope_type(scope.get_scope_type()).to_str(),
+            scope.get_name()
+        )?;
+        for index in scope.get_variables() {
+            self.write_variable(writer, hierarchy.get_variable(*index).unwrap(), codes)?;
+        }
+        for index in scope.get_scopes() {
+            self.write_scope(writer, hierarchy, *index, codes)?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        Ok(())
+    }
+
+    fn write_variable<W: Write>(
+        &self,
+        writer: &mut W,
+        variable: &WaveformVariable,
+        codes: &HashMap<usize, String>,
+    ) -> WaveformResult<()> {
+        let Some(code) = codes.get(&variable.get_id()) else {
+            return Ok(());
+        };
+        // The declared width always matches the stored signal
+        let width = match self.waveform.get_stored_signal(variable.get_id()) {
+            Some(WaveformSignalResult::Vector(signal)) => signal.get_width(),
+            _ => 64,
+        };
+        write!(
+            writer,
+            "$var {} {} {} {}",
+            get_vcd_variable_type(variable.get_variable_type()).to_str(),
+            width,
+            code,
+            variable.get_name()
+        )?;
+        match variable.get_range() {
+            Some(WaveformBitRange { msb, lsb }) if msb == lsb => write!(writer, " [{msb}]")?,
+            Some(WaveformBitRange { msb, lsb }) => write!(writer, " [{msb}:{lsb}]")?,
+            None => {}
+        }
+        writeln!(writer, " $end")?;
+        Ok(())
+    }
+
+    fn write_body<W: Write>(
+        &self,
+        writer: &mut W,
+        ids: &[usize],
+        codes: &HashMap<usize, String>,
+        Range { start, end }: Range<usize>,
+        ratio: u64,
+    ) -> WaveformResult<()> {
+        let timestamps = self.waveform.get_timestamps();
+        if start >= end {
+            return Ok(());
+        }
+
+        // Every signal starts from its value at the first timestamp, or X if
+        // it has not been written yet
+        writeln!(writer, "#{}\n$dumpvars", timestamps[start] * ratio)?;
+        for id in ids {
+            match self
+                .waveform
+                .search_value(*id, start, WaveformSearchMode::Before)
+            {
+                Some(value) => write_value(writer, &value, &codes[id])?,
+                None => {
+                    if let Some(signal) = self.waveform.get_vector_signal(*id) {
+                        let value =
+                            BitVector::from_ascii_four_state(&vec![b'x'; signal.get_width()]);
+                        write_value(
+                            writer,
+                            &WaveformValueResult::Vector(value, start),
+                            &codes[id],
+                        )?;
+                    }
+                }
+            }
+        }
+        writeln!(writer, "$end")?;
+
+        // The remaining changes of every signal are merged in timestamp order
+        let mut events = self.waveform.iter_events(ids, start + 1..end).peekable();
+        for (timestamp_index, timestamp) in timestamps.iter().enumerate().take(end).skip(start + 1)
+        {
+            writeln!(writer, "#{}", timestamp * ratio)?;
+            while let Some((_, id, value)) =
+                events.next_if(|(_, _, value)| value.get_timestamp_index() == timestamp_index)
+            {
+                write_value(writer, &value, &codes[&id])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_value<W: Write>(
+    writer: &mut W,
+    value: &WaveformValueResult,
+    code: &str,
+) -> WaveformResult<()> {
+    match value {
+        WaveformValueResult::Vector(bv, _) => {
+            let bits = (0..bv.get_bit_width())
+                .rev()
+                .map(|i| bv.get_bit(i).to_str().to_ascii_lowercase())
+                .collect::<String>();
+            if bits.len() == 1 {
+                writeln!(writer, "{bits}{code}")?;
+            } else {
+                writeln!(writer, "b{bits} {code}")?;
+            }
+        }
+        // Non-finite values are spelled the way C strtod reads them
+        WaveformValueResult::Real(r, _) if r.is_nan() => writeln!(writer, "rnan {code}")?,
+        WaveformValueResult::Real(r, _) if r.is_infinite() => {
+            let sign = if r.is_sign_negative() { "-" } else { "" };
+            writeln!(writer, "r{sign}inf {code}")?
+        }
+        WaveformValueResult::Real(r, _) => writeln!(writer, "r{r} {code}")?,
+    }
+    Ok(())
+}
+
+/// Writes an entire waveform as a VCD
+pub fn write_vcd<W: Write>(waveform: &Waveform, writer: &mut W) -> WaveformResult<()> {
+    WaveformVcdWriter::new(waveform).write(writer)
+}
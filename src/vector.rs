@@ -1,5 +1,8 @@
+use std::ops::Range;
+
 use crate::bitvector::BitVector;
 use crate::history::WaveformHistory;
+use crate::WaveformSearchMode;
 
 #[derive(Clone, Debug, PartialEq)]
 enum WaveformVectorPacking {
@@ -20,13 +23,13 @@ impl WaveformVectorPacking {
     }
 }
 
-pub struct WaveformSignalVector {
+pub struct WaveformSignalVector<B = Vec<u8>> {
     // How many bits wide is the signal
     width: usize,
     // How many bytes are used to store the four-state vector
     packing: WaveformVectorPacking,
-    history: WaveformHistory,
-    vectors: Vec<u8>,
+    history: WaveformHistory<B>,
+    vectors: B,
     vector_index: usize,
     bits_unused: usize,
 }
@@ -43,35 +46,54 @@ impl WaveformSignalVector {
         }
     }
 
-    pub fn get_history(&self) -> &WaveformHistory {
-        &self.history
-    }
-
+    /// Adds a value change at the given timestamp index, replacing the last
+    /// value instead if it was also at that timestamp index
     pub fn update(&mut self, timestamp_index: usize, bv: BitVector) {
+        if self.history.get_last_timestamp_index() == Some(timestamp_index) {
+            self.write_bitvector(self.vector_index - 1, bv);
+            return;
+        }
         self.history.add_change(timestamp_index, self.vector_index);
-        let offset = self.vectors.len();
+        self.push_bitvector(bv);
+    }
+
+    fn push_bitvector(&mut self, bv: BitVector) {
         match self.packing {
             WaveformVectorPacking::Bits(bits) => {
-                let combined_mask = (1 << (bits / 2)) - 1;
-                let (value, mask) = bv.to_bits_four_state::<u8>();
-                let combined =
-                    ((value & combined_mask) | ((mask & combined_mask) << (bits / 2))) as u8;
-                match self.bits_unused {
-                    2 => self.vectors[offset - 1] |= combined << 6,
-                    4 => self.vectors[offset - 1] |= combined << 4,
-                    6 => self.vectors[offset - 1] |= combined << 2,
-                    _ => {
-                        self.vectors.push(combined);
-                        self.bits_unused = 8;
-                    }
+                if self.bits_unused == 0 {
+                    self.vectors.push(0);
+                    self.bits_unused = 8;
                 }
                 self.bits_unused -= bits;
             }
             WaveformVectorPacking::Bytes(bytes) => {
+                self.vectors.resize(self.vectors.len() + bytes, 0);
+            }
+        }
+        self.write_bitvector(self.vector_index, bv);
+        self.vector_index += 1;
+    }
+
+    fn write_bitvector(&mut self, index: usize, bv: BitVector) {
+        match self.packing {
+            WaveformVectorPacking::Bits(bits) => {
+                let combined_mask = (1 << (bits / 2)) - 1;
+                let (value, mask) = bv.to_bits_four_state::<u8>();
+                let combined = (value & combined_mask) | ((mask & combined_mask) << (bits / 2));
+                let vectors_per_byte = 8 / bits;
+                let byte_index = index / vectors_per_byte;
+                let bit_index = (index % vectors_per_byte) * bits;
+                let bit_mask = (((1u16 << bits) - 1) << bit_index) as u8;
+                self.vectors[byte_index] =
+                    (self.vectors[byte_index] & !bit_mask) | (combined << bit_index);
+            }
+            WaveformVectorPacking::Bytes(bytes) => {
+                let offset = bytes * index;
                 let byte_width = ((bv.get_bit_width() - 1) / 8) + 1;
-                self.vectors.resize(offset + bytes, 0);
                 let (value_vector, mask_vector) =
                     self.vectors[offset..offset + bytes].split_at_mut(bytes / 2);
+                value_vector.fill(0);
+                mask_vector.fill(0);
                 // Compensate for incoming vectors that are shorter than the allocated space
                 bv.to_be_bytes_four_state(
                     &mut value_vector[(bytes / 2 - byte_width)..(bytes / 2)],
@@ -79,7 +101,114 @@ impl WaveformSignalVector {
                 );
             }
         }
-        self.vector_index += 1;
+    }
+
+    /// Appends the changes of another signal of the same width, offsetting its
+    /// timestamp indices. The first change of the other signal is dropped if
+    /// it only repeats the last value of this signal
+    pub fn append(&mut self, other: WaveformSignalVector, timestamp_offset: usize) {
+        self.append_changes(other, timestamp_offset, true);
+    }
+
+    // Appends the changes of another signal, only dropping a repeated first
+    // value when asked to
+    pub(crate) fn append_changes(
+        &mut self,
+        mut other: WaveformSignalVector,
+        timestamp_offset: usize,
+        drop_repeated: bool,
+    ) {
+        debug_assert_eq!(self.width, other.width);
+        if other.history.get_block_count() == 0 {
+            return;
+        }
+        let mut start = other.history.get_block(0).get_value_index();
+        if drop_repeated
+            && !self.is_empty()
+            && self.get_bitvector(self.len() - 1) == other.get_bitvector(start)
+        {
+            other.history.remove_first_change();
+            start += 1;
+        }
+        self.history
+            .append(&other.history, timestamp_offset, self.vector_index);
+        match self.packing {
+            // Packed values no longer line up with bytes so they are re-packed
+            WaveformVectorPacking::Bits(_) => {
+                for index in start..other.len() {
+                    self.push_bitvector(other.get_bitvector(index));
+                }
+            }
+            WaveformVectorPacking::Bytes(bytes) => {
+                self.vectors
+                    .extend_from_slice(&other.vectors[start * bytes..]);
+                self.vector_index += other.len() - start;
+            }
+        }
+    }
+
+    /// Replaces every timestamp index of this signal with its entry in the
+    /// given table, which must be strictly increasing
+    pub fn remap_timestamps(&mut self, timestamp_indices: &[usize]) {
+        self.history.remap_timestamps(timestamp_indices);
+    }
+
+    /// Drops the history and values before the change in effect at the given
+    /// timestamp index, rebasing the remaining timestamp indices to start there
+    pub fn truncate_before(&mut self, timestamp_index: usize) {
+        match self.packing {
+            WaveformVectorPacking::Bits(bits) => {
+                let vectors_per_byte = 8 / bits;
+                let dropped = self
+                    .history
+                    .truncate_before(timestamp_index, vectors_per_byte);
+                self.vectors.drain(..dropped / vectors_per_byte);
+                self.vector_index -= dropped;
+            }
+            WaveformVectorPacking::Bytes(bytes) => {
+                let dropped = self.history.truncate_before(timestamp_index, 1);
+                self.vectors.drain(..dropped * bytes);
+                self.vector_index -= dropped;
+            }
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> WaveformSignalVector<B> {
+    /// Creates a signal from its history and packed values, returning None if
+    /// the values are not the size the packing for this width requires
+    pub(crate) fn from_parts(
+        width: usize,
+        history: WaveformHistory<B>,
+        vectors: B,
+        len: usize,
+    ) -> Option<Self> {
+        let packing = WaveformVectorPacking::new(width);
+        let (size, bits_unused) = match packing {
+            WaveformVectorPacking::Bits(bits) => {
+                let vectors_per_byte = 8 / bits;
+                (
+                    len.div_ceil(vectors_per_byte),
+                    (vectors_per_byte - len % vectors_per_byte) % vectors_per_byte * bits,
+                )
+            }
+            WaveformVectorPacking::Bytes(bytes) => (len.checked_mul(bytes)?, 0),
+        };
+        if width == 0 || vectors.as_ref().len() != size {
+            return None;
+        }
+        Some(Self {
+            width,
+            packing,
+            history,
+            vectors,
+            vector_index: len,
+            bits_unused,
+        })
+    }
+
+    pub fn get_history(&self) -> &WaveformHistory<B> {
+        &self.history
     }
 
     pub fn get_bitvector(&self, index: usize) -> BitVector {
@@ -89,7 +218,7 @@ impl WaveformSignalVector {
                 let vectors_per_byte = 8 / bits;
                 let byte_index = index / vectors_per_byte;
                 let bit_index = (index % vectors_per_byte) * bits;
-                let byte = self.vectors[byte_index];
+                let byte = self.vectors.as_ref()[byte_index];
                 let value = (byte >> bit_index) & bit_mask;
                 let mask = (byte >> (bit_index + bits / 2)) & bit_mask;
                 BitVector::from_bits_four_state(self.get_width(), value, mask)
@@ -98,15 +227,108 @@ impl WaveformSignalVector {
                 let offset = bytes * index;
                 BitVector::from_be_bytes_four_state(
                     self.get_width(),
-                    &self.vectors[offset..offset + bytes / 2],
-                    &self.vectors[offset + bytes / 2..offset + bytes],
+                    &self.vectors.as_ref()[offset..offset + bytes / 2],
+                    &self.vectors.as_ref()[offset + bytes / 2..offset + bytes],
                 )
             }
         }
     }
 
+    /// Returns the (timestamp index, value) pairs for every change of this
+    /// signal with a timestamp index inside the given range
+    pub fn iter_range(&self, range: Range<usize>) -> impl Iterator<Item = (usize, BitVector)> + '_ {
+        self.history.iter_range(range).map(|index| {
+            (
+                index.get_timestamp_index(),
+                self.get_bitvector(index.get_value_index()),
+            )
+        })
+    }
+
+    /// Returns the (timestamp index, bits) pairs for every change with a
+    /// timestamp index inside the given range where the selected bits differ
+    /// from the bits in effect before it, skipping changes to other bits
+    pub fn iter_bit_changes(
+        &self,
+        range: Range<usize>,
+        bits: Range<usize>,
+    ) -> impl Iterator<Item = (usize, BitVector)> + '_ {
+        let mut previous = range
+            .start
+            .checked_sub(1)
+            .and_then(|timestamp_index| {
+                self.history
+                    .search_timestamp_index(timestamp_index, WaveformSearchMode::Before)
+            })
+            .map(|index| {
+                self.get_bitvector(index.get_value_index())
+                    .get_range(bits.clone())
+            });
+        self.iter_range(range)
+            .filter_map(move |(timestamp_index, bv)| {
+                let bv = bv.get_range(bits.clone());
+                if previous.as_ref() == Some(&bv) {
+                    return None;
+                }
+                previous = Some(bv.clone());
+                Some((timestamp_index, bv))
+            })
+    }
+
+    /// Searches for the change that set the selected bits to their value at
+    /// the given timestamp index, or for the change of those bits nearest to
+    /// it depending on the search mode, ignoring changes to any other bits
+    pub fn search_bit_change(
+        &self,
+        timestamp_index: usize,
+        search_mode: WaveformSearchMode,
+        bits: Range<usize>,
+    ) -> Option<(usize, BitVector)> {
+        let before = || {
+            let mut iter = self
+                .history
+                .iter_before(timestamp_index.saturating_add(1))
+                .map(|index| {
+                    let bv = self.get_bitvector(index.get_value_index());
+                    (index.get_timestamp_index(), bv.get_range(bits.clone()))
+                })
+                .peekable();
+            while let Some((timestamp_index, bv)) = iter.next() {
+                if iter.peek().map(|(_, previous)| previous) != Some(&bv) {
+                    return Some((timestamp_index, bv));
+                }
+            }
+            None
+        };
+        let after = || {
+            self.iter_bit_changes(timestamp_index..usize::MAX, bits.clone())
+                .next()
+        };
+        match search_mode {
+            WaveformSearchMode::Before => before(),
+            WaveformSearchMode::After => after(),
+            WaveformSearchMode::Exact => before().filter(|(index, _)| *index == timestamp_index),
+            WaveformSearchMode::Closest => match (before(), after()) {
+                (Some(before), Some(after)) => {
+                    if after.0 - timestamp_index < timestamp_index - before.0 {
+                        Some(after)
+                    } else {
+                        Some(before)
+                    }
+                }
+                (before, after) => before.or(after),
+            },
+        }
+    }
+
+    /// Returns the packed values, which are indexed by the value indices of
+    /// the history
+    pub fn get_vectors(&self) -> &[u8] {
+        self.vectors.as_ref()
+    }
+
     pub fn get_vector_size(&self) -> usize {
-        self.vectors.len()
+        self.vectors.as_ref().len()
     }
 
     pub fn get_width(&self) -> usize {
@@ -1,11 +1,12 @@
+#![allow(clippy::identity_op)]
+
 use makai_waveform_db::history::{index::WaveformHistoryIndex, WaveformHistory};
 
 fn generate_history(num: usize, start: usize) -> (WaveformHistory, Vec<WaveformHistoryIndex>) {
     let mut history = WaveformHistory::new();
     let mut timestamp_index = start;
-    let mut value_index = 0;
     let mut expected = Vec::new();
-    for _ in 0..num {
+    for value_index in 0..num {
         expected.push(WaveformHistoryIndex {
             timestamp_index,
             value_index,
@@ -16,7 +17,6 @@ fn generate_history(num: usize, start: usize) -> (WaveformHistory, Vec<WaveformH
             delta = rand::random::<u8>();
         }
         timestamp_index += delta as usize;
-        value_index += 1;
     }
     (history, expected)
 }
@@ -126,7 +126,7 @@ fn test_waveform_history_seek() {
         iter.seek(expected[i].get_timestamp_index()),
         Some(expected[i].clone()),
     );
-    assert_eq!(iter.next(), expected.get(i + 1).map(|i| i.clone()));
+    assert_eq!(iter.next(), expected.get(i + 1).cloned());
 
     // The chose value has one after and a gap in the timestamps
     if i < expected.len() - 1
@@ -135,7 +135,7 @@ fn test_waveform_history_seek() {
         let mut iter = history.into_iter();
         let seeked = iter.seek(expected[i].get_timestamp_index() + 1);
         assert_eq!(seeked, Some(expected[i].clone()),);
-        assert_eq!(iter.next(), expected.get(i + 1).map(|i| i.clone()));
+        assert_eq!(iter.next(), expected.get(i + 1).cloned());
     }
 
     // Check for last index by looking for one after
@@ -274,3 +274,2858 @@ fn test_waveform_search_timestamp() {
     assert_eq!(waveform.search_timestamp(25, Mode::Closest), Some(3));
     assert_eq!(waveform.search_timestamp(26, Mode::Closest), Some(3));
 }
+
+#[test]
+fn test_waveform_history_iter_range() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+
+    let (history, expected) = generate_history(1 << 16, 0);
+    let last = expected.last().unwrap().get_timestamp_index();
+    for _ in 0..16 {
+        let start = rng.gen_range(0..last);
+        let end = rng.gen_range(start..last + 2);
+        let range_expected = expected
+            .iter()
+            .filter(|i| (start..end).contains(&i.get_timestamp_index()))
+            .cloned()
+            .collect::<Vec<WaveformHistoryIndex>>();
+        assert_eq!(
+            history
+                .iter_range(start..end)
+                .collect::<Vec<WaveformHistoryIndex>>(),
+            range_expected
+        );
+    }
+
+    // Ranges covering everything, nothing, or starting at an exact change
+    assert_eq!(history.iter_range(0..usize::MAX).count(), expected.len());
+    assert_eq!(history.iter_range(last + 1..usize::MAX).count(), 0);
+    let i = rng.gen_range(0..expected.len());
+    assert_eq!(
+        history
+            .iter_range(expected[i].get_timestamp_index()..usize::MAX)
+            .next(),
+        Some(expected[i].clone())
+    );
+
+    // Empty histories have nothing to iterate
+    let history = WaveformHistory::new();
+    assert_eq!(history.iter_range(0..usize::MAX).count(), 0);
+    assert_eq!(history.into_iter().count(), 0);
+}
+
+#[test]
+fn test_waveform_signal_iter_range() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::real::WaveformSignalReal;
+    use makai_waveform_db::vector::WaveformSignalVector;
+
+    let mut vector = WaveformSignalVector::new(8);
+    let mut real = WaveformSignalReal::new();
+    for i in 0..1024usize {
+        vector.update(i * 3, BitVector::from(i as u8));
+        real.update(i * 3, i as f64 / 2.0);
+    }
+
+    assert_eq!(
+        vector
+            .iter_range(10..20)
+            .collect::<Vec<(usize, BitVector)>>(),
+        vec![
+            (12, BitVector::from(4u8)),
+            (15, BitVector::from(5u8)),
+            (18, BitVector::from(6u8)),
+        ]
+    );
+    assert_eq!(
+        real.iter_range(3000..3010).collect::<Vec<(usize, f64)>>(),
+        vec![(3000, 500.0), (3003, 500.5), (3006, 501.0), (3009, 501.5)]
+    );
+}
+
+#[test]
+fn test_waveform_history_count_changes() {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+
+    let (history, expected) = generate_history(1 << 16, 16);
+    let last = expected.last().unwrap().get_timestamp_index();
+    for _ in 0..16 {
+        let start = rng.gen_range(0..last);
+        let end = rng.gen_range(start..last + 2);
+        let count = expected
+            .iter()
+            .filter(|i| (start..end).contains(&i.get_timestamp_index()))
+            .count();
+        assert_eq!(history.count_changes(start..end), count);
+        assert_eq!(history.has_change(start..end), count > 0);
+    }
+
+    assert_eq!(history.count_changes(0..usize::MAX), expected.len());
+    assert_eq!(history.count_changes(0..16), 0);
+    assert_eq!(history.count_changes(16..17), 1);
+    assert_eq!(history.count_changes(last + 1..usize::MAX), 0);
+    assert!(!history.has_change(0..16));
+    assert!(history.has_change(16..17));
+    assert!(history.has_change(last..last + 1));
+    assert!(!history.has_change(last + 1..usize::MAX));
+
+    // Gaps between consecutive changes never contain a change
+    let i = rng.gen_range(0..expected.len() - 1);
+    let gap = expected[i].get_timestamp_index() + 1..expected[i + 1].get_timestamp_index();
+    assert_eq!(history.count_changes(gap.clone()), 0);
+    assert!(!history.has_change(gap));
+
+    let history = WaveformHistory::new();
+    assert_eq!(history.count_changes(0..usize::MAX), 0);
+    assert!(!history.has_change(0..usize::MAX));
+}
+
+#[test]
+fn test_waveform_history_from_blocks() {
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::history::{block::WaveformHistoryBlock, BLOCK_SIZE};
+
+    // Round trip a valid history and keep appending to it
+    let (history, mut expected) = generate_history(1 << 12, 0);
+    let mut loaded = WaveformHistory::from_blocks(history.get_blocks().to_vec()).unwrap();
+    assert_eq!(
+        loaded.into_iter().collect::<Vec<WaveformHistoryIndex>>(),
+        expected
+    );
+    let last = expected.last().unwrap().clone();
+    for i in 1..=4 {
+        let index = WaveformHistoryIndex {
+            timestamp_index: last.get_timestamp_index() + i * 2,
+            value_index: last.get_value_index() + i,
+        };
+        loaded.add_change(index.get_timestamp_index(), index.get_value_index());
+        expected.push(index);
+    }
+    assert_eq!(
+        loaded.into_iter().collect::<Vec<WaveformHistoryIndex>>(),
+        expected
+    );
+    assert!(WaveformHistory::from_blocks(Vec::new()).is_ok());
+
+    // Gaps wider than 56 bits take more than eight skip bytes
+    #[cfg(target_pointer_width = "64")]
+    {
+        let mut wide = WaveformHistory::new();
+        wide.add_change(0, 0);
+        wide.add_change(1 << 60, 1);
+        let loaded = WaveformHistory::from_blocks(wide.get_blocks().to_vec()).unwrap();
+        assert_eq!(
+            loaded.into_iter().collect::<Vec<WaveformHistoryIndex>>(),
+            vec![
+                WaveformHistoryIndex {
+                    timestamp_index: 0,
+                    value_index: 0
+                },
+                WaveformHistoryIndex {
+                    timestamp_index: 1 << 60,
+                    value_index: 1
+                },
+            ]
+        );
+    }
+
+    // Malformed individual blocks
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&[0; 16]),
+        Err(WaveformError::InvalidBlockSize { size: 16 })
+    ));
+    let mut block_raw = vec![0; BLOCK_SIZE];
+    assert!(WaveformHistoryBlock::try_new(&block_raw).is_ok());
+    block_raw[16] = 0x80;
+    block_raw[17] = 0x00; // Zero skips before a change
+    block_raw[18] = 0x80;
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 17 })
+    ));
+    block_raw[17] = 0x01;
+    assert!(WaveformHistoryBlock::try_new(&block_raw).is_ok());
+    block_raw[100] = 0x01; // Garbage after the last change
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 100 })
+    ));
+    let mut block_raw = vec![0; BLOCK_SIZE];
+    block_raw[16] = 0x80;
+    block_raw[17..28].copy_from_slice(&[1; 11]); // Too many skip bytes
+    block_raw[28] = 0x80;
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 17 })
+    ));
+    block_raw[17..28].copy_from_slice(&[
+        0, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F,
+    ]);
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 17 })
+    ));
+    let mut block_raw = vec![0; BLOCK_SIZE];
+    block_raw[16] = 0x01; // Skips before the first change
+    block_raw[17] = 0x80;
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 16 })
+    ));
+    assert!(matches!(
+        WaveformHistory::from_blocks(block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 16 })
+    ));
+    let mut block_raw = vec![0; BLOCK_SIZE];
+    block_raw[0..8].copy_from_slice(&u64::MAX.to_be_bytes()); // Overflowing index
+    block_raw[16] = 0x80;
+    assert!(matches!(
+        WaveformHistoryBlock::try_new(&block_raw),
+        Err(WaveformError::InvalidBlockEncoding { offset: 16 })
+    ));
+
+    // Malformed sequences of blocks
+    assert!(matches!(
+        WaveformHistory::from_blocks(vec![0; BLOCK_SIZE + 1]),
+        Err(WaveformError::InvalidBlockSize { .. })
+    ));
+    assert!(matches!(
+        WaveformHistory::from_blocks(vec![0; BLOCK_SIZE]),
+        Err(WaveformError::InvalidBlockEncoding { offset: 16 })
+    ));
+    let mut blocks = history.get_blocks().to_vec();
+    blocks[BLOCK_SIZE..BLOCK_SIZE + 8].copy_from_slice(&0u64.to_be_bytes());
+    assert!(matches!(
+        WaveformHistory::from_blocks(blocks),
+        Err(WaveformError::InvalidBlockOrder { block_index: 1 })
+    ));
+    let mut blocks = history.get_blocks().to_vec();
+    let value_index = history.get_block(1).get_value_index();
+    blocks[BLOCK_SIZE + 8..BLOCK_SIZE + 16]
+        .copy_from_slice(&(value_index as u64 + 1).to_be_bytes());
+    assert!(matches!(
+        WaveformHistory::from_blocks(blocks),
+        Err(WaveformError::InvalidValueIndex { block_index: 1, .. })
+    ));
+}
+
+// Writes hand-written changes in timestamp order, inserting each timestamp
+// not in the waveform yet, where vector values are written in binary such as
+// "01xz" and real values as decimals
+fn write_changes(waveform: &mut makai_waveform_db::Waveform, changes: &[(u64, usize, &str)]) {
+    use makai_waveform_db::bitvector::BitVector;
+
+    for (timestamp, id, value) in changes {
+        if waveform.get_timestamps().last() != Some(timestamp) {
+            waveform.insert_timestamp(*timestamp).unwrap();
+        }
+        if waveform.get_vector_signal(*id).is_none() {
+            waveform.update_real(*id, value.parse().unwrap()).unwrap();
+        } else if value.bytes().all(|b| b == b'0' || b == b'1') {
+            let bv = BitVector::from_ascii(value.as_bytes());
+            waveform.update_vector(*id, bv).unwrap();
+        } else {
+            let bv = BitVector::from_ascii_four_state(value.as_bytes());
+            waveform.update_vector(*id, bv).unwrap();
+        }
+    }
+}
+
+// Writes a clock rising at every odd timestamp, along with hand-written values
+// for each of its rising edges as in `write_changes`, where edge k is at
+// timestamp index 2k + 1 and samples the values written at 2k
+fn write_clocked(
+    waveform: &mut makai_waveform_db::Waveform,
+    clock: usize,
+    signals: &[(usize, &[&str])],
+) {
+    let edges = signals
+        .iter()
+        .map(|(_, values)| values.len())
+        .max()
+        .unwrap();
+    for edge in 0..edges as u64 {
+        write_changes(waveform, &[(edge * 2, clock, "0")]);
+        for (id, values) in signals {
+            if let Some(value) = values.get(edge as usize) {
+                write_changes(waveform, &[(edge * 2, *id, value)]);
+            }
+        }
+        write_changes(waveform, &[(edge * 2 + 1, clock, "1")]);
+    }
+}
+
+// Formats a value as written in `write_changes`
+fn format_value(value: &makai_waveform_db::WaveformValueResult) -> String {
+    match value {
+        makai_waveform_db::WaveformValueResult::Vector(bv, _) => {
+            bv.to_string()[1..].to_ascii_lowercase()
+        }
+        makai_waveform_db::WaveformValueResult::Real(r, _) => r.to_string(),
+    }
+}
+
+// Returns the value of a signal in effect at every timestamp index, formatted
+// as in `write_changes` or "-" before its first change
+fn get_values(waveform: &impl makai_waveform_db::query::WaveformQuery, id: usize) -> Vec<String> {
+    (0..waveform.get_timestamps().len())
+        .map(|timestamp_index| {
+            waveform
+                .search_value(
+                    id,
+                    timestamp_index,
+                    makai_waveform_db::WaveformSearchMode::Before,
+                )
+                .map_or("-".to_string(), |value| format_value(&value))
+        })
+        .collect()
+}
+
+#[test]
+fn test_waveform_truncate_before() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::Waveform;
+
+    // The vector changes at every timestamp and the real every 1000
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_real(1);
+    for timestamp in 0..1 << 14 {
+        waveform.insert_timestamp(timestamp * 10).unwrap();
+        waveform
+            .update_vector(0, BitVector::from(timestamp as u8))
+            .unwrap();
+        if timestamp % 1000 == 0 {
+            waveform.update_real(1, timestamp as f64).unwrap();
+        }
+    }
+    let size = waveform.get_block_size() + waveform.get_vector_size();
+
+    // Truncate between two timestamps so the later one is kept, along with
+    // the values in effect at it
+    waveform.truncate_before(15384 * 10 - 5);
+    assert_eq!(waveform.timestamps_count(), 1000);
+    assert_eq!(waveform.get_timestamps()[0], 153840);
+    let (vector, real) = (get_values(&waveform, 0), get_values(&waveform, 1));
+    assert_eq!(vector[0], "00011000");
+    assert_eq!(vector[999], "11111111");
+    assert_eq!(real[0], "15000");
+    assert_eq!(real[615], "15000");
+    assert_eq!(real[616], "16000");
+    assert!(waveform.get_block_size() + waveform.get_vector_size() < size);
+
+    // Continue appending after truncation
+    write_changes(
+        &mut waveform,
+        &[(163840, 0, "00000001"), (163850, 1, "2.5")],
+    );
+    let (vector, real) = (get_values(&waveform, 0), get_values(&waveform, 1));
+    assert_eq!(vector[1000..], ["00000001", "00000001"]);
+    assert_eq!(real[999..], ["16000", "16000", "2.5"]);
+
+    // Truncating past the end keeps the last timestamp
+    waveform.truncate_before(u64::MAX);
+    assert_eq!(waveform.get_timestamps(), &vec![163850]);
+    assert_eq!(get_values(&waveform, 0), ["00000001"]);
+    assert_eq!(get_values(&waveform, 1), ["2.5"]);
+}
+
+#[test]
+fn test_waveform_bounded() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::Waveform;
+
+    // Bounded waveforms drop down to the bound once they reach twice it
+    let mut waveform = Waveform::new_bounded(1000);
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_real(1);
+    for timestamp in 0..10000u64 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        waveform
+            .update_vector(0, BitVector::from(timestamp as u8))
+            .unwrap();
+        if timestamp % 3000 == 0 {
+            waveform.update_real(1, timestamp as f64).unwrap();
+        }
+        assert!(waveform.timestamps_count() <= 2000);
+    }
+    // The last drop happened when inserting 9007, keeping 8008 onwards
+    assert_eq!(waveform.timestamps_count(), 1992);
+    assert_eq!(waveform.get_timestamps()[0], 8008);
+    assert_eq!(*waveform.get_timestamps().last().unwrap(), 9999);
+    let (vector, real) = (get_values(&waveform, 0), get_values(&waveform, 1));
+    assert_eq!(vector[0], "01001000");
+    assert_eq!(vector[1991], "00001111");
+    assert_eq!(real[0], "6000");
+    assert_eq!(real[991], "6000");
+    assert_eq!(real[992], "9000");
+}
+
+#[test]
+fn test_waveform_update_same_timestamp() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_real(1);
+    waveform.insert_timestamp(0).unwrap();
+    waveform.update_vector(0, BitVector::from(1u8)).unwrap();
+    waveform.update_real(1, 1.0).unwrap();
+    waveform.insert_timestamp(10).unwrap();
+    waveform.update_vector(0, BitVector::from(2u8)).unwrap();
+    waveform.update_real(1, 2.0).unwrap();
+
+    // A second update at the same timestamp replaces the first one
+    waveform.update_vector(0, BitVector::from(3u8)).unwrap();
+    waveform.update_real(1, 3.0).unwrap();
+    for (timestamp_index, vector, real) in [(0, 1u8, 1.0), (1, 3, 3.0)] {
+        assert_eq!(
+            waveform.search_value(0, timestamp_index, WaveformSearchMode::Exact),
+            Some(WaveformValueResult::Vector(
+                BitVector::from(vector),
+                timestamp_index
+            ))
+        );
+        assert_eq!(
+            waveform.search_value(1, timestamp_index, WaveformSearchMode::Exact),
+            Some(WaveformValueResult::Real(real, timestamp_index))
+        );
+    }
+    let signal = waveform.get_vector_signal(0).unwrap();
+    assert_eq!(signal.get_history().get_block_size(), 512);
+    assert_eq!(signal.len(), 2);
+}
+
+#[test]
+fn test_waveform_append() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+    // Join segments end to end, where the real only changes at the start of
+    // each segment
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_real(1);
+    for segment in 0..4u64 {
+        let mut other = Waveform::new();
+        other.initialize_vector(0, 8);
+        other.initialize_real(1);
+        for timestamp in segment * 5000..(segment + 1) * 5000 {
+            other.insert_timestamp(timestamp).unwrap();
+            other
+                .update_vector(0, BitVector::from((timestamp / 4) as u8))
+                .unwrap();
+            if timestamp % 5000 == 0 {
+                other.update_real(1, segment as f64).unwrap();
+            }
+        }
+        waveform.append(other).unwrap();
+    }
+    assert_eq!(waveform.timestamps_count(), 20000);
+    let (vector, real) = (get_values(&waveform, 0), get_values(&waveform, 1));
+    assert_eq!(vector[0], "00000000");
+    assert_eq!(vector[4999], "11100001");
+    assert_eq!(vector[5000], "11100010");
+    assert_eq!(vector[19999], "10000111");
+    assert_eq!(real[4999], "0");
+    assert_eq!(real[5000], "1");
+    assert_eq!(real[19999], "3");
+    assert_eq!(
+        waveform
+            .get_real_signal(1)
+            .unwrap()
+            .get_history()
+            .count_changes(0..20000),
+        4
+    );
+    for id in 0..2 {
+        let history = match waveform.get_signal(id).unwrap() {
+            makai_waveform_db::WaveformSignalResult::Vector(signal) => signal.get_history(),
+            makai_waveform_db::WaveformSignalResult::Real(signal) => signal.get_history(),
+        };
+        assert!(WaveformHistory::from_blocks(history.get_blocks().to_vec()).is_ok());
+    }
+
+    // Repeated values at the boundary are dropped
+    let mut first = Waveform::new();
+    first.initialize_vector(0, 8);
+    first.insert_timestamp(0).unwrap();
+    first.update_vector(0, BitVector::from(5u8)).unwrap();
+    let mut second = Waveform::new();
+    second.initialize_vector(0, 8);
+    second.initialize_real(1);
+    second.insert_timestamp(10).unwrap();
+    second.update_vector(0, BitVector::from(5u8)).unwrap();
+    second.update_real(1, 1.5).unwrap();
+    second.insert_timestamp(20).unwrap();
+    second.update_vector(0, BitVector::from(6u8)).unwrap();
+    first.append(second).unwrap();
+    assert_eq!(first.get_timestamps(), &vec![0, 10, 20]);
+    let signal = first.get_vector_signal(0).unwrap();
+    assert_eq!(signal.len(), 2);
+    assert_eq!(signal.get_history().count_changes(0..3), 2);
+    assert_eq!(
+        first.search_value(0, 1, WaveformSearchMode::Before),
+        Some(WaveformValueResult::Vector(BitVector::from(5u8), 0))
+    );
+    assert_eq!(
+        first.search_value(1, 2, WaveformSearchMode::Before),
+        Some(WaveformValueResult::Real(1.5, 1))
+    );
+
+    // Invalid waveforms are rejected without modifying anything
+    let mut other = Waveform::new();
+    other.insert_timestamp(20).unwrap();
+    assert!(matches!(
+        first.append(other),
+        Err(WaveformError::OverlappingTimestamps { timestamp: 20 })
+    ));
+    let mut other = Waveform::new();
+    other.initialize_vector(0, 4);
+    other.insert_timestamp(30).unwrap();
+    assert!(matches!(
+        first.append(other),
+        Err(WaveformError::InvalidWidth {
+            id: 0,
+            expected: 8,
+            actual: 4
+        })
+    ));
+    let mut other = Waveform::new();
+    other.initialize_vector(1, 4);
+    other.insert_timestamp(30).unwrap();
+    assert!(matches!(
+        first.append(other),
+        Err(WaveformError::MismatchedSignalType { id: 1 })
+    ));
+    assert_eq!(first.timestamps_count(), 3);
+}
+
+#[test]
+fn test_waveform_merge() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+    // Signals split across waveforms with different timestamps
+    let (mut waveform_a, mut waveform_b) = (Waveform::new(), Waveform::new());
+    waveform_a.initialize_vector(0, 4);
+    waveform_a.initialize_real(1);
+    waveform_b.initialize_vector(2, 1);
+    write_changes(
+        &mut waveform_a,
+        &[(0, 0, "0001"), (4, 0, "0010"), (4, 1, "0.5"), (6, 1, "1.5")],
+    );
+    write_changes(&mut waveform_b, &[(1, 2, "1"), (4, 2, "0"), (7, 2, "1")]);
+    let merged = Waveform::merge(vec![waveform_a, waveform_b]).unwrap();
+    assert_eq!(merged.get_timestamps(), &vec![0, 1, 4, 6, 7]);
+    assert_eq!(
+        get_values(&merged, 0),
+        ["0001", "0001", "0010", "0010", "0010"]
+    );
+    assert_eq!(get_values(&merged, 1), ["-", "-", "0.5", "1.5", "1.5"]);
+    assert_eq!(get_values(&merged, 2), ["-", "1", "0", "0", "1"]);
+
+    // Bounded waveforms stay within the bound once merged
+    let (mut waveform_a, mut waveform_b) = (Waveform::new_bounded(2), Waveform::new_bounded(2));
+    waveform_a.initialize_vector(0, 8);
+    waveform_b.initialize_vector(1, 8);
+    for timestamp in 0..3u8 {
+        waveform_a.insert_timestamp(timestamp as u64 * 2).unwrap();
+        waveform_a
+            .update_vector(0, BitVector::from(timestamp))
+            .unwrap();
+        waveform_b
+            .insert_timestamp(timestamp as u64 * 2 + 1)
+            .unwrap();
+        waveform_b
+            .update_vector(1, BitVector::from(timestamp + 10))
+            .unwrap();
+    }
+    let merged = Waveform::merge(vec![waveform_a, waveform_b]).unwrap();
+    assert_eq!(merged.get_max_timestamps(), Some(2));
+    assert_eq!(merged.get_timestamps(), &vec![4, 5]);
+    for (id, timestamp_index, value) in [(0, 0, 2u8), (0, 1, 2), (1, 0, 11), (1, 1, 12)] {
+        assert_eq!(
+            merged.search_value(id, timestamp_index, WaveformSearchMode::Before),
+            Some(WaveformValueResult::Vector(
+                BitVector::from(value),
+                if value == 12 { 1 } else { 0 }
+            ))
+        );
+    }
+    let merged = Waveform::merge(vec![
+        Waveform::new(),
+        Waveform::new_bounded(5),
+        Waveform::new_bounded(3),
+    ])
+    .unwrap();
+    assert_eq!(merged.get_max_timestamps(), Some(3));
+
+    // Colliding ids are reported instead of overwritten
+    let (mut waveform_a, mut waveform_b) = (Waveform::new(), Waveform::new());
+    waveform_a.initialize_vector(0, 1);
+    waveform_a.insert_timestamp(0).unwrap();
+    waveform_b.initialize_real(0);
+    waveform_b.insert_timestamp(1).unwrap();
+    assert!(matches!(
+        Waveform::merge(vec![waveform_a, waveform_b]),
+        Err(WaveformError::DuplicateId { id: 0 })
+    ));
+    let mut waveform = Waveform::new();
+    for id in 0..4 {
+        waveform.initialize_vector(id, 1);
+    }
+    waveform.initialize_real(4);
+    let mut shards = waveform.shard(2);
+    shards[1].initialize_real(2);
+    assert!(matches!(
+        Waveform::unshard(shards),
+        Err(WaveformError::DuplicateId { id: 2 })
+    ));
+}
+
+#[test]
+fn test_waveform_hierarchy() {
+    use makai_waveform_db::hierarchy::*;
+    use makai_waveform_db::Waveform;
+
+    let mut waveform = Waveform::new();
+    let hierarchy = waveform.get_hierarchy_mut();
+    let top = hierarchy.push_scope("top", WaveformScopeType::Module);
+    hierarchy.add_variable(WaveformVariable::new(
+        "clk",
+        0,
+        WaveformVariableType::Wire,
+        1,
+        None,
+        WaveformVariableDirection::Input,
+    ));
+    let cpu = hierarchy.push_scope("cpu", WaveformScopeType::Module);
+    hierarchy.add_variable(WaveformVariable::new(
+        "clk",
+        0,
+        WaveformVariableType::Wire,
+        1,
+        None,
+        WaveformVariableDirection::Input,
+    ));
+    hierarchy.add_variable(WaveformVariable::new(
+        "pc",
+        1,
+        WaveformVariableType::Reg,
+        32,
+        Some(WaveformBitRange { msb: 31, lsb: 0 }),
+        WaveformVariableDirection::Implicit,
+    ));
+    hierarchy.push_scope("gen_alu", WaveformScopeType::Generate);
+    hierarchy.add_variable(WaveformVariable::new(
+        "result",
+        2,
+        WaveformVariableType::Real,
+        64,
+        None,
+        WaveformVariableDirection::Output,
+    ));
+    assert_eq!(hierarchy.pop_scope(), Some(2));
+    assert_eq!(hierarchy.pop_scope(), Some(cpu));
+    // Reopening a scope continues adding to it
+    assert_eq!(hierarchy.push_scope("cpu", WaveformScopeType::Module), cpu);
+    hierarchy.pop_scope();
+    assert_eq!(hierarchy.pop_scope(), Some(top));
+    assert_eq!(hierarchy.pop_scope(), None);
+
+    let hierarchy = waveform.get_hierarchy();
+    assert_eq!(hierarchy.get_root_scopes(), &[top]);
+    assert_eq!(hierarchy.find_scope("top.cpu"), Some(cpu));
+    assert_eq!(hierarchy.find_scope("top.cpu.gen_alu"), Some(2));
+    assert_eq!(hierarchy.find_scope("cpu"), None);
+    assert_eq!(hierarchy.find_id("top.clk"), Some(0));
+    assert_eq!(hierarchy.find_id("top.cpu.clk"), Some(0));
+    assert_eq!(hierarchy.find_id("top.cpu.gen_alu.result"), Some(2));
+    assert_eq!(hierarchy.find_id("top.cpu.missing"), None);
+    let pc = hierarchy.get_variable(hierarchy.find_variables("top.cpu.pc")[0]);
+    assert_eq!(pc.unwrap().get_range().unwrap().get_width(), 32);
+    assert_eq!(hierarchy.get_variables_by_id(0).len(), 2);
+    assert_eq!(
+        hierarchy.get_variable_path(hierarchy.get_variables_by_id(0)[1]),
+        "top.cpu.clk"
+    );
+    assert_eq!(hierarchy.get_scope_ids(cpu), vec![0, 1, 2]);
+    assert_eq!(hierarchy.get_scope_ids(2), vec![2]);
+    assert_eq!(
+        WaveformVariableType::from_str("parameter"),
+        Some(WaveformVariableType::Parameter)
+    );
+
+    // Merging identical hierarchies does not duplicate variables
+    let mut merged = hierarchy.clone();
+    merged.merge(hierarchy);
+    assert_eq!(merged.get_variables().len(), 4);
+    let mut other = WaveformHierarchy::new();
+    other.push_scope("top", WaveformScopeType::Module);
+    other.add_variable(WaveformVariable::new(
+        "rst",
+        3,
+        WaveformVariableType::Wire,
+        1,
+        None,
+        WaveformVariableDirection::Input,
+    ));
+    merged.merge(&other);
+    assert_eq!(merged.get_variables().len(), 5);
+    assert_eq!(merged.get_root_scopes().len(), 1);
+    assert_eq!(merged.find_id("top.rst"), Some(3));
+
+    // Dots inside escaped names are escaped in paths
+    let mut escaped = WaveformHierarchy::new();
+    escaped.push_scope("top", WaveformScopeType::Module);
+    escaped.push_scope("a", WaveformScopeType::Module);
+    escaped.add_variable(WaveformVariable::new(
+        "b",
+        0,
+        WaveformVariableType::Wire,
+        1,
+        None,
+        WaveformVariableDirection::Implicit,
+    ));
+    escaped.pop_scope();
+    let index = escaped.add_variable(WaveformVariable::new(
+        "\\a.b",
+        1,
+        WaveformVariableType::Wire,
+        1,
+        None,
+        WaveformVariableDirection::Implicit,
+    ));
+    assert_eq!(escaped.get_variable_path(index), "top.\\\\a\\.b");
+    assert_eq!(escaped.find_id("top.a.b"), Some(0));
+    assert_eq!(escaped.find_id("top.\\\\a\\.b"), Some(1));
+}
+
+const VCD: &str = "$date today $end
+$version test $end
+$timescale 10 ns $end
+$scope module top $end
+$var wire 1 ! clk $end
+$var reg 8 \" data [7:0] $end
+$scope module cpu $end
+$var wire 1 ! clk $end
+$var real 64 # temp $end
+$var wire 4 $ nibble[3:0] $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+$dumpvars
+0!
+bx \"
+r1.5 #
+b1 $
+$end
+#10
+1!
+b1010 \"
+#20
+0!
+bz1 $
+$comment ignored $end
+#30
+$dumpoff
+x!
+$end
+";
+
+#[test]
+fn test_vcd_reader() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::hierarchy::{WaveformScopeType, WaveformVariableType};
+    use makai_waveform_db::vcd::reader::{read_vcd, WaveformVcdReader};
+    use makai_waveform_db::{WaveformSearchMode, WaveformValueResult};
+
+    let mut reader = WaveformVcdReader::new(VCD.as_bytes());
+    let mut waveform = makai_waveform_db::Waveform::new();
+    reader.read_header(&mut waveform).unwrap();
+    let timescale = waveform.get_timescale().unwrap();
+    assert_eq!(timescale.to_string(), "10ns");
+    assert_eq!(reader.get_timescale(), Some(timescale));
+    assert_eq!(reader.get_identifiers().len(), 4);
+    reader.read_body(&mut waveform).unwrap();
+    assert_eq!(waveform.get_timestamps(), &vec![0, 10, 20, 30]);
+
+    let hierarchy = waveform.get_hierarchy();
+    assert_eq!(hierarchy.find_id("top.clk"), Some(0));
+    assert_eq!(hierarchy.find_id("top.cpu.clk"), Some(0));
+    assert_eq!(hierarchy.find_id("top.data"), Some(1));
+    assert_eq!(hierarchy.find_id("top.cpu.temp"), Some(2));
+    let nibble = hierarchy.find_variables("top.cpu.nibble")[0];
+    let nibble = hierarchy.get_variable(nibble).unwrap();
+    assert_eq!(nibble.get_range().unwrap().get_width(), 4);
+
+    let value = |id, timestamp_index| match waveform.search_value(
+        id,
+        timestamp_index,
+        WaveformSearchMode::Before,
+    ) {
+        Some(WaveformValueResult::Vector(bv, _)) => bv.to_string(),
+        Some(WaveformValueResult::Real(r, _)) => r.to_string(),
+        None => String::new(),
+    };
+    assert_eq!(value(0, 0), "b0");
+    assert_eq!(value(0, 1), "b1");
+    assert_eq!(value(0, 3), "bX");
+    assert_eq!(value(1, 0), "bXXXXXXXX");
+    assert_eq!(value(1, 2), "b00001010");
+    assert_eq!(value(2, 3), "1.5");
+    assert_eq!(value(3, 0), "b0001");
+    assert_eq!(value(3, 2), "bZZZ1");
+    assert_eq!(
+        waveform.search_value(1, 2, WaveformSearchMode::Before),
+        Some(WaveformValueResult::Vector(
+            BitVector::from_ascii(b"00001010"),
+            1
+        ))
+    );
+
+    let waveform = read_vcd(VCD.as_bytes()).unwrap();
+    assert_eq!(waveform.timestamps_count(), 4);
+
+    // Types from other tools fall back to a module scope and a wire, or a
+    // real if they are named as one
+    let waveform = read_vcd(
+        "$scope foo top $end\n$var bar 2 ! a $end\n$var sv_real 64 \" b $end\n\
+         $upscope $end\n$enddefinitions $end\n#0\nr2.5 \"\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let hierarchy = waveform.get_hierarchy();
+    assert_eq!(
+        hierarchy.get_scope(0).unwrap().get_scope_type(),
+        WaveformScopeType::Module
+    );
+    let variable = hierarchy.get_variable(0).unwrap();
+    assert_eq!(variable.get_variable_type(), WaveformVariableType::Wire);
+    assert_eq!(waveform.get_vector_signal(0).unwrap().get_width(), 2);
+    let variable = hierarchy.get_variable(1).unwrap();
+    assert_eq!(variable.get_variable_type(), WaveformVariableType::Real);
+    assert_eq!(
+        waveform.search_value(1, 0, WaveformSearchMode::Exact),
+        Some(WaveformValueResult::Real(2.5, 0))
+    );
+}
+
+#[test]
+fn test_vcd_reader_errors() {
+    use makai_waveform_db::errors::{WaveformError, WaveformVcdError};
+    use makai_waveform_db::vcd::reader::read_vcd;
+
+    let header =
+        "$scope module top $end\n$var wire 4 ! a $end\n$upscope $end\n$enddefinitions $end\n";
+    let read = |body: &str| read_vcd(format!("{header}{body}").as_bytes());
+    assert!(matches!(
+        read("#0\nb101 ?\n"),
+        Err(WaveformError::InvalidVcd {
+            line: 6,
+            error: WaveformVcdError::UnknownIdentifier { .. }
+        })
+    ));
+    assert!(matches!(
+        read("#0\nb10101 !\n"),
+        Err(WaveformError::InvalidVcd {
+            error: WaveformVcdError::InvalidValue { .. },
+            ..
+        })
+    ));
+    assert!(matches!(
+        read("#0\nb102 !\n"),
+        Err(WaveformError::InvalidVcd {
+            error: WaveformVcdError::InvalidValue { .. },
+            ..
+        })
+    ));
+    assert!(matches!(
+        read("#1a\n"),
+        Err(WaveformError::InvalidVcd {
+            error: WaveformVcdError::InvalidTimestamp { .. },
+            ..
+        })
+    ));
+    assert!(matches!(
+        read("#10\n#5\n"),
+        Err(WaveformError::DecreasingTimestamp { timestamp: 5 })
+    ));
+    assert!(matches!(
+        read("#0\n$bogus\n"),
+        Err(WaveformError::InvalidVcd {
+            error: WaveformVcdError::UnknownCommand { .. },
+            ..
+        })
+    ));
+    // Aliases of a code have to match the signal declared first
+    assert!(matches!(
+        read_vcd("$scope module top $end\n$var wire 4 ! a $end\n$var wire 2 ! b $end\n".as_bytes()),
+        Err(WaveformError::InvalidVcd {
+            line: 3,
+            error: WaveformVcdError::InvalidWidth { .. }
+        })
+    ));
+    assert!(matches!(
+        read_vcd(
+            "$scope module top $end\n$var wire 4 ! a $end\n$var real 64 ! b $end\n".as_bytes()
+        ),
+        Err(WaveformError::InvalidVcd {
+            line: 3,
+            error: WaveformVcdError::MismatchedSignalType { .. }
+        })
+    ));
+    assert!(matches!(
+        read_vcd("$scope module top $end\n$upscope $end\n$upscope $end\n".as_bytes()),
+        Err(WaveformError::InvalidVcd {
+            line: 3,
+            error: WaveformVcdError::UnbalancedScope
+        })
+    ));
+    assert!(matches!(
+        read_vcd("$scope module top $end\n$var wire 1 ! a\n".as_bytes()),
+        Err(WaveformError::InvalidVcd {
+            error: WaveformVcdError::UnexpectedEnd,
+            ..
+        })
+    ));
+    assert!(matches!(
+        read_vcd("$timescale 3 ns $end\n".as_bytes()),
+        Err(WaveformError::InvalidVcd {
+            error: WaveformVcdError::InvalidTimescale { .. },
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_vcd_reader_parallel() {
+    use makai_waveform_db::vcd::parallel::{read_vcd_parallel, WaveformVcdParallelReader};
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::WaveformSearchMode;
+    // Signal i changes every i + 2 timestamps, cycling through every state
+    let widths = [1, 3, 8, 40, 0];
+    let states = ['0', '1', 'x', 'z'];
+    let mut vcd = String::from("$scope module top $end\n");
+    for (i, width) in widths.iter().enumerate() {
+        match width {
+            0 => vcd.push_str(&format!("$var real 64 s{i} r{i} $end\n")),
+            w => vcd.push_str(&format!("$var wire {w} s{i} v{i} $end\n")),
+        }
+    }
+    vcd.push_str("$upscope $end\n$enddefinitions $end\n");
+    for timestamp in 0..2000 {
+        vcd.push_str(&format!("#{}\n", timestamp * 5));
+        for (i, width) in widths.iter().enumerate() {
+            if timestamp % (i + 2) != 0 {
+                continue;
+            }
+            match width {
+                0 => vcd.push_str(&format!("r{} s{i}\n", timestamp as f64 / 8.0)),
+                w => {
+                    let value = (0..*w)
+                        .map(|bit| states[(timestamp + bit) % 4])
+                        .collect::<String>();
+                    if *w == 1 {
+                        vcd.push_str(&format!("{value}s{i}\n"));
+                    } else {
+                        vcd.push_str(&format!("b{value} s{i}\n"));
+                    }
+                }
+            }
+        }
+    }
+
+    // A timestamp repeated right where a chunk could be cut stays in one chunk
+    vcd.push_str("#10000\n1s0\n#10000\n0s0\n#10005\n0s0\n");
+
+    let expected = read_vcd(vcd.as_bytes()).unwrap();
+    for (num_threads, chunk_size) in [(1, 1 << 22), (2, 0), (3, 100), (8, 1000)] {
+        let mut reader = WaveformVcdParallelReader::new(vcd.as_bytes(), num_threads);
+        reader.set_chunk_size(chunk_size);
+        let waveform = reader.read().unwrap();
+        assert_eq!(waveform.get_timestamps(), expected.get_timestamps());
+        assert_eq!(waveform.get_hierarchy().get_variables().len(), widths.len());
+        for id in 0..widths.len() {
+            for timestamp_index in 0..expected.timestamps_count() {
+                assert_eq!(
+                    waveform.search_value(id, timestamp_index, WaveformSearchMode::Before),
+                    expected.search_value(id, timestamp_index, WaveformSearchMode::Before)
+                );
+            }
+        }
+    }
+    let waveform = read_vcd_parallel(vcd.as_bytes(), 4).unwrap();
+    assert_eq!(waveform.get_timestamps(), expected.get_timestamps());
+
+    // Errors are still reported with their line in the whole file, and an
+    // error in an earlier chunk is reported first
+    let vcd = format!("{vcd}#99999\nb1 ?\n#100000\nb1 ?\n");
+    let mut reader = WaveformVcdParallelReader::new(vcd.as_bytes(), 4);
+    reader.set_chunk_size(0);
+    assert!(matches!(
+        reader.read(),
+        Err(makai_waveform_db::errors::WaveformError::InvalidVcd { line, .. })
+            if line == vcd.lines().count() - 2
+    ));
+}
+
+#[test]
+fn test_vcd_writer() {
+    use makai_waveform_db::hierarchy::*;
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::vcd::writer::{get_identifier_code, write_vcd, WaveformVcdWriter};
+    use makai_waveform_db::WaveformSearchMode;
+
+    assert_eq!(get_identifier_code(0), "!");
+    assert_eq!(get_identifier_code(93), "~");
+    assert_eq!(get_identifier_code(94), "!!");
+    let codes = (0..20000)
+        .map(get_identifier_code)
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(codes.len(), 20000);
+
+    // Round trip through the writer keeps every value and variable
+    let expected = read_vcd(VCD.as_bytes()).unwrap();
+    let mut output = Vec::new();
+    write_vcd(&expected, &mut output).unwrap();
+    let waveform = read_vcd(output.as_slice()).unwrap();
+    assert_eq!(waveform.get_timestamps(), expected.get_timestamps());
+    for (variable, other) in waveform
+        .get_hierarchy()
+        .get_variables()
+        .iter()
+        .zip(expected.get_hierarchy().get_variables())
+    {
+        assert_eq!(variable, other);
+    }
+    for id in 0..4 {
+        for timestamp_index in 0..expected.timestamps_count() {
+            assert_eq!(
+                waveform.search_value(id, timestamp_index, WaveformSearchMode::Before),
+                expected.search_value(id, timestamp_index, WaveformSearchMode::Before)
+            );
+        }
+    }
+
+    // Filtering starts from the values in effect at the first timestamp
+    let mut writer = WaveformVcdWriter::new(&expected);
+    writer.set_range(Some(5..25));
+    writer.set_selected(Some([0, 3].into_iter().collect()));
+    let mut output = Vec::new();
+    writer.write(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("$timescale 10ns $end"));
+    assert!(!output.contains("data"));
+    assert!(output.ends_with("#10\n$dumpvars\n1!\nb0001 \"\n$end\n#20\n0!\nbzzz1 \"\n"));
+    let waveform = read_vcd(output.as_bytes()).unwrap();
+    assert_eq!(waveform.get_timestamps(), &vec![10, 20]);
+    assert_eq!(waveform.get_hierarchy().find_id("top.cpu.clk"), Some(0));
+    assert_eq!(waveform.get_hierarchy().find_id("top.cpu.nibble"), Some(1));
+
+    // Signals without a hierarchy are still declared
+    let mut waveform = makai_waveform_db::Waveform::new();
+    waveform.initialize_real(7);
+    waveform.insert_timestamp(3).unwrap();
+    waveform.update_real(7, 2.5).unwrap();
+    let mut output = Vec::new();
+    write_vcd(&waveform, &mut output).unwrap();
+    let waveform = read_vcd(output.as_slice()).unwrap();
+    assert_eq!(waveform.get_hierarchy().find_id("signal_7"), Some(0));
+    assert_eq!(waveform.get_real_signal(0).unwrap().get_real(0), 2.5);
+
+    // Non-finite reals are written so they can be read back
+    let mut waveform = makai_waveform_db::Waveform::new();
+    waveform.initialize_real(0);
+    for (timestamp, value) in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY]
+        .into_iter()
+        .enumerate()
+    {
+        waveform.insert_timestamp(timestamp as u64).unwrap();
+        waveform.update_real(0, value).unwrap();
+    }
+    let mut output = Vec::new();
+    write_vcd(&waveform, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.ends_with("rnan !\n$end\n#1\nrinf !\n#2\nr-inf !\n"));
+    let waveform = read_vcd(output.as_bytes()).unwrap();
+    let signal = waveform.get_real_signal(0).unwrap();
+    assert!(signal.get_real(0).is_nan());
+    assert_eq!(signal.get_real(1), f64::INFINITY);
+    assert_eq!(signal.get_real(2), f64::NEG_INFINITY);
+
+    // Scope and variable types outside IEEE 1364 are written as the closest
+    // of its types
+    let mut waveform = makai_waveform_db::Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_vector(1, 32);
+    waveform.initialize_real(2);
+    let hierarchy = waveform.get_hierarchy_mut();
+    hierarchy.push_scope("top", WaveformScopeType::Interface);
+    hierarchy.push_scope("gen", WaveformScopeType::Generate);
+    for (name, id, variable_type, width) in [
+        ("a", 0, WaveformVariableType::Logic, 8),
+        ("b", 1, WaveformVariableType::Int, 32),
+        ("c", 2, WaveformVariableType::ShortReal, 64),
+    ] {
+        hierarchy.add_variable(WaveformVariable::new(
+            name,
+            id,
+            variable_type,
+            width,
+            None,
+            WaveformVariableDirection::Implicit,
+        ));
+    }
+    let mut output = Vec::new();
+    write_vcd(&waveform, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(
+        "$scope module top $end\n$scope begin gen $end\n$var reg 8 ! a $end\n\
+         $var integer 32 \" b $end\n$var real 64 # c $end\n"
+    ));
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn test_fst_reader() {
+    use makai_waveform_db::fst::reader::{read_fst, WaveformFstReader};
+    use makai_waveform_db::hierarchy::WaveformVariableDirection;
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::WaveformSearchMode;
+
+    let open = |name: &str| {
+        let path = format!("{}/tests/data/{name}.fst", env!("CARGO_MANIFEST_DIR"));
+        std::io::BufReader::new(std::fs::File::open(path).unwrap())
+    };
+    // The files hold the same waveform as the VCD with each compression
+    let expected = read_vcd(VCD.as_bytes()).unwrap();
+    for name in ["zlib", "lz4", "fastlz"] {
+        let waveform = read_fst(open(name)).unwrap();
+        assert_eq!(waveform.get_timestamps(), expected.get_timestamps());
+        for id in 0..4 {
+            for timestamp_index in 0..expected.timestamps_count() {
+                assert_eq!(
+                    waveform.search_value(id, timestamp_index, WaveformSearchMode::Before),
+                    expected.search_value(id, timestamp_index, WaveformSearchMode::Before)
+                );
+            }
+        }
+        let hierarchy = waveform.get_hierarchy();
+        assert_eq!(hierarchy.find_id("top.clk"), Some(0));
+        assert_eq!(hierarchy.find_id("top.cpu.clk"), Some(0));
+        assert_eq!(hierarchy.find_id("top.cpu.temp"), Some(2));
+        for (path, width) in [("top.data", 8), ("top.cpu.nibble", 4)] {
+            let variable = hierarchy.find_variables(path)[0];
+            let variable = hierarchy.get_variable(variable).unwrap();
+            assert_eq!(variable.get_range().unwrap().get_width(), width);
+        }
+        let clk = hierarchy.get_variable(hierarchy.get_variables_by_id(0)[0]);
+        assert_eq!(
+            clk.unwrap().get_direction(),
+            WaveformVariableDirection::Input
+        );
+    }
+
+    // Selecting signals from the hierarchy before reading the body
+    let mut reader = WaveformFstReader::new(open("lz4")).unwrap();
+    let mut waveform = makai_waveform_db::Waveform::new();
+    reader.read_header(&mut waveform).unwrap();
+    assert_eq!(reader.get_timescale_exponent(), -8);
+    assert_eq!(waveform.get_timescale().unwrap().to_string(), "10ns");
+    assert_eq!(reader.get_signals().len(), 4);
+    let nibble = waveform.get_hierarchy().find_id("top.cpu.nibble").unwrap();
+    reader.set_selected(Some([nibble].into_iter().collect()));
+    reader.read_body(&mut waveform).unwrap();
+    assert_eq!(waveform.get_timestamps(), expected.get_timestamps());
+    assert!(waveform.get_signal(0).is_none());
+    assert_eq!(
+        waveform.search_value(nibble, 3, WaveformSearchMode::Before),
+        expected.search_value(nibble, 3, WaveformSearchMode::Before)
+    );
+
+    assert!(read_fst(std::io::Cursor::new(VCD.as_bytes())).is_err());
+}
+
+#[cfg(feature = "fst")]
+#[test]
+fn test_fst_writer() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::fst::reader::{read_fst, WaveformFstReader};
+    use makai_waveform_db::fst::writer::{write_fst, WaveformFstCompression, WaveformFstWriter};
+    use makai_waveform_db::hierarchy::*;
+    use makai_waveform_db::timescale::WaveformTimescale;
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::WaveformSearchMode;
+
+    // Round trip through the writer keeps every value and variable
+    let expected = read_vcd(VCD.as_bytes()).unwrap();
+    let mut output = Vec::new();
+    write_fst(&expected, &mut output).unwrap();
+    let waveform = read_fst(std::io::Cursor::new(output)).unwrap();
+    assert_eq!(waveform.get_timestamps(), expected.get_timestamps());
+    for (variable, other) in waveform
+        .get_hierarchy()
+        .get_variables()
+        .iter()
+        .zip(expected.get_hierarchy().get_variables())
+    {
+        assert_eq!(variable, other);
+    }
+    for id in 0..4 {
+        for timestamp_index in 0..expected.timestamps_count() {
+            assert_eq!(
+                waveform.search_value(id, timestamp_index, WaveformSearchMode::Before),
+                expected.search_value(id, timestamp_index, WaveformSearchMode::Before)
+            );
+        }
+    }
+
+    // Ports are declared with the width of their values
+    let mut waveform = makai_waveform_db::Waveform::new();
+    waveform.initialize_vector(0, 4);
+    let hierarchy = waveform.get_hierarchy_mut();
+    hierarchy.push_scope("top", WaveformScopeType::Module);
+    hierarchy.add_variable(WaveformVariable::new(
+        "port",
+        0,
+        WaveformVariableType::Port,
+        4,
+        Some(WaveformBitRange { msb: 3, lsb: 0 }),
+        WaveformVariableDirection::Input,
+    ));
+    write_changes(&mut waveform, &[(0, 0, "01xz"), (5, 0, "1100")]);
+    let mut output = Vec::new();
+    write_fst(&waveform, &mut output).unwrap();
+    let read = read_fst(std::io::Cursor::new(output)).unwrap();
+    assert_eq!(
+        read.get_hierarchy().get_variables(),
+        waveform.get_hierarchy().get_variables()
+    );
+    assert_eq!(get_values(&read, 0), ["01xz", "1100"]);
+
+    // Many changes split across blocks with each compression, where the wide
+    // vector and the real change less often than the narrow vector
+    let mut waveform = makai_waveform_db::Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_vector(3, 77);
+    waveform.initialize_real(4);
+    for timestamp in 0..1000u64 {
+        waveform.insert_timestamp(timestamp * 5).unwrap();
+        waveform
+            .update_vector(0, BitVector::from(timestamp as u8))
+            .unwrap();
+        if timestamp % 7 == 0 {
+            let bits = ["01x", "z10"][timestamp as usize % 2].repeat(26);
+            waveform
+                .update_vector(3, BitVector::from_ascii_four_state(&bits.as_bytes()[..77]))
+                .unwrap();
+        }
+        if timestamp % 3 == 0 {
+            waveform.update_real(4, timestamp as f64 / 2.0).unwrap();
+        }
+    }
+    waveform.set_timescale(WaveformTimescale::parse("1ps"));
+    for compression in [WaveformFstCompression::Zlib, WaveformFstCompression::Lz4] {
+        let mut writer = WaveformFstWriter::new(&waveform);
+        writer.set_compression(compression);
+        writer.set_block_timestamps(300);
+        let mut output = Vec::new();
+        writer.write(&mut output).unwrap();
+        let reader = WaveformFstReader::new(std::io::Cursor::new(output)).unwrap();
+        assert_eq!(reader.get_timescale_exponent(), -12);
+        let read = reader.read().unwrap();
+        assert_eq!(read.get_hierarchy().find_id("signal_3"), Some(1));
+        assert_eq!(read.get_timestamps(), waveform.get_timestamps());
+        let (vector, wide, real) = (
+            get_values(&read, 0),
+            get_values(&read, 1),
+            get_values(&read, 2),
+        );
+        assert_eq!(vector[299..301], ["00101011", "00101100"]);
+        assert_eq!(vector[999], "11100111");
+        assert_eq!(wide[300], "01x".repeat(26)[..77]);
+        assert_eq!(wide[301], "z10".repeat(26)[..77]);
+        assert_eq!(real[599..601], ["298.5", "300"]);
+        assert_eq!(real[999], "499.5");
+    }
+
+    // Timestamps are converted to a finer timescale set on the writer
+    let expected = read_vcd(VCD.as_bytes()).unwrap();
+    let mut writer = WaveformFstWriter::new(&expected);
+    writer.set_timescale_exponent(-12);
+    writer.set_block_timestamps(3);
+    let mut output = Vec::new();
+    writer.write(&mut output).unwrap();
+    let waveform = read_fst(std::io::Cursor::new(output)).unwrap();
+    assert_eq!(waveform.get_timescale(), WaveformTimescale::parse("1ps"));
+    assert_eq!(waveform.get_timestamps(), &vec![0, 100000, 200000, 300000]);
+    for id in 0..4 {
+        for timestamp_index in 0..expected.timestamps_count() {
+            assert_eq!(
+                waveform.search_value(id, timestamp_index, WaveformSearchMode::Before),
+                expected.search_value(id, timestamp_index, WaveformSearchMode::Before)
+            );
+        }
+    }
+    writer.set_timescale_exponent(-6);
+    assert!(matches!(
+        writer.write(&mut Vec::new()),
+        Err(WaveformError::InvalidRescale { .. })
+    ));
+
+    // Filtering starts from the values in effect at the first timestamp
+    let expected = read_vcd(VCD.as_bytes()).unwrap();
+    let mut writer = WaveformFstWriter::new(&expected);
+    writer.set_range(Some(5..25));
+    writer.set_selected(Some([0, 3].into_iter().collect()));
+    let mut output = Vec::new();
+    writer.write(&mut output).unwrap();
+    let waveform = read_fst(std::io::Cursor::new(output)).unwrap();
+    assert_eq!(waveform.get_timestamps(), &vec![10, 20]);
+    assert_eq!(waveform.get_hierarchy().find_id("top.cpu.clk"), Some(0));
+    assert_eq!(waveform.get_hierarchy().find_id("top.data"), None);
+    let nibble = waveform.get_vector_signal(1).unwrap();
+    assert_eq!(nibble.get_bitvector(0).to_string(), "b0001");
+    assert_eq!(nibble.get_bitvector(1).to_string(), "bZZZ1");
+    assert_eq!(
+        waveform
+            .get_vector_signal(0)
+            .unwrap()
+            .get_bitvector(0)
+            .to_string(),
+        "b1"
+    );
+}
+
+#[test]
+fn test_waveform_save_load() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::{WaveformError, WaveformNativeError};
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::Waveform;
+
+    // Bounded waveforms keep their bound and truncated packed values
+    let mut waveform = Waveform::new_bounded(1000);
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_vector(1, 77);
+    waveform.initialize_real(2);
+    for timestamp in 0..5001u64 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        waveform
+            .update_vector(0, BitVector::from(timestamp as u8))
+            .unwrap();
+        if timestamp % 700 == 0 {
+            let bits = format!("{:077b}", timestamp);
+            waveform
+                .update_vector(1, BitVector::from_ascii(bits.as_bytes()))
+                .unwrap();
+            waveform.update_real(2, timestamp as f64).unwrap();
+        }
+    }
+    let mut output = Vec::new();
+    waveform.save(&mut output).unwrap();
+    let mut loaded = Waveform::load(output.as_slice()).unwrap();
+    assert_eq!(loaded.get_max_timestamps(), Some(1000));
+    assert_eq!(loaded.get_timestamps(), waveform.get_timestamps());
+    for id in 0..3 {
+        assert_eq!(get_values(&loaded, id), get_values(&waveform, id));
+    }
+    // The last drop kept 3003 onwards, where the value written at 2800 holds
+    assert_eq!(loaded.get_timestamps()[0], 3003);
+    assert_eq!(get_values(&loaded, 2)[0], "2800");
+    assert_eq!(
+        loaded.get_block_size() + loaded.get_vector_size(),
+        waveform.get_block_size() + waveform.get_vector_size()
+    );
+    // Loaded signals can keep being updated
+    write_changes(
+        &mut loaded,
+        &[
+            (5001, 0, "00000001"),
+            (5002, 1, &"1".repeat(77)),
+            (5002, 2, "0.5"),
+        ],
+    );
+    let last = loaded.timestamps_count() - 1;
+    assert_eq!(get_values(&loaded, 0)[last], "00000001");
+    assert_eq!(get_values(&loaded, 1)[last - 1], format!("{:077b}", 4900));
+    assert_eq!(get_values(&loaded, 1)[last], "1".repeat(77));
+    assert_eq!(get_values(&loaded, 2)[last - 1..], ["4900", "0.5"]);
+
+    // The hierarchy is loaded with the same indices
+    let waveform = read_vcd(VCD.as_bytes()).unwrap();
+    let mut output = Vec::new();
+    waveform.save(&mut output).unwrap();
+    let loaded = Waveform::load(output.as_slice()).unwrap();
+    assert_eq!(loaded.get_max_timestamps(), None);
+    assert_eq!(loaded.get_timescale(), waveform.get_timescale());
+    let (hierarchy, expected) = (loaded.get_hierarchy(), waveform.get_hierarchy());
+    assert_eq!(hierarchy.get_scopes(), expected.get_scopes());
+    assert_eq!(hierarchy.get_variables(), expected.get_variables());
+    assert_eq!(hierarchy.find_id("top.cpu.temp"), Some(2));
+    assert_eq!(hierarchy.get_variables_by_id(0).len(), 2);
+    assert_eq!(loaded.get_real_signal(2).unwrap().get_real(0), 1.5);
+
+    // Corruption anywhere is caught by a checksum
+    for offset in [20, 60, output.len() - 600, output.len() - 1] {
+        let mut corrupted = output.clone();
+        corrupted[offset] ^= 1;
+        assert!(matches!(
+            Waveform::load(corrupted.as_slice()),
+            Err(WaveformError::InvalidNative {
+                error: WaveformNativeError::InvalidChecksum { .. }
+            })
+        ));
+    }
+    assert!(matches!(
+        Waveform::load(VCD.as_bytes()),
+        Err(WaveformError::InvalidNative {
+            error: WaveformNativeError::InvalidMagic
+        })
+    ));
+    assert!(matches!(
+        Waveform::load(&output[..output.len() - 1]),
+        Err(WaveformError::Io { .. })
+    ));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_waveform_mapped() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::{WaveformError, WaveformNativeError};
+    use makai_waveform_db::native::mapped::WaveformMapped;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformSignalResult};
+
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_vector(3, 77);
+    waveform.initialize_real(4);
+    for timestamp in 0..5000u64 {
+        waveform.insert_timestamp(timestamp * 3).unwrap();
+        waveform
+            .update_vector(0, BitVector::from((timestamp / 2) as u8))
+            .unwrap();
+        if timestamp % 10 == 0 {
+            let bits = format!("{:077b}", timestamp * 3);
+            waveform
+                .update_vector(3, BitVector::from_ascii(bits.as_bytes()))
+                .unwrap();
+        }
+        if timestamp % 9 == 0 {
+            waveform.update_real(4, timestamp as f64).unwrap();
+        }
+    }
+    let mut output = Vec::new();
+    waveform.save(&mut output).unwrap();
+    let path = std::env::temp_dir().join(format!("makai_mapped_{}.bin", std::process::id()));
+    std::fs::write(&path, &output).unwrap();
+
+    // Signals are only paged in once they are accessed
+    let mapped = WaveformMapped::open(&std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        mapped.get_timestamps(),
+        waveform.get_timestamps().as_slice()
+    );
+    assert_eq!(
+        mapped.search_timestamp(7, WaveformSearchMode::After),
+        Some(3)
+    );
+    assert!(!mapped.is_loaded(3));
+    for id in [0, 3, 4] {
+        assert_eq!(get_values(&mapped, id), get_values(&waveform, id));
+    }
+    assert!(mapped.is_loaded(3));
+    assert_eq!(get_values(&mapped, 3)[4999], format!("{:077b}", 4990 * 3));
+    assert_eq!(get_values(&mapped, 4)[4999], "4995");
+    let (Some(WaveformSignalResult::Vector(signal)), Some(expected)) =
+        (mapped.get_signal(3), waveform.get_vector_signal(3))
+    else {
+        panic!("Signal 3 is a vector");
+    };
+    assert!(signal
+        .iter_range(100..900)
+        .eq(expected.iter_range(100..900)));
+    assert!(mapped.validate().is_ok());
+
+    // Corrupted signals are only found once they are paged in
+    let last = output.len() - 1;
+    output[last] ^= 1;
+    std::fs::write(&path, &output).unwrap();
+    let mapped = WaveformMapped::open(&std::fs::File::open(&path).unwrap()).unwrap();
+    assert!(mapped.get_signal(0).is_some());
+    assert!(mapped.get_signal(4).is_none());
+    assert!(matches!(
+        mapped.load_signal(4),
+        Err(WaveformError::InvalidNative {
+            error: WaveformNativeError::InvalidChecksum { .. }
+        })
+    ));
+    assert!(mapped.validate().is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_waveform_live() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::live::WaveformLiveWriter;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::search::WaveformPredicate;
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+    let mut writer = WaveformLiveWriter::new(Waveform::new());
+    writer.initialize_vector(0, 16).unwrap();
+    writer.initialize_real(1).unwrap();
+    assert!(matches!(
+        writer.initialize_real(0),
+        Err(WaveformError::DuplicateId { id: 0 })
+    ));
+    let reader = writer.get_reader();
+    assert_eq!(
+        reader
+            .snapshot()
+            .search_value(0, 0, WaveformSearchMode::Before),
+        None
+    );
+
+    // Readers only ever see whole batches of committed timestamps
+    let readers = (0..4)
+        .map(|_| {
+            let reader = reader.clone();
+            std::thread::spawn(move || loop {
+                let snapshot = reader.snapshot();
+                let count = snapshot.timestamps_count();
+                assert_eq!(count % 100, 0);
+                if count > 0 {
+                    let last = count - 1;
+                    assert_eq!(snapshot.get_timestamp(last), Some(last as u64 * 2));
+                    assert_eq!(
+                        snapshot.search_value(0, usize::MAX, WaveformSearchMode::Before),
+                        Some(WaveformValueResult::Vector(
+                            BitVector::from(last as u16),
+                            last
+                        ))
+                    );
+                    assert_eq!(
+                        snapshot.search_value(1, last, WaveformSearchMode::After),
+                        Some(WaveformValueResult::Real(last as f64, last))
+                    );
+                    assert_eq!(
+                        snapshot.search_value(0, count, WaveformSearchMode::Exact),
+                        None
+                    );
+                    assert_eq!(
+                        snapshot.search_timestamp(u64::MAX, WaveformSearchMode::Before),
+                        Some(last)
+                    );
+                }
+                if count == 10000 {
+                    return;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for timestamp_index in 0..10000u64 {
+        writer.insert_timestamp(timestamp_index * 2).unwrap();
+        writer
+            .update_vector(0, BitVector::from(timestamp_index as u16))
+            .unwrap();
+        if timestamp_index % 100 == 99 {
+            writer.update_real(1, timestamp_index as f64).unwrap();
+            writer.commit();
+        }
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    // Committed timestamps can no longer be changed
+    assert_eq!(writer.get_committed(), 10000);
+    assert!(matches!(
+        writer.update_vector(0, BitVector::from(1u16)),
+        Err(WaveformError::CommittedTimestamp { timestamp: 19998 })
+    ));
+    assert!(matches!(
+        writer.insert_timestamp(19998),
+        Err(WaveformError::CommittedTimestamp { timestamp: 19998 })
+    ));
+    writer.insert_timestamp(20000).unwrap();
+    writer.update_vector(0, BitVector::from(1u16)).unwrap();
+    let snapshot = reader.snapshot();
+    assert_eq!(
+        snapshot.search_value(0, 9999, WaveformSearchMode::Closest),
+        Some(WaveformValueResult::Vector(BitVector::from(9999u16), 9999))
+    );
+
+    // Queries of the snapshot stop at its last timestamp
+    snapshot.read(|view| {
+        assert_eq!(view.get_timestamps().len(), 10000);
+        let value = |index: usize| {
+            Some(WaveformValueResult::Vector(
+                BitVector::from(index as u16),
+                index,
+            ))
+        };
+        assert_eq!(
+            view.search_value(0, usize::MAX, WaveformSearchMode::Before),
+            value(9999)
+        );
+        assert_eq!(
+            view.find_next(0, 9998, &WaveformPredicate::Change, None),
+            value(9999)
+        );
+        assert_eq!(
+            view.find_next(0, 9999, &WaveformPredicate::Change, None),
+            None
+        );
+        assert_eq!(
+            view.find_prev(0, usize::MAX, &WaveformPredicate::Change, None),
+            value(9999)
+        );
+        assert_eq!(
+            view.sample(&[0], &[9999, 10000]),
+            [[value(9999), value(9999)]]
+        );
+        assert_eq!(view.iter_events(&[0], 9999..usize::MAX).count(), 1);
+    });
+}
+
+#[test]
+fn test_waveform_timescale() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::timescale::*;
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::vcd::writer::{write_vcd, WaveformVcdWriter};
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+    // Timescales are parsed as declared in a VCD
+    let timescale = WaveformTimescale::parse("10 ps").unwrap();
+    assert_eq!(timescale.get_magnitude(), 10);
+    assert_eq!(timescale.get_unit(), WaveformTimeUnit::Picosecond);
+    assert_eq!(timescale.get_exponent(), -11);
+    assert_eq!(timescale.get_femtoseconds(), 10_000);
+    assert_eq!(timescale.to_string(), "10ps");
+    assert_eq!(WaveformTimescale::from_exponent(-11), Some(timescale));
+    assert_eq!(
+        WaveformTimescale::from_exponent(2).unwrap().to_string(),
+        "100s"
+    );
+    assert_eq!(WaveformTimescale::from_exponent(-16), None);
+    assert_eq!(
+        WaveformTimescale::parse("1fs").unwrap().get_femtoseconds(),
+        1
+    );
+    assert_eq!(WaveformTimescale::parse("3ns"), None);
+    assert_eq!(WaveformTimescale::parse("10 xs"), None);
+
+    // Physical times convert exactly and round to the nearest tick
+    assert_eq!(parse_time("12.5 ns"), Some(12_500_000));
+    assert_eq!(parse_time("3us"), Some(3_000_000_000));
+    assert_eq!(parse_time(".25ps"), Some(250));
+    assert_eq!(parse_time("0.0001 fs"), None);
+    assert_eq!(parse_time("12.5"), None);
+    assert_eq!(parse_time("ns"), None);
+    assert_eq!(format_time(12_500_000), "12.5 ns");
+    assert_eq!(format_time(3_000_000_000), "3 us");
+    assert_eq!(format_time(250), "250 fs");
+    assert_eq!(format_time(0), "0 fs");
+    assert_eq!(timescale.format_ticks(1250), "12.5 ns");
+    assert_eq!(timescale.parse_ticks("12.5 ns"), Some(1250));
+    assert_eq!(timescale.parse_ticks("12.504 ns"), Some(1250));
+    assert_eq!(timescale.parse_ticks("12.505 ns"), Some(1251));
+    assert_eq!(timescale.ticks_to_seconds(100_000), 1e-6);
+
+    // Rescaling only goes to finer timescales
+    let mut waveform = read_vcd(VCD.as_bytes()).unwrap();
+    let nanoseconds = WaveformTimescale::parse("1ns").unwrap();
+    waveform.rescale(nanoseconds).unwrap();
+    assert_eq!(waveform.get_timescale(), Some(nanoseconds));
+    assert_eq!(waveform.get_timestamps(), &vec![0, 100, 200, 300]);
+    assert!(matches!(
+        waveform.rescale(WaveformTimescale::parse("1us").unwrap()),
+        Err(WaveformError::InvalidRescale { .. })
+    ));
+    assert_eq!(waveform.get_timestamps(), &vec![0, 100, 200, 300]);
+    let mut output = Vec::new();
+    write_vcd(&waveform, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("$timescale 1ns $end"));
+    assert!(output.contains("#300"));
+
+    // Writers convert timestamps to a finer timescale set on them, leaving
+    // the waveform as is
+    let mut writer = WaveformVcdWriter::new(&waveform);
+    writer.set_timescale(WaveformTimescale::parse("100ps"));
+    let mut output = Vec::new();
+    writer.write(&mut output).unwrap();
+    let written = read_vcd(output.as_slice()).unwrap();
+    assert_eq!(written.get_timescale(), WaveformTimescale::parse("100ps"));
+    assert_eq!(written.get_timestamps(), &vec![0, 1000, 2000, 3000]);
+    assert_eq!(waveform.get_timestamps(), &vec![0, 100, 200, 300]);
+    writer.set_timescale(WaveformTimescale::parse("10ns"));
+    assert!(matches!(
+        writer.write(&mut Vec::new()),
+        Err(WaveformError::InvalidRescale { .. })
+    ));
+    let mut overflowing = Waveform::new();
+    overflowing.set_timescale(WaveformTimescale::parse("1s"));
+    overflowing.insert_timestamp(u64::MAX / 10).unwrap();
+    let mut writer = WaveformVcdWriter::new(&overflowing);
+    writer.set_timescale(WaveformTimescale::parse("1ms"));
+    assert!(matches!(
+        writer.write(&mut Vec::new()),
+        Err(WaveformError::InvalidRescale { .. })
+    ));
+    // Waveforms without a timescale only take on the declaration
+    let mut unscaled = read_vcd(VCD.as_bytes()).unwrap();
+    unscaled.set_timescale(None);
+    let mut writer = WaveformVcdWriter::new(&unscaled);
+    writer.set_timescale(WaveformTimescale::parse("1ps"));
+    let mut output = Vec::new();
+    writer.write(&mut output).unwrap();
+    let written = read_vcd(output.as_slice()).unwrap();
+    assert_eq!(written.get_timescale(), WaveformTimescale::parse("1ps"));
+    assert_eq!(written.get_timestamps(), &vec![0, 10, 20, 30]);
+
+    // Merged waveforms use the finest timescale
+    let mut waveform_a = Waveform::new();
+    waveform_a.set_timescale(WaveformTimescale::parse("1ns"));
+    waveform_a.initialize_vector(0, 8);
+    waveform_a.insert_timestamp(0).unwrap();
+    waveform_a.update_vector(0, BitVector::from(0u8)).unwrap();
+    waveform_a.insert_timestamp(2).unwrap();
+    waveform_a.update_vector(0, BitVector::from(1u8)).unwrap();
+    let mut waveform_b = Waveform::new();
+    waveform_b.set_timescale(WaveformTimescale::parse("100ps"));
+    waveform_b.initialize_vector(1, 8);
+    waveform_b.insert_timestamp(15).unwrap();
+    waveform_b.update_vector(1, BitVector::from(1u8)).unwrap();
+    let merged = Waveform::merge(vec![waveform_a, waveform_b]).unwrap();
+    assert_eq!(merged.get_timescale(), WaveformTimescale::parse("100ps"));
+    assert_eq!(merged.get_timestamps(), &vec![0, 15, 20]);
+    assert_eq!(
+        merged.search_value(0, 2, WaveformSearchMode::Exact),
+        Some(WaveformValueResult::Vector(BitVector::from(1u8), 2))
+    );
+
+    // Appended waveforms are rescaled before checking for overlap
+    let mut first = Waveform::new();
+    first.set_timescale(WaveformTimescale::parse("1us"));
+    first.initialize_vector(0, 8);
+    first.insert_timestamp(1).unwrap();
+    first.update_vector(0, BitVector::from(0u8)).unwrap();
+    let mut second = Waveform::new();
+    second.set_timescale(WaveformTimescale::parse("1ns"));
+    second.initialize_vector(0, 8);
+    second.insert_timestamp(500).unwrap();
+    second.update_vector(0, BitVector::from(1u8)).unwrap();
+    assert!(matches!(
+        first.append(second),
+        Err(WaveformError::OverlappingTimestamps { timestamp: 500 })
+    ));
+    assert_eq!(first.get_timestamps(), &vec![1]);
+    assert_eq!(first.get_timescale(), WaveformTimescale::parse("1us"));
+    let mut second = Waveform::new();
+    second.set_timescale(WaveformTimescale::parse("1ns"));
+    second.initialize_vector(0, 8);
+    second.insert_timestamp(1500).unwrap();
+    second.update_vector(0, BitVector::from(1u8)).unwrap();
+    first.append(second).unwrap();
+    assert_eq!(first.get_timestamps(), &vec![1000, 1500]);
+    assert_eq!(first.get_timescale(), WaveformTimescale::parse("1ns"));
+}
+
+#[test]
+fn test_waveform_find() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::search::WaveformPredicate;
+    use makai_waveform_db::{Waveform, WaveformValueResult};
+
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(2, 4);
+    waveform.initialize_real(4);
+    write_changes(
+        &mut waveform,
+        &[
+            (0, 0, "0"),
+            (0, 2, "0000"),
+            (0, 4, "0.5"),
+            (1, 0, "1"),
+            (1, 2, "0101"),
+            (2, 2, "1000"),
+            (3, 0, "0"),
+            (3, 2, "1001"),
+            (4, 2, "0101"),
+            (4, 4, "1.5"),
+            (5, 0, "1"),
+            (6, 2, "1100"),
+        ],
+    );
+    let found = |result: Option<WaveformValueResult>| {
+        result.map(|result| (result.get_timestamp_index(), format_value(&result)))
+    };
+
+    // Searches only look strictly after or before the timestamp index
+    let rising = WaveformPredicate::Rising;
+    let next = |id, timestamp_index, predicate, bit_index| {
+        found(waveform.find_next(id, timestamp_index, predicate, bit_index))
+    };
+    let prev = |id, timestamp_index, predicate, bit_index| {
+        found(waveform.find_prev(id, timestamp_index, predicate, bit_index))
+    };
+    assert_eq!(next(0, 0, &rising, None), Some((1, "1".to_string())));
+    assert_eq!(next(0, 1, &rising, None), Some((5, "1".to_string())));
+    assert_eq!(next(0, 5, &rising, None), None);
+    assert_eq!(prev(0, 5, &rising, None), Some((1, "1".to_string())));
+    assert_eq!(prev(0, 1, &rising, None), None);
+    assert_eq!(
+        next(0, 0, &WaveformPredicate::Falling, None),
+        Some((3, "0".to_string()))
+    );
+
+    // Values match where they start to equal the value or masked bits
+    let equals = WaveformPredicate::Equals(BitVector::from_ascii(b"0101"));
+    assert_eq!(next(2, 0, &equals, None), Some((1, "0101".to_string())));
+    assert_eq!(next(2, 1, &equals, None), Some((4, "0101".to_string())));
+    assert_eq!(prev(2, 4, &equals, None), Some((1, "0101".to_string())));
+    let masked = WaveformPredicate::Masked {
+        value: BitVector::from_ascii(b"1000"),
+        mask: BitVector::from_ascii(b"1100"),
+    };
+    assert_eq!(next(2, 0, &masked, None), Some((2, "1000".to_string())));
+    assert_eq!(next(2, 2, &masked, None), None);
+
+    // Single bits only match changes of that bit
+    assert_eq!(next(2, 1, &rising, Some(0)), Some((3, "1".to_string())));
+    let change = WaveformPredicate::Change;
+    assert_eq!(next(2, 2, &change, Some(3)), Some((4, "0".to_string())));
+    assert_eq!(prev(2, 6, &change, Some(3)), Some((4, "0".to_string())));
+
+    // Real signals only match changes
+    assert_eq!(next(4, 0, &change, None), Some((4, "1.5".to_string())));
+    assert_eq!(next(4, 0, &rising, None), None);
+    assert_eq!(next(5, 0, &change, None), None);
+
+    // Searches across many history blocks, toggling every 7 timestamps
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    for timestamp in 0..3000u64 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        let value = [b'0' + (timestamp / 7 % 2) as u8];
+        waveform
+            .update_vector(0, BitVector::from_ascii(&value))
+            .unwrap();
+    }
+    let index = |result: Option<WaveformValueResult>| result.map(|r| r.get_timestamp_index());
+    assert_eq!(index(waveform.find_next(0, 0, &rising, None)), Some(7));
+    assert_eq!(index(waveform.find_next(0, 7, &rising, None)), Some(21));
+    assert_eq!(index(waveform.find_next(0, 2990, &rising, None)), None);
+    assert_eq!(
+        index(waveform.find_prev(0, 2999, &rising, None)),
+        Some(2989)
+    );
+    let falling = WaveformPredicate::Falling;
+    assert_eq!(index(waveform.find_next(0, 0, &falling, None)), Some(14));
+    // The first value counts as falling from unknown
+    assert_eq!(index(waveform.find_prev(0, 14, &falling, None)), Some(0));
+
+    // Edges and unknowns with four-state values
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 2);
+    for (timestamp, value) in [b"00", b"0x", b"01", b"z1", b"z0", b"10", b"11"]
+        .into_iter()
+        .enumerate()
+    {
+        waveform.insert_timestamp(timestamp as u64).unwrap();
+        waveform
+            .update_vector(0, BitVector::from_ascii_four_state(value))
+            .unwrap();
+    }
+    let find_all = |predicate: &WaveformPredicate, bit_index: Option<usize>| {
+        let mut indices = Vec::new();
+        let mut timestamp_index = 0;
+        while let Some(result) = waveform.find_next(0, timestamp_index, predicate, bit_index) {
+            timestamp_index = result.get_timestamp_index();
+            indices.push(timestamp_index);
+        }
+        indices
+    };
+    assert_eq!(find_all(&WaveformPredicate::Rising, None), vec![1, 2, 6]);
+    assert_eq!(find_all(&WaveformPredicate::Falling, None), vec![4]);
+    assert_eq!(find_all(&WaveformPredicate::Unknown, None), vec![1, 3]);
+    assert_eq!(find_all(&WaveformPredicate::Unknown, Some(1)), vec![3]);
+    assert_eq!(find_all(&WaveformPredicate::Change, Some(1)), vec![3, 5]);
+    let masked = WaveformPredicate::Masked {
+        value: BitVector::from_ascii_four_state(b"z0"),
+        mask: BitVector::from_ascii(b"10"),
+    };
+    assert_eq!(find_all(&masked, None), vec![3]);
+    assert_eq!(
+        waveform.find_prev(0, 7, &WaveformPredicate::Falling, Some(1)),
+        Some(WaveformValueResult::Vector(BitVector::from_ascii(b"0"), 0))
+    );
+    assert_eq!(
+        waveform.find_prev(0, 100, &WaveformPredicate::Rising, None),
+        Some(WaveformValueResult::Vector(BitVector::from_ascii(b"11"), 6))
+    );
+}
+
+#[test]
+fn test_waveform_bit_changes() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::{Waveform, WaveformSearchMode};
+
+    let bv = BitVector::from_ascii_four_state(b"10xz0110");
+    assert_eq!(
+        bv.get_range(2..6),
+        BitVector::from_ascii_four_state(b"xz01")
+    );
+    assert_eq!(bv.get_range(6..10), BitVector::from_ascii(b"0010"));
+
+    // One bit flips at each change, where only another signal changes at
+    // timestamp 3
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_vector(1, 1);
+    write_changes(
+        &mut waveform,
+        &[
+            (0, 0, "00000000"),
+            (1, 0, "00000001"),
+            (2, 0, "00010001"),
+            (3, 1, "1"),
+            (4, 0, "10010001"),
+            (5, 0, "10011001"),
+        ],
+    );
+    waveform.insert_timestamp(6).unwrap();
+    let signal = waveform.get_vector_signal(0).unwrap();
+    let changes = |range: std::ops::Range<usize>, bits: std::ops::Range<usize>| {
+        signal
+            .iter_bit_changes(range, bits)
+            .map(|(index, bv)| (index, bv.to_string()[1..].to_string()))
+            .collect::<Vec<_>>()
+    };
+    let expected = |changes: &[(usize, &str)]| {
+        changes
+            .iter()
+            .map(|(index, value)| (*index, value.to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(changes(0..7, 0..1), expected(&[(0, "0"), (1, "1")]));
+    assert_eq!(
+        changes(0..7, 4..8),
+        expected(&[(0, "0000"), (2, "0001"), (4, "1001")])
+    );
+    assert_eq!(
+        changes(0..7, 3..5),
+        expected(&[(0, "00"), (2, "10"), (5, "11")])
+    );
+    assert_eq!(
+        changes(0..7, 0..8)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>(),
+        vec![0, 1, 2, 4, 5]
+    );
+    assert_eq!(changes(2..5, 4..8), expected(&[(2, "0001"), (4, "1001")]));
+    assert_eq!(changes(6..7, 0..8), vec![]);
+
+    let search = |timestamp_index, search_mode, bits| {
+        waveform
+            .search_bit_change(0, timestamp_index, search_mode, bits)
+            .map(|result| (result.get_timestamp_index(), format_value(&result)))
+    };
+    let found = |index: usize, value: &str| Some((index, value.to_string()));
+    assert_eq!(
+        search(3, WaveformSearchMode::Before, 4..8),
+        found(2, "0001")
+    );
+    assert_eq!(search(3, WaveformSearchMode::After, 4..8), found(4, "1001"));
+    assert_eq!(search(3, WaveformSearchMode::Exact, 4..8), None);
+    assert_eq!(search(4, WaveformSearchMode::Exact, 4..8), found(4, "1001"));
+    // Ties go to the earlier change
+    assert_eq!(
+        search(3, WaveformSearchMode::Closest, 4..8),
+        found(2, "0001")
+    );
+    assert_eq!(search(4, WaveformSearchMode::Closest, 3..5), found(5, "11"));
+    assert_eq!(search(5, WaveformSearchMode::After, 4..8), None);
+    assert_eq!(search(6, WaveformSearchMode::Before, 0..1), found(1, "1"));
+
+    // Changes of one bit are found across many history blocks of changes to
+    // the other bits
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 16);
+    for timestamp in 0..3000u64 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        let value = (timestamp / 500 % 2) << 15 | (timestamp & 0x7fff);
+        waveform
+            .update_vector(0, BitVector::from_bits_two_state(16, value as u16))
+            .unwrap();
+    }
+    let signal = waveform.get_vector_signal(0).unwrap();
+    assert_eq!(
+        signal
+            .iter_bit_changes(0..3000, 15..16)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>(),
+        vec![0, 500, 1000, 1500, 2000, 2500]
+    );
+    let index = |search_mode| {
+        waveform
+            .search_bit_change(0, 1999, search_mode, 15..16)
+            .map(|result| result.get_timestamp_index())
+    };
+    assert_eq!(index(WaveformSearchMode::Before), Some(1500));
+    assert_eq!(index(WaveformSearchMode::After), Some(2000));
+    assert_eq!(index(WaveformSearchMode::Closest), Some(2000));
+    assert_eq!(
+        waveform.search_bit_change(0, 3000, WaveformSearchMode::After, 0..16),
+        None
+    );
+}
+
+#[test]
+fn test_waveform_sample() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::Waveform;
+
+    // The vector changes every 5 timestamps and the real every 1000, where
+    // id 9 has no signal
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_real(4);
+    for timestamp in 0..20000u64 {
+        waveform.insert_timestamp(timestamp * 3).unwrap();
+        if timestamp % 5 == 0 {
+            waveform
+                .update_vector(0, BitVector::from((timestamp / 5) as u8))
+                .unwrap();
+        }
+        if timestamp % 1000 == 0 {
+            waveform.update_real(4, timestamp as f64).unwrap();
+        }
+    }
+    let ids = [9, 0, 4];
+    let sample = |timestamp_indices: &[usize]| {
+        waveform
+            .sample(&ids, timestamp_indices)
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| value.as_ref().map_or("-".to_string(), format_value))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    // Repeated samples, samples past the end and no samples at all
+    assert_eq!(
+        sample(&[0, 0, 4, 5, 19999, 19999, 25000]),
+        [
+            ["-", "-", "-", "-", "-", "-", "-"],
+            ["00000000", "00000000", "00000000", "00000001", "10011111", "10011111", "10011111"],
+            ["0", "0", "0", "0", "19000", "19000", "19000"],
+        ]
+    );
+    assert_eq!(sample(&[]), [[""; 0]; 3]);
+
+    // Dense samples across many blocks
+    let timestamp_indices = (0..20000).step_by(7).collect::<Vec<usize>>();
+    let table = sample(&timestamp_indices);
+    for (i, timestamp_index) in timestamp_indices.iter().enumerate() {
+        assert_eq!(table[0][i], "-");
+        assert_eq!(table[1][i], format!("{:08b}", timestamp_index / 5 % 256));
+        assert_eq!(table[2][i], (timestamp_index / 1000 * 1000).to_string());
+    }
+
+    // Values before the first change are missing
+    let mut waveform = Waveform::new();
+    waveform.initialize_real(0);
+    for timestamp in 0..10 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        if timestamp % 4 == 3 {
+            waveform.update_real(0, timestamp as f64).unwrap();
+        }
+    }
+    let table = waveform.sample(&[0], &[0, 2, 3, 6, 7, 9]);
+    let values = table[0]
+        .iter()
+        .map(|value| value.as_ref().map(|value| value.get_timestamp_index()))
+        .collect::<Vec<Option<usize>>>();
+    assert_eq!(values, vec![None, None, Some(3), Some(3), Some(7), Some(7)]);
+}
+
+#[test]
+fn test_waveform_snapshot() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::{Waveform, WaveformValueResult};
+
+    // Snapshots of a scope include the aliases and nested scopes inside it
+    let waveform = read_vcd(VCD.as_bytes()).unwrap();
+    let cpu = waveform.get_hierarchy().find_scope("top.cpu").unwrap();
+    let ids = |snapshot: &[(usize, WaveformValueResult)]| {
+        snapshot.iter().map(|(id, _)| *id).collect::<Vec<usize>>()
+    };
+    assert_eq!(ids(&waveform.snapshot(2, Some(cpu))), vec![0, 2, 3]);
+    let top = waveform.get_hierarchy().find_scope("top").unwrap();
+    assert_eq!(ids(&waveform.snapshot(2, Some(top))), vec![0, 1, 2, 3]);
+    assert_eq!(
+        waveform.snapshot(2, Some(cpu))[2],
+        (
+            3,
+            WaveformValueResult::Vector(BitVector::from_ascii_four_state(b"zzz1"), 2)
+        )
+    );
+
+    // Snapshots leave out signals without a value yet, where id 9 is never
+    // written
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 4);
+    waveform.initialize_vector(1, 1);
+    waveform.initialize_real(2);
+    waveform.initialize_vector(9, 8);
+    write_changes(
+        &mut waveform,
+        &[(0, 0, "0001"), (1, 1, "1"), (2, 2, "2.5"), (3, 0, "0010")],
+    );
+    let values = |snapshot: &[(usize, WaveformValueResult)]| {
+        snapshot
+            .iter()
+            .map(|(id, value)| (*id, value.get_timestamp_index(), format_value(value)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        values(&waveform.snapshot(0, None)),
+        [(0, 0, "0001".to_string())]
+    );
+    assert_eq!(
+        values(&waveform.snapshot(3, None)),
+        [
+            (0, 3, "0010".to_string()),
+            (1, 1, "1".to_string()),
+            (2, 2, "2.5".to_string())
+        ]
+    );
+
+    // Values are found across many blocks, with the same snapshot taken on
+    // any number of threads
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 8);
+    waveform.initialize_vector(1, 1);
+    waveform.initialize_real(2);
+    waveform.initialize_vector(9, 8);
+    for timestamp in 0..5000u64 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        waveform
+            .update_vector(0, BitVector::from(timestamp as u8))
+            .unwrap();
+        if timestamp % 700 == 0 {
+            let value = [b'0' + (timestamp / 700 % 2) as u8];
+            waveform
+                .update_vector(1, BitVector::from_ascii(&value))
+                .unwrap();
+        }
+        if timestamp % 1000 == 0 {
+            waveform.update_real(2, timestamp as f64).unwrap();
+        }
+    }
+    let snapshot = waveform.snapshot(4321, None);
+    assert_eq!(
+        values(&snapshot),
+        [
+            (0, 4321, "11100001".to_string()),
+            (1, 4200, "0".to_string()),
+            (2, 4000, "4000".to_string())
+        ]
+    );
+    for num_threads in [1, 2, 16] {
+        assert_eq!(
+            waveform.snapshot_parallel(4321, None, num_threads),
+            snapshot
+        );
+    }
+}
+
+#[test]
+fn test_waveform_events() {
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::Waveform;
+
+    // Id 9 has no signal and is skipped
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(1, 2);
+    waveform.initialize_vector(3, 4);
+    waveform.initialize_real(4);
+    write_changes(
+        &mut waveform,
+        &[
+            (0, 0, "1"),
+            (0, 1, "00"),
+            (0, 3, "0000"),
+            (2, 4, "1.5"),
+            (4, 0, "0"),
+            (4, 3, "0001"),
+            (6, 1, "01"),
+            (6, 4, "2.5"),
+        ],
+    );
+
+    // Changes at the same timestamp come in the order of the ids given
+    let ids = [3, 0, 9, 4, 1];
+    let events = |range| {
+        waveform
+            .iter_events(&ids, range)
+            .map(|(timestamp, id, value)| (timestamp, id, format_value(&value)))
+            .collect::<Vec<_>>()
+    };
+    let expected = |events: &[(u64, usize, &str)]| {
+        events
+            .iter()
+            .map(|(timestamp, id, value)| (*timestamp, *id, value.to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        events(0..4),
+        expected(&[
+            (0, 3, "0000"),
+            (0, 0, "1"),
+            (0, 1, "00"),
+            (2, 4, "1.5"),
+            (4, 3, "0001"),
+            (4, 0, "0"),
+            (6, 4, "2.5"),
+            (6, 1, "01"),
+        ])
+    );
+    assert_eq!(
+        events(1..3),
+        expected(&[(2, 4, "1.5"), (4, 3, "0001"), (4, 0, "0")])
+    );
+    assert_eq!(events(3..4), expected(&[(6, 4, "2.5"), (6, 1, "01")]));
+    assert_eq!(events(4..10), vec![]);
+    assert_eq!(waveform.iter_events(&[], 0..4).count(), 0);
+    assert_eq!(
+        waveform
+            .iter_events(&[0], 0..4)
+            .map(|(_, _, value)| value.get_timestamp_index())
+            .collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+}
+
+#[test]
+fn test_waveform_cycles() {
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::search::WaveformPredicate;
+    use makai_waveform_db::Waveform;
+
+    // Clock toggling at every timestamp, where data changes at the same time
+    // as the rising edge at index 3 and id 9 has no signal
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(1, 2);
+    waveform.initialize_real(4);
+    let data = [
+        (0, 1, "00"),
+        (15, 1, "01"),
+        (20, 4, "0.5"),
+        (30, 1, "10"),
+        (40, 1, "11"),
+    ];
+    for timestamp in (0..60).step_by(5) {
+        let clock = ["0", "1"][timestamp as usize / 5 % 2];
+        write_changes(&mut waveform, &[(timestamp, 0, clock)]);
+        for (_, id, value) in data.iter().filter(|(t, _, _)| *t == timestamp) {
+            write_changes(&mut waveform, &[(timestamp, *id, value)]);
+        }
+    }
+    let ids = [1, 9, 4];
+    let cycles = |edge, setup| {
+        let cycles = waveform.sample_cycles(0, edge, &ids, setup);
+        assert_eq!(
+            waveform
+                .iter_cycles(0, edge, &ids, setup)
+                .collect::<Vec<_>>(),
+            cycles
+        );
+        cycles
+            .iter()
+            .map(|cycle| {
+                assert_eq!(
+                    cycle.get_timestamp(),
+                    cycle.get_timestamp_index() as u64 * 5
+                );
+                let values = cycle
+                    .get_values()
+                    .iter()
+                    .map(|value| value.as_ref().map_or("-".to_string(), format_value))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                (cycle.get_timestamp_index(), values)
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = |cycles: &[(usize, &str)]| {
+        cycles
+            .iter()
+            .map(|(index, values)| (*index, values.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    // Values are sampled at the last timestamp before the edge
+    let rising = WaveformPredicate::Rising;
+    assert_eq!(
+        cycles(&rising, 0),
+        expected(&[
+            (1, "00 - -"),
+            (3, "00 - -"),
+            (5, "01 - 0.5"),
+            (7, "10 - 0.5"),
+            (9, "11 - 0.5"),
+            (11, "11 - 0.5"),
+        ])
+    );
+    // The setup time moves the sample back to before the previous timestamp
+    assert_eq!(
+        cycles(&rising, 5),
+        expected(&[
+            (1, "- - -"),
+            (3, "00 - -"),
+            (5, "01 - -"),
+            (7, "01 - 0.5"),
+            (9, "10 - 0.5"),
+            (11, "11 - 0.5"),
+        ])
+    );
+    assert_eq!(cycles(&rising, 4), cycles(&rising, 0));
+    // The clock starting low is a falling edge with nothing before it
+    assert_eq!(
+        cycles(&WaveformPredicate::Falling, 0),
+        expected(&[
+            (0, "- - -"),
+            (2, "00 - -"),
+            (4, "01 - -"),
+            (6, "01 - 0.5"),
+            (8, "10 - 0.5"),
+            (10, "11 - 0.5"),
+        ])
+    );
+    assert_eq!(
+        waveform
+            .iter_cycles(9, &WaveformPredicate::Rising, &ids, 0)
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_waveform_derived() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::derived::WaveformExpression;
+    use makai_waveform_db::errors::WaveformError;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::search::WaveformPredicate;
+    use makai_waveform_db::vcd::reader::read_vcd;
+    use makai_waveform_db::{Waveform, WaveformSearchMode, WaveformValueResult};
+
+    let mut waveform = read_vcd(VCD.as_bytes()).unwrap();
+    let expression = waveform
+        .parse_expression("{top.clk, top.cpu.nibble[1:0]} ^ 3'b001")
+        .unwrap();
+    assert_eq!(expression.get_ids(), vec![0, 3]);
+    assert_eq!(waveform.get_expression_width(&expression).unwrap(), 3);
+    waveform.add_derived(10, expression.clone()).unwrap();
+    let values = ["000", "100", "0x0", "xx0"];
+    for (index, value) in values.iter().enumerate() {
+        assert_eq!(
+            waveform.search_value(10, index, WaveformSearchMode::Before),
+            Some(WaveformValueResult::Vector(
+                BitVector::from_ascii_four_state(value.as_bytes()),
+                index
+            ))
+        );
+    }
+    assert_eq!(
+        waveform.search_value_bit_index(10, 1, WaveformSearchMode::Exact, Some(2)),
+        Some(WaveformValueResult::Vector(BitVector::new_one_bit(), 1))
+    );
+    let equal = waveform.parse_expression("top.data == 8'ha").unwrap();
+    assert!(waveform.evaluate(&equal, 0).is_unknown());
+    assert_eq!(waveform.evaluate(&equal, 1), BitVector::new_one_bit());
+
+    // Derived signals only change where their result does
+    let zero = waveform.parse_expression("top.clk & 1'b0").unwrap();
+    waveform.add_derived(11, zero).unwrap();
+    assert_eq!(
+        waveform.search_value(11, 3, WaveformSearchMode::Before),
+        Some(WaveformValueResult::Vector(BitVector::new_zero_bit(), 0))
+    );
+    assert_eq!(waveform.iter_events(&[11], 0..4).count(), 1);
+    assert_eq!(waveform.iter_events(&[10], 0..4).count(), 4);
+
+    // Every query sees derived signals like stored ones
+    assert_eq!(
+        waveform.find_next(10, 0, &WaveformPredicate::Rising, Some(2)),
+        Some(WaveformValueResult::Vector(BitVector::new_one_bit(), 1))
+    );
+    assert_eq!(
+        waveform.find_prev(10, 3, &WaveformPredicate::Unknown, None),
+        Some(WaveformValueResult::Vector(
+            BitVector::from_ascii_four_state(b"0x0"),
+            2
+        ))
+    );
+    assert_eq!(
+        waveform.sample(&[10], &[2])[0][0],
+        Some(WaveformValueResult::Vector(
+            BitVector::from_ascii_four_state(b"0x0"),
+            2
+        ))
+    );
+    let cycles = waveform.sample_cycles(0, &WaveformPredicate::Rising, &[10], 0);
+    assert_eq!(
+        cycles[0].get_values(),
+        &[Some(WaveformValueResult::Vector(
+            BitVector::from_ascii_four_state(b"000"),
+            0
+        ))]
+    );
+
+    // Changing a source drops the materialised changes
+    waveform.insert_timestamp(40).unwrap();
+    waveform.update_vector(0, BitVector::new_one_bit()).unwrap();
+    assert_eq!(
+        waveform.search_value(10, 4, WaveformSearchMode::Exact),
+        Some(WaveformValueResult::Vector(
+            BitVector::from_ascii_four_state(b"1x0"),
+            4
+        ))
+    );
+    waveform.remove_derived(11).unwrap();
+
+    // Materialising stores the same values as a vector signal
+    let signal = waveform.materialize(&expression).unwrap();
+    assert_eq!(signal.get_width(), 3);
+    assert_eq!(signal.len(), 5);
+    waveform.materialize_derived(10).unwrap();
+    assert!(waveform.get_derived(10).is_none());
+    assert!(waveform.get_vector_signal(10).is_some());
+    for (index, value) in values.iter().enumerate() {
+        assert_eq!(
+            waveform.search_value(10, index, WaveformSearchMode::Exact),
+            Some(WaveformValueResult::Vector(
+                BitVector::from_ascii_four_state(value.as_bytes()),
+                index
+            ))
+        );
+    }
+
+    for (expression, offset) in [
+        ("top.clk &", 9),
+        ("top.missing", 0),
+        ("(top.clk", 8),
+        ("4'b102", 0),
+        ("top.clk[0:1]", 8),
+        ("top.clk ~", 8),
+        ("{top.clk, #0}", 10),
+    ] {
+        match waveform.parse_expression(expression) {
+            Err(WaveformError::InvalidExpression { offset: actual, .. }) => {
+                assert_eq!(actual, offset, "{expression}")
+            }
+            result => panic!("Unexpected result for {expression}: {result:?}"),
+        }
+    }
+    let temp = waveform.parse_expression("top.cpu.temp").unwrap();
+    assert!(matches!(
+        waveform.add_derived(11, temp),
+        Err(WaveformError::MismatchedSignalType { id: 2 })
+    ));
+    assert!(matches!(
+        waveform.add_derived(0, WaveformExpression::Signal(3)),
+        Err(WaveformError::DuplicateId { id: 0 })
+    ));
+    assert!(matches!(
+        waveform.add_derived(11, WaveformExpression::Signal(9)),
+        Err(WaveformError::InvalidId { id: 9 })
+    ));
+
+    // (a[13:10] & ~b) | {c, d ^ c}, where nothing changes the result at
+    // timestamp 3
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(1, 2);
+    waveform.initialize_vector(2, 4);
+    waveform.initialize_vector(3, 16);
+    write_changes(
+        &mut waveform,
+        &[
+            (0, 0, "1"),
+            (0, 1, "10"),
+            (0, 2, "0011"),
+            (0, 3, "0011110000000000"),
+            (1, 2, "1111"),
+            (2, 0, "0"),
+            (3, 3, "0000000000000000"),
+            (4, 1, "01"),
+        ],
+    );
+    let signal = |id| Box::new(WaveformExpression::Signal(id));
+    let expression = WaveformExpression::Or(
+        Box::new(WaveformExpression::And(
+            Box::new(WaveformExpression::Slice(signal(3), 10..14)),
+            Box::new(WaveformExpression::Not(signal(2))),
+        )),
+        Box::new(WaveformExpression::Concat(vec![
+            WaveformExpression::Signal(0),
+            WaveformExpression::Xor(signal(1), signal(0)),
+        ])),
+    );
+    waveform.add_derived(5, expression.clone()).unwrap();
+    waveform.add_derived(6, expression).unwrap();
+    waveform.materialize_derived(6).unwrap();
+    let values = ["1111", "0111", "0010", "0010", "0001"];
+    assert_eq!(get_values(&waveform, 5), values);
+    assert_eq!(get_values(&waveform, 6), values);
+    // Only changes of the result are stored
+    let changes = |id| {
+        (0..5)
+            .map(|index| {
+                waveform
+                    .search_value(id, index, WaveformSearchMode::Before)
+                    .unwrap()
+                    .get_timestamp_index()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(changes(5), [0, 1, 2, 2, 4]);
+    assert_eq!(changes(6), [0, 1, 2, 2, 4]);
+
+    // Snapshots include derived signals, but saving leaves them out
+    let ids = |snapshot: Vec<(usize, WaveformValueResult)>| {
+        snapshot.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(waveform.snapshot(4, None)), [0, 1, 2, 3, 5, 6]);
+    let mut output = Vec::new();
+    waveform.save(&mut output).unwrap();
+    let loaded = Waveform::load(output.as_slice()).unwrap();
+    assert_eq!(ids(loaded.snapshot(4, None)), [0, 1, 2, 3, 6]);
+
+    // Escaped names are written with their escapes
+    let waveform = read_vcd(
+        "$scope module top $end\n$var wire 1 ! \\a.b $end\n$upscope $end\n\
+         $enddefinitions $end\n#0\n1!\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let expression = waveform.parse_expression("~top.\\\\a\\.b").unwrap();
+    assert_eq!(expression.get_ids(), vec![0]);
+    assert_eq!(waveform.evaluate(&expression, 0), BitVector::new_zero_bit());
+    assert!(waveform.parse_expression("top.a.b").is_err());
+}
+
+#[test]
+fn test_waveform_triggers() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::search::WaveformPredicate;
+    use makai_waveform_db::trigger::{WaveformCondition, WaveformTrigger, WaveformTriggerStage};
+    use makai_waveform_db::Waveform;
+
+    // A rises at edges 1, 4, 8 and 12, B is 3 at edges 3, 6, 10 and 18, and C
+    // is only set at edge 5
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(1, 1);
+    waveform.initialize_vector(2, 2);
+    waveform.initialize_vector(3, 1);
+    let edges = |values: &'static str| -> Vec<&'static str> {
+        (0..values.len()).map(|i| &values[i..i + 1]).collect()
+    };
+    let a = edges("01101000100010000000");
+    let b = edges("00010010001000000010")
+        .into_iter()
+        .map(|bit| if bit == "1" { "11" } else { "00" })
+        .collect::<Vec<_>>();
+    let c = edges("00000100000000000000");
+    write_clocked(&mut waveform, 0, &[(1, &a), (2, &b), (3, &c)]);
+
+    // A rises, then within 5 edges the low bits of B are both set while bit 0
+    // of C stays clear
+    let mut trigger = WaveformTrigger::new(0, WaveformPredicate::Rising);
+    trigger.push_stage(WaveformTriggerStage::new(WaveformCondition::Becomes(
+        1,
+        WaveformPredicate::Rising,
+    )));
+    let mut stage = WaveformTriggerStage::new(WaveformCondition::Is(
+        2,
+        WaveformPredicate::Masked {
+            value: BitVector::from_ascii(b"11"),
+            mask: BitVector::from_ascii(b"11"),
+        },
+    ));
+    stage.set_within(Some(5));
+    stage.set_guard(Some(WaveformCondition::Not(Box::new(
+        WaveformCondition::Is(3, WaveformPredicate::Rising),
+    ))));
+    trigger.push_stage(stage);
+    assert_eq!(trigger.get_ids(), vec![1, 2, 3]);
+
+    // The attempt from edge 4 fails its guard at edge 5 and the one from edge
+    // 12 runs out of time, leaving edges 1 to 3 and 8 to 10
+    let results = waveform.iter_triggers(&trigger).collect::<Vec<_>>();
+    assert_eq!(
+        results
+            .iter()
+            .map(|result| (result.get_start_index(), result.get_end_index()))
+            .collect::<Vec<_>>(),
+        vec![(3, 7), (17, 21)]
+    );
+    assert_eq!((results[0].get_start(), results[0].get_end()), (3, 7));
+
+    // The low bits of B held for 3 consecutive edges, where a match starts
+    // holding over again
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(2, 2);
+    let b = [
+        "11", "11", "00", "11", "11", "11", "11", "11", "11", "11", "00",
+    ];
+    write_clocked(&mut waveform, 0, &[(2, &b)]);
+    let mut trigger = WaveformTrigger::new(0, WaveformPredicate::Rising);
+    let mut stage = WaveformTriggerStage::new(WaveformCondition::All(vec![
+        WaveformCondition::Is(2, WaveformPredicate::Rising),
+        WaveformCondition::Is(
+            2,
+            WaveformPredicate::Masked {
+                value: BitVector::from_ascii(b"10"),
+                mask: BitVector::from_ascii(b"10"),
+            },
+        ),
+    ]));
+    stage.set_hold(3);
+    trigger.push_stage(stage);
+    assert_eq!(
+        waveform
+            .iter_triggers(&trigger)
+            .map(|result| (result.get_start_index(), result.get_end_index()))
+            .collect::<Vec<_>>(),
+        vec![(7, 11), (13, 17)]
+    );
+
+    assert_eq!(
+        waveform
+            .iter_triggers(&WaveformTrigger::new(0, WaveformPredicate::Rising))
+            .count(),
+        0
+    );
+
+    // A level held high starts an attempt on every edge, where only the
+    // earliest of them is kept waiting for the next stage
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(1, 1);
+    waveform.initialize_vector(2, 1);
+    let edges = 200_000u64;
+    for timestamp in 0..edges * 2 {
+        waveform.insert_timestamp(timestamp).unwrap();
+        waveform
+            .update_vector(0, BitVector::from_ascii(&[b'0' + (timestamp % 2) as u8]))
+            .unwrap();
+        if timestamp == 0 {
+            waveform
+                .update_vector(1, BitVector::from_ascii(b"1"))
+                .unwrap();
+            waveform
+                .update_vector(2, BitVector::from_ascii(b"0"))
+                .unwrap();
+        }
+        if timestamp == edges * 2 - 4 {
+            waveform
+                .update_vector(2, BitVector::from_ascii(b"1"))
+                .unwrap();
+        }
+    }
+    let mut trigger = WaveformTrigger::new(0, WaveformPredicate::Rising);
+    trigger.push_stage(WaveformTriggerStage::new(WaveformCondition::Is(
+        1,
+        WaveformPredicate::Rising,
+    )));
+    let mut stage = WaveformTriggerStage::new(WaveformCondition::Is(2, WaveformPredicate::Rising));
+    stage.set_hold(2);
+    trigger.push_stage(stage);
+    let results = waveform
+        .iter_triggers(&trigger)
+        .map(|result| (result.get_start_index(), result.get_end_index()))
+        .collect::<Vec<_>>();
+    let last = edges as usize * 2 - 1;
+    assert_eq!(results, vec![(1, last)]);
+}
+
+#[test]
+fn test_waveform_properties() {
+    use makai_waveform_db::bitvector::BitVector;
+    use makai_waveform_db::property::{WaveformImplication, WaveformProperty, WaveformSequence};
+    use makai_waveform_db::query::WaveformQuery;
+    use makai_waveform_db::search::WaveformPredicate;
+    use makai_waveform_db::trigger::WaveformCondition;
+    use makai_waveform_db::Waveform;
+
+    let mut waveform = Waveform::new();
+    waveform.initialize_vector(0, 1);
+    waveform.initialize_vector(1, 2);
+    waveform.initialize_vector(2, 2);
+    waveform.initialize_vector(3, 1);
+    let a = [
+        "00", "01", "01", "00", "01", "11", "11", "00", "01", "00", "00", "01",
+    ];
+    let b = [
+        "00", "00", "11", "00", "01", "00", "00", "00", "00", "00", "10", "00",
+    ];
+    let c = ["0", "0", "0", "0", "0", "0", "0", "0", "1", "0", "0", "0"];
+    write_clocked(&mut waveform, 0, &[(1, &a), (2, &b), (3, &c)]);
+    let failures = |property: &WaveformProperty| {
+        waveform
+            .check_property(property)
+            .iter()
+            .map(|failure| {
+                assert_eq!(failure.get_start(), failure.get_start_index() as u64);
+                assert_eq!(failure.get_end(), failure.get_end_index() as u64);
+                (failure.get_start_index(), failure.get_end_index())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // disable iff (c[0]) $rose(a) |-> ##[1:3] (b[1:0] == 3) ##1 $stable(a),
+    // where a rises at edges 1, 4, 8 and 11. The attempt from edge 1 matches
+    // b at edge 2 but a changes at edge 3, the one from edge 4 never sees b,
+    // the one from edge 8 is disabled and the one from edge 11 runs past the
+    // end
+    let mut antecedent = WaveformSequence::new();
+    antecedent.push(0, 0, WaveformCondition::Rose(1));
+    let mut consequent = WaveformSequence::new();
+    consequent.push(
+        1,
+        3,
+        WaveformCondition::Is(
+            2,
+            WaveformPredicate::Masked {
+                value: BitVector::from_ascii(b"11"),
+                mask: BitVector::from_ascii(b"11"),
+            },
+        ),
+    );
+    consequent.push(1, 1, WaveformCondition::Stable(1));
+    let mut property = WaveformProperty::new(0, WaveformPredicate::Rising, consequent);
+    property.set_antecedent(Some((antecedent, WaveformImplication::Overlapping)));
+    property.set_disable(Some(WaveformCondition::Is(3, WaveformPredicate::Rising)));
+    assert_eq!(property.get_ids(), vec![1, 2, 3]);
+    assert_eq!(failures(&property), vec![(3, 9), (9, 15)]);
+
+    // $fell(a) |=> $stable(b), where a falls at edges 0, 3, 7 and 9 and b
+    // changes at edges 4 and 10
+    let mut antecedent = WaveformSequence::new();
+    antecedent.push(0, 0, WaveformCondition::Fell(1));
+    let mut consequent = WaveformSequence::new();
+    consequent.push(0, 0, WaveformCondition::Stable(2));
+    let mut property = WaveformProperty::new(0, WaveformPredicate::Rising, consequent);
+    property.set_antecedent(Some((antecedent, WaveformImplication::NonOverlapping)));
+    assert_eq!(failures(&property), vec![(7, 9), (19, 21)]);
+
+    // $rose(a) |-> ##[1:$] c[0], where the attempts from edges 1 and 4 see c
+    // at edge 8 and the later ones are still pending at the end
+    let mut antecedent = WaveformSequence::new();
+    antecedent.push(0, 0, WaveformCondition::Rose(1));
+    let mut consequent = WaveformSequence::new();
+    consequent.push(
+        1,
+        usize::MAX,
+        WaveformCondition::Is(3, WaveformPredicate::Rising),
+    );
+    let mut property = WaveformProperty::new(0, WaveformPredicate::Rising, consequent);
+    property.set_antecedent(Some((antecedent, WaveformImplication::Overlapping)));
+    assert_eq!(failures(&property), vec![]);
+
+    // a[0] | a[1], checked at every clock edge
+    let mut sequence = WaveformSequence::new();
+    sequence.push(
+        0,
+        0,
+        WaveformCondition::Any(vec![
+            WaveformCondition::Is(1, WaveformPredicate::Rising),
+            WaveformCondition::Is(
+                1,
+                WaveformPredicate::Masked {
+                    value: BitVector::from_ascii(b"10"),
+                    mask: BitVector::from_ascii(b"10"),
+                },
+            ),
+        ]),
+    );
+    let property = WaveformProperty::new(0, WaveformPredicate::Rising, sequence);
+    assert_eq!(
+        failures(&property),
+        vec![(1, 1), (7, 7), (15, 15), (19, 19), (21, 21)]
+    );
+
+    // Attempts look past the edges held for the ones before them
+    let mut waveform = Waveform::new();
+    for id in 0..3 {
+        waveform.initialize_vector(id, 1);
+    }
+    let a = ["0", "1", "0", "0", "1", "0", "0", "0", "1", "0"];
+    let b = ["0", "0", "0", "1", "0", "0", "0", "0", "0", "0"];
+    write_clocked(&mut waveform, 0, &[(1, &a), (2, &b)]);
+    let results = |property: &WaveformProperty| {
+        waveform
+            .check_property(property)
+            .iter()
+            .map(|failure| (failure.get_start_index(), failure.get_end_index()))
+            .collect::<Vec<_>>()
+    };
+
+    // $rose(a) |=> ##[1:2] b, where the attempt at edge 8 runs past the end
+    let mut antecedent = WaveformSequence::new();
+    antecedent.push(0, 0, WaveformCondition::Rose(1));
+    let mut consequent = WaveformSequence::new();
+    consequent.push(1, 2, WaveformCondition::Is(2, WaveformPredicate::Rising));
+    let mut property = WaveformProperty::new(0, WaveformPredicate::Rising, consequent);
+    property.set_antecedent(Some((antecedent, WaveformImplication::NonOverlapping)));
+    assert_eq!(results(&property), vec![(9, 15)]);
+
+    // ##2 b from every edge, where the attempts at edges 8 and 9 run past the
+    // end
+    let mut sequence = WaveformSequence::new();
+    sequence.push(2, 2, WaveformCondition::Is(2, WaveformPredicate::Rising));
+    let property = WaveformProperty::new(0, WaveformPredicate::Rising, sequence);
+    assert_eq!(
+        results(&property),
+        vec![
+            (1, 5),
+            (5, 9),
+            (7, 11),
+            (9, 13),
+            (11, 15),
+            (13, 17),
+            (15, 19)
+        ]
+    );
+}